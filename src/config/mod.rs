@@ -0,0 +1,567 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+/// 未指定 --config 时尝试读取的配置文件
+pub const DEFAULT_CONFIG_FILE: &str = "po_manager.json";
+
+/// 应用运行配置
+///
+/// 优先级从低到高: 默认值 < 配置文件 < 环境变量 < 命令行参数
+///
+/// - 配置文件: `--config <path>` 或 `PO_MANAGER_CONFIG`, 未指定时读取当前目录下的 po_manager.json (存在时)
+/// - 环境变量: `PO_MANAGER_DATA_DIR` `PO_MANAGER_BIND_ADDR` `PO_MANAGER_STORAGE` `PO_MANAGER_ENCRYPTION_KEY`
+/// - 命令行参数: `--data-dir <path>` `--bind <addr>` `--storage <json|sqlite>`
+///
+/// 命令: `migrate [--dry-run]` 迁移数据后退出, 不指定时启动服务
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AppConfig {
+    /// 数据文件所在目录
+    pub data_dir: PathBuf,
+    /// 服务监听地址
+    pub bind_addr: String,
+    /// 各集合的数据文件名, 相对于 data_dir
+    pub collections: CollectionFiles,
+    /// 存储后端
+    pub storage: StorageKind,
+    /// sqlite 后端使用的数据库文件名, 相对于 data_dir
+    pub sqlite_file: String,
+    /// 备份配置
+    pub backup: BackupConfig,
+    /// 持久化配置
+    pub persistence: PersistenceConfig,
+    /// 删除被引用记录时的处理方式
+    pub integrity: IntegrityConfig,
+    /// 回收站配置
+    pub trash: TrashConfig,
+    /// 数据文件外部修改的检测配置
+    pub hot_reload: HotReloadConfig,
+    /// 数据加密配置
+    pub encryption: EncryptionConfig,
+    /// 节假日导入配置
+    pub holiday: HolidayConfig,
+    /// 数据文件损坏时的恢复配置
+    pub recovery: RecoveryConfig,
+}
+
+/// 数据文件损坏时的恢复配置
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RecoveryConfig {
+    /// 数据文件损坏且 .bak 备份也无法读取时以空集合启动, 默认拒绝启动, 以免误以为数据已丢失而继续写入
+    pub allow_empty: bool,
+}
+
+/// 节假日导入配置
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct HolidayConfig {
+    /// 可导入的节假日文件所在目录, 未配置时为 data_dir 下的 holidays 目录
+    pub dir: Option<PathBuf>,
+}
+
+/// 回收站配置
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TrashConfig {
+    /// 记录在回收站中保留的天数, 超过后自动彻底删除, 为 0 时不自动清理
+    pub purge_after_days: u64,
+}
+
+/// 数据加密配置, 仅 json 后端支持, 密钥为 base64 编码的 32 字节, 均未配置时不加密
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    /// 密钥
+    pub key: Option<String>,
+    /// 密钥文件, 内容为密钥
+    pub key_file: Option<PathBuf>,
+}
+
+impl EncryptionConfig {
+    /// 读取配置的密钥, 未配置时返回 None
+    pub fn load_key(&self) -> Result<Option<String>> {
+        match (&self.key, &self.key_file) {
+            (Some(_), Some(_)) => bail!("encryption.key 与 encryption.key_file 只能配置一项"),
+            (Some(key), None) => Ok(Some(key.clone())),
+            (None, Some(path)) => fs::read_to_string(path)
+                .map(Some)
+                .with_context(|| format!("读取密钥文件失败: {}", path.display())),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+/// 数据文件外部修改的检测配置, 仅 json 后端支持
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HotReloadConfig {
+    /// 检查数据文件是否被外部修改的间隔 (毫秒), 为 0 时不检查
+    pub interval_ms: u64,
+}
+
+impl Default for HotReloadConfig {
+    fn default() -> Self {
+        HotReloadConfig { interval_ms: 1000 }
+    }
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig {
+            purge_after_days: 30,
+        }
+    }
+}
+
+/// 删除被引用的记录时, 对引用它的记录的处理方式
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum OnDelete {
+    /// 存在引用时拒绝删除
+    Restrict,
+    /// 一并删除引用它的记录
+    Cascade,
+    /// 清空引用它的记录中的关联字段
+    Nullify,
+}
+
+/// 各关联关系在删除被引用记录时的处理方式
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct IntegrityConfig {
+    /// 删除项目时, 该项目的入项记录
+    pub employee_change_project: OnDelete,
+    /// 删除人员时, 该人员的入项记录
+    pub employee_change_employee: OnDelete,
+    /// 删除人员时, 该人员的考勤记录
+    pub attendance_employee: OnDelete,
+}
+
+impl Default for IntegrityConfig {
+    fn default() -> Self {
+        IntegrityConfig {
+            employee_change_project: OnDelete::Restrict,
+            employee_change_employee: OnDelete::Cascade,
+            attendance_employee: OnDelete::Cascade,
+        }
+    }
+}
+
+/// 持久化配置
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PersistenceConfig {
+    /// 收到变更后等待多久再写入磁盘 (毫秒), 期间的变更合并为一次写入; 有请求在等待写入时立即写入
+    pub debounce_ms: u64,
+    /// 写请求默认等待变更写入磁盘后再响应, 可以通过 x-durable 请求头按请求覆盖
+    pub wait_for_flush: bool,
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        PersistenceConfig {
+            debounce_ms: 200,
+            wait_for_flush: true,
+        }
+    }
+}
+
+/// 备份配置
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// 备份目录, 未配置时为 data_dir 下的 backups 目录
+    pub dir: Option<PathBuf>,
+    /// 定时备份间隔 (分钟), 为 0 时不进行定时备份
+    pub interval_minutes: u64,
+    /// 保留的备份数量, 超出时删除最旧的备份, 至少为 1
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            dir: None,
+            interval_minutes: 24 * 60,
+            keep: 10,
+        }
+    }
+}
+
+/// 存储后端类型
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageKind {
+    /// 每个集合一个 json 文件
+    Json,
+    /// 所有集合存放在同一个 sqlite 数据库文件中
+    Sqlite,
+}
+
+impl FromStr for StorageKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(StorageKind::Json),
+            "sqlite" => Ok(StorageKind::Sqlite),
+            _ => bail!("未知的存储后端: {}, 可选 json / sqlite", s),
+        }
+    }
+}
+
+/// 各集合的数据文件名
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CollectionFiles {
+    pub project: String,
+    pub employee: String,
+    pub employee_change: String,
+    pub attendance: String,
+    pub special_date: String,
+    pub leave_policy: String,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            data_dir: PathBuf::from("db"),
+            bind_addr: "0.0.0.0:3000".to_string(),
+            collections: CollectionFiles::default(),
+            storage: StorageKind::Json,
+            sqlite_file: "po_manager.sqlite".to_string(),
+            backup: BackupConfig::default(),
+            persistence: PersistenceConfig::default(),
+            integrity: IntegrityConfig::default(),
+            trash: TrashConfig::default(),
+            hot_reload: HotReloadConfig::default(),
+            encryption: EncryptionConfig::default(),
+            holiday: HolidayConfig::default(),
+            recovery: RecoveryConfig::default(),
+        }
+    }
+}
+
+impl Default for CollectionFiles {
+    fn default() -> Self {
+        CollectionFiles {
+            project: "project.json".to_string(),
+            employee: "employee.json".to_string(),
+            employee_change: "employee_change.json".to_string(),
+            attendance: "attendance.json".to_string(),
+            special_date: "special_date.json".to_string(),
+            leave_policy: "leave_policy.json".to_string(),
+        }
+    }
+}
+
+/// 启动后执行的命令
+#[derive(Debug, Default, PartialEq, Clone)]
+pub enum Command {
+    /// 启动服务
+    #[default]
+    Serve,
+    /// 将数据迁移到最新版本后退出, dry_run 时只输出迁移报告不做修改
+    Migrate { dry_run: bool },
+    /// 使用新密钥重新加密全部数据后退出, 密钥文件不存在时生成新密钥
+    RotateKey { new_key_file: PathBuf },
+}
+
+/// 命令行参数中解析出的配置项
+#[derive(Debug, Default)]
+struct CliArgs {
+    command: Command,
+    config: Option<PathBuf>,
+    data_dir: Option<PathBuf>,
+    bind_addr: Option<String>,
+    storage: Option<StorageKind>,
+}
+
+impl CliArgs {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut cli = CliArgs::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "migrate" => {
+                    cli.command = Command::Migrate { dry_run: false };
+                    continue;
+                }
+                "--dry-run" => {
+                    match &mut cli.command {
+                        Command::Migrate { dry_run } => *dry_run = true,
+                        _ => bail!("--dry-run 只能用于 migrate 命令"),
+                    }
+                    continue;
+                }
+                "rotate-key" => {
+                    cli.command = Command::RotateKey {
+                        new_key_file: PathBuf::new(),
+                    };
+                    continue;
+                }
+                _ => {}
+            }
+
+            // 同时支持 --key value 和 --key=value
+            let (key, inline) = match arg.split_once('=') {
+                Some((k, v)) => (k.to_string(), Some(v.to_string())),
+                None => (arg, None),
+            };
+
+            let mut value = || -> Result<String> {
+                match inline.clone() {
+                    Some(v) => Ok(v),
+                    None => args.next().with_context(|| format!("参数 {} 缺少值", key)),
+                }
+            };
+
+            match key.as_str() {
+                "--config" => cli.config = Some(PathBuf::from(value()?)),
+                "--data-dir" => cli.data_dir = Some(PathBuf::from(value()?)),
+                "--bind" => cli.bind_addr = Some(value()?),
+                "--storage" => cli.storage = Some(value()?.parse()?),
+                "--new-key-file" => match &mut cli.command {
+                    Command::RotateKey { new_key_file } => *new_key_file = PathBuf::from(value()?),
+                    _ => bail!("--new-key-file 只能用于 rotate-key 命令"),
+                },
+                _ => bail!("未知参数: {}", key),
+            }
+        }
+
+        if let Command::RotateKey { new_key_file } = &cli.command
+            && new_key_file.as_os_str().is_empty()
+        {
+            bail!("rotate-key 命令需要 --new-key-file <path>");
+        }
+
+        Ok(cli)
+    }
+}
+
+impl AppConfig {
+    /// 从进程参数及环境变量加载配置, 同时返回要执行的命令
+    pub fn load() -> Result<(Self, Command)> {
+        Self::load_from(env::args().skip(1))
+    }
+
+    /// 从给定参数加载配置, 参数不包含程序名
+    pub fn load_from(args: impl IntoIterator<Item = String>) -> Result<(Self, Command)> {
+        let cli = CliArgs::parse(args)?;
+
+        let config_path = cli
+            .config
+            .clone()
+            .or_else(|| env::var_os("PO_MANAGER_CONFIG").map(PathBuf::from));
+
+        let mut config = match config_path {
+            Some(path) => Self::from_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => AppConfig::default(),
+        };
+
+        if let Some(dir) = env::var_os("PO_MANAGER_DATA_DIR") {
+            config.data_dir = PathBuf::from(dir);
+        }
+        if let Ok(addr) = env::var("PO_MANAGER_BIND_ADDR") {
+            config.bind_addr = addr;
+        }
+        if let Ok(storage) = env::var("PO_MANAGER_STORAGE") {
+            config.storage = storage.parse()?;
+        }
+        if let Ok(key) = env::var("PO_MANAGER_ENCRYPTION_KEY") {
+            config.encryption.key = Some(key);
+            config.encryption.key_file = None;
+        }
+
+        if let Some(dir) = cli.data_dir {
+            config.data_dir = dir;
+        }
+        if let Some(addr) = cli.bind_addr {
+            config.bind_addr = addr;
+        }
+        if let Some(storage) = cli.storage {
+            config.storage = storage;
+        }
+
+        config.validate()?;
+
+        Ok((config, cli.command))
+    }
+
+    /// 校验配置取值
+    pub fn validate(&self) -> Result<()> {
+        if self.backup.keep == 0 {
+            bail!("backup.keep 至少为 1, 否则新创建的备份会被立即删除");
+        }
+
+        Ok(())
+    }
+
+    /// 读取 json 格式的配置文件, 未填写的字段使用默认值
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("读取配置文件失败: {}", path.display()))?;
+
+        serde_json::from_str(&content)
+            .with_context(|| format!("解析配置文件失败: {}", path.display()))
+    }
+
+    /// 使用指定数据目录构造配置, 其余使用默认值
+    #[cfg(test)]
+    pub fn with_data_dir(data_dir: impl Into<PathBuf>) -> Self {
+        AppConfig {
+            data_dir: data_dir.into(),
+            ..AppConfig::default()
+        }
+    }
+
+    /// sqlite 数据库文件路径
+    pub fn sqlite_path(&self) -> PathBuf {
+        self.data_dir.join(&self.sqlite_file)
+    }
+
+    /// 备份目录
+    pub fn backup_dir(&self) -> PathBuf {
+        self.backup
+            .dir
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("backups"))
+    }
+
+    /// 节假日文件目录
+    pub fn holiday_dir(&self) -> PathBuf {
+        self.holiday
+            .dir
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("holidays"))
+    }
+
+    /// 确保数据目录存在
+    pub fn prepare_data_dir(&self) -> Result<()> {
+        fs::create_dir_all(&self.data_dir)
+            .with_context(|| format!("创建数据目录失败: {}", self.data_dir.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn paths_follow_data_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AppConfig::with_data_dir(dir.path());
+
+        assert_eq!(config.sqlite_path(), dir.path().join("po_manager.sqlite"));
+        assert_eq!(config.backup_dir(), dir.path().join("backups"));
+        assert_eq!(config.holiday_dir(), dir.path().join("holidays"));
+        assert_eq!(
+            config.data_dir.join(&config.collections.attendance),
+            dir.path().join("attendance.json")
+        );
+    }
+
+    #[test]
+    fn prepare_data_dir_creates_nested_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AppConfig::with_data_dir(dir.path().join("a").join("b"));
+
+        config.prepare_data_dir().unwrap();
+
+        assert!(config.data_dir.is_dir());
+    }
+
+    #[test]
+    fn file_values_fill_missing_fields_with_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        fs::write(
+            &path,
+            r#"{"bind_addr": "127.0.0.1:8080", "backup": {"keep": 3}}"#,
+        )
+        .unwrap();
+
+        let config = AppConfig::from_file(&path).unwrap();
+
+        assert_eq!(config.bind_addr, "127.0.0.1:8080");
+        assert_eq!(config.backup.keep, 3);
+        assert_eq!(config.backup.interval_minutes, 24 * 60);
+        assert_eq!(config.data_dir, PathBuf::from("db"));
+        assert_eq!(config.collections.project, "project.json");
+    }
+
+    #[test]
+    fn cli_overrides_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        let data_dir = dir.path().join("data");
+        fs::write(
+            &path,
+            r#"{"data_dir": "from_file", "bind_addr": "127.0.0.1:8080", "storage": "sqlite"}"#,
+        )
+        .unwrap();
+
+        let (config, command) = AppConfig::load_from(args(&[
+            "--config",
+            path.to_str().unwrap(),
+            &format!("--data-dir={}", data_dir.display()),
+            "--storage",
+            "json",
+        ]))
+        .unwrap();
+
+        assert_eq!(command, Command::Serve);
+        assert_eq!(config.data_dir, data_dir);
+        assert_eq!(config.bind_addr, "127.0.0.1:8080");
+        assert_eq!(config.storage, StorageKind::Json);
+    }
+
+    #[test]
+    fn rejects_zero_backup_keep() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        fs::write(&path, r#"{"backup": {"keep": 0}}"#).unwrap();
+
+        let err = AppConfig::load_from(args(&["--config", path.to_str().unwrap()])).unwrap_err();
+
+        assert!(err.to_string().contains("backup.keep"));
+    }
+
+    #[test]
+    fn parses_commands() {
+        let cli = CliArgs::parse(args(&["migrate", "--dry-run"])).unwrap();
+        assert_eq!(cli.command, Command::Migrate { dry_run: true });
+
+        let cli = CliArgs::parse(args(&["rotate-key", "--new-key-file", "new.key"])).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::RotateKey {
+                new_key_file: PathBuf::from("new.key")
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_args() {
+        assert!(CliArgs::parse(args(&["--dry-run"])).is_err());
+        assert!(CliArgs::parse(args(&["rotate-key"])).is_err());
+        assert!(CliArgs::parse(args(&["--data-dir"])).is_err());
+        assert!(CliArgs::parse(args(&["--storage", "mongo"])).is_err());
+        assert!(CliArgs::parse(args(&["--unknown"])).is_err());
+    }
+}
@@ -1,10 +1,10 @@
-use chrono::NaiveDate;
+use chrono::{DateTime, Local, NaiveDate};
 use serde::{Deserialize, Serialize};
 
 use crate::serde_custom::date_format::{date_format, date_format_option};
 
 /// 特殊考勤类型
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub enum AttendanceType {
     /// 请假
     Leave,
@@ -15,7 +15,7 @@ pub enum AttendanceType {
 }
 
 /// 特殊出勤记录, 记录 AttendanceType 中的非正常出勤
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct EntityAttendance {
     pub id: String,
     /// 开始时间
@@ -25,14 +25,21 @@ pub struct EntityAttendance {
     #[serde(default)]
     #[serde(with = "date_format_option", skip_serializing_if = "Option::is_none")]
     pub end_time: Option<NaiveDate>,
-    /// 人员id
-    pub employee_id: String,
+    /// 人员id, 人员被删除且按 nullify 处理后为空
+    #[serde(default)]
+    pub employee_id: Option<String>,
     /// 类型
     pub date_type: AttendanceType,
     /// start_time 是否表示半天
     pub start_half: bool,
     /// end_time 是否表示半天
     pub end_half: bool,
+    /// 版本号, 每次修改加一, 用于乐观并发控制
+    #[serde(default)]
+    pub version: u64,
+    /// 删除时间, 不为空时表示记录已移入回收站
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Local>>,
 }
 
 /// 特殊出勤记录创建参数
@@ -55,7 +62,32 @@ pub struct DTOAttendanceCreate {
     pub end_half: bool,
 }
 
-/// 特殊出勤记录创建参数
+/// 特殊出勤记录修改参数, 只修改提供的字段
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOAttendanceUpdate {
+    /// 开始时间
+    #[serde(default)]
+    #[serde(with = "date_format_option")]
+    pub start_time: Option<NaiveDate>,
+    /// 结束时间
+    #[serde(default)]
+    #[serde(with = "date_format_option")]
+    pub end_time: Option<NaiveDate>,
+    /// 人员id
+    #[serde(default)]
+    pub employee_id: Option<String>,
+    /// 类型
+    #[serde(default)]
+    pub date_type: Option<AttendanceType>,
+    /// start_time 是否表示半天
+    #[serde(default)]
+    pub start_half: Option<bool>,
+    /// end_time 是否表示半天
+    #[serde(default)]
+    pub end_half: Option<bool>,
+}
+
+/// 特殊出勤记录查询参数
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DTOAttendanceParam {
     #[serde(default)]
@@ -80,4 +112,12 @@ pub struct DTOAttendanceParam {
     /// end_time 是否表示半天
     #[serde(default)]
     pub end_half: Option<bool>,
+    /// 与该日期之后 (包含当天) 有交集的记录
+    #[serde(default)]
+    #[serde(with = "date_format_option")]
+    pub from: Option<NaiveDate>,
+    /// 与该日期之前 (包含当天) 有交集的记录
+    #[serde(default)]
+    #[serde(with = "date_format_option")]
+    pub to: Option<NaiveDate>,
 }
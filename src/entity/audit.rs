@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::serde_custom::date_format::{date_format_option, datetime_format};
+
+/// 变更类型
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOp {
+    /// 新增
+    Create,
+    /// 修改
+    Update,
+    /// 移入回收站
+    Delete,
+    /// 从回收站恢复
+    Restore,
+    /// 彻底删除
+    Purge,
+    /// 整个集合被替换, 如从备份恢复, 不记录字段变化
+    Replace,
+}
+
+/// 字段变化前后的值, 不存在时为 null
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FieldChange {
+    pub before: Value,
+    pub after: Value,
+}
+
+/// 审计记录, 每次变更一条, 只追加不修改
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EntityAudit {
+    pub id: String,
+    /// 变更时间
+    #[serde(with = "datetime_format")]
+    pub time: NaiveDateTime,
+    /// 操作人, 取自请求头 x-actor
+    pub actor: String,
+    /// 请求id, 同一请求中的变更相同, 非请求触发的变更为空
+    #[serde(default)]
+    pub request_id: String,
+    /// 集合名
+    pub entity: String,
+    /// 记录id
+    pub record_id: String,
+    /// 变更类型
+    pub op: AuditOp,
+    /// 发生变化的字段
+    pub changes: BTreeMap<String, FieldChange>,
+}
+
+/// 审计记录查询参数
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOAuditParam {
+    /// 集合名
+    #[serde(default)]
+    pub entity: Option<String>,
+    /// 记录id
+    #[serde(default)]
+    pub record_id: Option<String>,
+    /// 发生变化的字段
+    #[serde(default)]
+    pub field: Option<String>,
+    /// 操作人
+    #[serde(default)]
+    pub actor: Option<String>,
+    /// 变更类型
+    #[serde(default)]
+    pub op: Option<AuditOp>,
+    /// 开始日期, 包含当天
+    #[serde(default)]
+    #[serde(with = "date_format_option")]
+    pub from: Option<NaiveDate>,
+    /// 结束日期, 包含当天
+    #[serde(default)]
+    #[serde(with = "date_format_option")]
+    pub to: Option<NaiveDate>,
+}
@@ -0,0 +1,88 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::serde_custom::date_format::date_format;
+
+/// 日期类型
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DayKind {
+    /// 周一至周五
+    Workday,
+    /// 周六周日
+    Weekend,
+    /// 特殊日期中计入假日的日期
+    Holiday,
+    /// 特殊日期中从假日排除的日期, 即调休上班
+    AdjustedWorkday,
+}
+
+impl DayKind {
+    /// 是否需要上班
+    pub fn is_workday(&self) -> bool {
+        matches!(self, DayKind::Workday | DayKind::AdjustedWorkday)
+    }
+}
+
+/// 单个日期查询参数
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOCalendarDateParam {
+    #[serde(with = "date_format")]
+    pub date: NaiveDate,
+}
+
+/// 日期范围查询参数
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOCalendarRangeParam {
+    /// 开始日期, 包含当天
+    #[serde(with = "date_format")]
+    pub from: NaiveDate,
+    /// 结束日期, 包含当天
+    #[serde(with = "date_format")]
+    pub to: NaiveDate,
+    /// from 是否只计半天
+    #[serde(default)]
+    pub from_half: bool,
+    /// to 是否只计半天
+    #[serde(default)]
+    pub to_half: bool,
+}
+
+/// 工作日推算参数
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOCalendarAddParam {
+    /// 起始日期, 不计入天数
+    #[serde(with = "date_format")]
+    pub date: NaiveDate,
+    /// 工作日天数, 可以为半天, 负数表示向前推算
+    pub days: f64,
+}
+
+/// 日期及其类型
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOCalendarDay {
+    #[serde(with = "date_format")]
+    pub date: NaiveDate,
+    pub kind: DayKind,
+    pub workday: bool,
+}
+
+/// 日期范围内的工作日天数
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOCalendarWorkdays {
+    #[serde(with = "date_format")]
+    pub from: NaiveDate,
+    #[serde(with = "date_format")]
+    pub to: NaiveDate,
+    pub workdays: f64,
+}
+
+/// 工作日推算结果
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOCalendarAdd {
+    /// 最后一个工作日
+    #[serde(with = "date_format")]
+    pub date: NaiveDate,
+    /// 最后一个工作日是否只用到半天
+    pub half: bool,
+}
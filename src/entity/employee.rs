@@ -1,3 +1,4 @@
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
@@ -19,7 +20,7 @@ pub fn get_employee_status_meaning(status: &EmployeeStatus) -> String {
 }
 
 /// 员工信息
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct EntityEmployee {
     pub id: String,
     /// 姓名
@@ -28,6 +29,12 @@ pub struct EntityEmployee {
     pub status: EmployeeStatus,
     /// 岗位
     pub position: String,
+    /// 版本号, 每次修改加一, 用于乐观并发控制
+    #[serde(default)]
+    pub version: u64,
+    /// 删除时间, 不为空时表示记录已移入回收站
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Local>>,
 }
 
 /// 员工信息
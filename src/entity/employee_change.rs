@@ -1,16 +1,18 @@
-use chrono::NaiveDate;
+use chrono::{DateTime, Local, NaiveDate};
 use serde::{Deserialize, Serialize};
 
 use crate::serde_custom::date_format::{date_format, date_format_option};
 
 /// 人员入项和离项记录
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct EntityEmployeeChange {
     pub id: String,
-    /// 人员id
-    pub employee_id: String,
-    /// 项目id
-    pub project_id: String,
+    /// 人员id, 人员被删除且按 nullify 处理后为空
+    #[serde(default)]
+    pub employee_id: Option<String>,
+    /// 项目id, 项目被删除且按 nullify 处理后为空
+    #[serde(default)]
+    pub project_id: Option<String>,
     /// 入项时间
     #[serde(with = "date_format")]
     pub in_time: NaiveDate,
@@ -18,6 +20,12 @@ pub struct EntityEmployeeChange {
     #[serde(default)]
     #[serde(with = "date_format_option")]
     pub out_time: Option<NaiveDate>,
+    /// 版本号, 每次修改加一, 用于乐观并发控制
+    #[serde(default)]
+    pub version: u64,
+    /// 删除时间, 不为空时表示记录已移入回收站
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Local>>,
 }
 
 /// 人员入项和离项记录
@@ -25,10 +33,10 @@ pub struct EntityEmployeeChange {
 pub struct DTOEmployeeChange {
     pub id: String,
     /// 人员id
-    pub employee_id: String,
+    pub employee_id: Option<String>,
     pub employee_name: String,
     /// 项目id
-    pub project_id: String,
+    pub project_id: Option<String>,
     pub project_name: String,
     /// 入项时间
     #[serde(with = "date_format")]
@@ -0,0 +1,63 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::serde_custom::date_format::date_format;
+
+use super::special_date::{EntitySpecialDate, SpecialDateType};
+
+/// 节假日文件中的一个日期范围
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DTOHolidayRange {
+    /// 开始日期
+    #[serde(with = "date_format")]
+    pub start_time: NaiveDate,
+    /// 结束日期, 包含当天
+    #[serde(with = "date_format")]
+    pub end_time: NaiveDate,
+    /// 日期类型, 计入假日/从假日排除
+    pub date_type: SpecialDateType,
+    /// 名称, 如节日名, 仅用于结果展示
+    #[serde(default)]
+    pub name: String,
+}
+
+/// 导入参数
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOHolidayImport {
+    /// 节假日目录下的文件名, 按扩展名识别格式: .ics / .json / .csv
+    pub file: String,
+    /// 只导入该年份的日期
+    #[serde(default)]
+    pub year: Option<i32>,
+    /// 为 true 时先删除该年份已有的特殊日期再导入, 需要指定 year
+    #[serde(default)]
+    pub replace: bool,
+    /// 为 true 时只返回导入结果, 不修改数据
+    #[serde(default)]
+    pub preview: bool,
+}
+
+/// 未导入的日期范围及原因
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOHolidaySkipped {
+    #[serde(flatten)]
+    pub range: DTOHolidayRange,
+    pub reason: String,
+}
+
+/// 导入结果
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOHolidayImportResult {
+    /// 是否只是预览
+    pub preview: bool,
+    /// 文件中读取到的日期范围数
+    pub parsed: usize,
+    /// 新增的特殊日期
+    pub created: Vec<EntitySpecialDate>,
+    /// 替换模式下被截去该年份部分的特殊日期
+    pub updated: Vec<EntitySpecialDate>,
+    /// 替换模式下移入回收站的特殊日期
+    pub removed: Vec<EntitySpecialDate>,
+    /// 已存在或与已有特殊日期冲突而未导入的部分
+    pub skipped: Vec<DTOHolidaySkipped>,
+}
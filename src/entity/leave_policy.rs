@@ -0,0 +1,153 @@
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::serde_custom::date_format::{date_format, date_format_option};
+
+/// 年假政策, 分配给人员或岗位, 人员的分配优先于岗位
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EntityLeavePolicy {
+    pub id: String,
+    /// 名称
+    pub name: String,
+    /// 每年的年假天数
+    pub annual_quota: f64,
+    /// 每月初累积的天数, 累积总数不超过 annual_quota; 为 0 时年初即可使用全年额度
+    #[serde(default)]
+    pub accrual_per_month: f64,
+    /// 未用完的年假结转到下一年的上限, 为 0 时不结转
+    #[serde(default)]
+    pub carry_over_cap: f64,
+    /// 结转年假的过期日期, 格式为 月-日 (如 03-31), 到期未用完的部分作废; 为空时不过期
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub carry_over_expiry: Option<String>,
+    /// 生效日期, 之前的年份不累积结转; 创建时默认为当天, 为空时从人员有请假记录的第一年开始结转
+    #[serde(
+        default,
+        with = "date_format_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub start_date: Option<NaiveDate>,
+    /// 适用的人员id
+    #[serde(default)]
+    pub employee_ids: Vec<String>,
+    /// 适用的岗位
+    #[serde(default)]
+    pub positions: Vec<String>,
+    /// 版本号, 每次修改加一, 用于乐观并发控制
+    #[serde(default)]
+    pub version: u64,
+    /// 删除时间, 不为空时表示记录已移入回收站
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Local>>,
+}
+
+/// 年假政策创建参数
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOLeavePolicyCreate {
+    /// 名称
+    pub name: String,
+    /// 每年的年假天数
+    pub annual_quota: f64,
+    /// 每月初累积的天数
+    #[serde(default)]
+    pub accrual_per_month: f64,
+    /// 结转上限
+    #[serde(default)]
+    pub carry_over_cap: f64,
+    /// 结转年假的过期日期, 格式为 月-日
+    #[serde(default)]
+    pub carry_over_expiry: Option<String>,
+    /// 生效日期, 默认为当天
+    #[serde(default)]
+    #[serde(with = "date_format_option")]
+    pub start_date: Option<NaiveDate>,
+    /// 适用的人员id
+    #[serde(default)]
+    pub employee_ids: Vec<String>,
+    /// 适用的岗位
+    #[serde(default)]
+    pub positions: Vec<String>,
+}
+
+/// 年假政策更新参数
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOLeavePolicyUpdate {
+    /// 名称
+    #[serde(default)]
+    pub name: Option<String>,
+    /// 每年的年假天数
+    #[serde(default)]
+    pub annual_quota: Option<f64>,
+    /// 每月初累积的天数
+    #[serde(default)]
+    pub accrual_per_month: Option<f64>,
+    /// 结转上限
+    #[serde(default)]
+    pub carry_over_cap: Option<f64>,
+    /// 结转年假的过期日期, 格式为 月-日, 为空字符串时清除
+    #[serde(default)]
+    pub carry_over_expiry: Option<String>,
+    /// 生效日期
+    #[serde(default)]
+    #[serde(with = "date_format_option")]
+    pub start_date: Option<NaiveDate>,
+    /// 适用的人员id
+    #[serde(default)]
+    pub employee_ids: Option<Vec<String>>,
+    /// 适用的岗位
+    #[serde(default)]
+    pub positions: Option<Vec<String>>,
+}
+
+/// 年假政策查询参数
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOLeavePolicyParam {
+    #[serde(default)]
+    pub id: Option<String>,
+    /// 名称 (包含)
+    #[serde(default)]
+    pub name: Option<String>,
+    /// 分配给该人员的政策
+    #[serde(default)]
+    pub employee_id: Option<String>,
+    /// 分配给该岗位的政策
+    #[serde(default)]
+    pub position: Option<String>,
+}
+
+/// 年假余额查询参数
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOLeaveBalanceParam {
+    /// 计算截至该日期的余额, 默认为当天
+    #[serde(default)]
+    #[serde(with = "date_format_option")]
+    pub date: Option<NaiveDate>,
+}
+
+/// 人员某一年的年假余额
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOLeaveBalance {
+    /// 人员id
+    pub employee_id: String,
+    /// 适用的年假政策id
+    pub policy_id: String,
+    /// 适用的年假政策名称
+    pub policy_name: String,
+    /// 年份
+    pub year: i32,
+    /// 计算截至的日期
+    #[serde(with = "date_format")]
+    pub date: NaiveDate,
+    /// 全年额度
+    pub annual_quota: f64,
+    /// 截至 date 已累积的额度
+    pub accrued: f64,
+    /// 从上一年结转的天数
+    pub carried_over: f64,
+    /// 结转天数中已过期作废的部分
+    pub carried_over_expired: f64,
+    /// 该年已请 (包括已登记的将来日期) 的年假天数
+    pub used: f64,
+    /// 剩余天数, 超出额度时为负数
+    pub remaining: f64,
+}
@@ -0,0 +1,47 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::serde_custom::date_format::{date_format, date_format_option};
+
+use super::attendance::AttendanceType;
+
+/// 考勤台账中的一条记录
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOLedgerEntry {
+    /// 考勤记录id
+    pub attendance_id: String,
+    /// 类型
+    pub date_type: AttendanceType,
+    /// 开始时间
+    #[serde(with = "date_format")]
+    pub start_time: NaiveDate,
+    /// 结束时间
+    #[serde(default)]
+    #[serde(with = "date_format_option", skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<NaiveDate>,
+    /// start_time 是否表示半天
+    pub start_half: bool,
+    /// end_time 是否表示半天
+    pub end_half: bool,
+    /// 折算的天数, 请假和调休只计工作日, 加班只计休息日
+    pub days: f64,
+    /// 计入本条后的调休余额
+    pub balance: f64,
+}
+
+/// 人员的加班与调休余额
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DTOAttendanceBalance {
+    /// 人员id
+    pub employee_id: String,
+    /// 加班获得的天数
+    pub overtime: f64,
+    /// 已调休的天数
+    pub compensatory_leave: f64,
+    /// 请假天数, 不影响余额
+    pub leave: f64,
+    /// 剩余可调休天数
+    pub balance: f64,
+    /// 按开始时间排列的考勤记录
+    pub entries: Vec<DTOLedgerEntry>,
+}
@@ -1,5 +1,10 @@
 pub mod attendance;
+pub mod audit;
+pub mod calendar;
 pub mod employee;
 pub mod employee_change;
+pub mod holiday;
+pub mod leave_policy;
+pub mod ledger;
 pub mod project;
 pub mod special_date;
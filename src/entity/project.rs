@@ -1,9 +1,9 @@
 use crate::serde_custom::date_format::{date_format, date_format_option};
-use chrono::NaiveDate;
+use chrono::{DateTime, Local, NaiveDate};
 use serde::{Deserialize, Serialize};
 
 /// 项目信息
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct EntityProject {
     pub id: String,
     /// 名称
@@ -24,6 +24,12 @@ pub struct EntityProject {
     pub price: f64,
     /// 项目经理
     pub pm: String,
+    /// 版本号, 每次修改加一, 用于乐观并发控制
+    #[serde(default)]
+    pub version: u64,
+    /// 删除时间, 不为空时表示记录已移入回收站
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Local>>,
 }
 
 /// 项目信息创建参数
@@ -60,19 +66,19 @@ pub struct DTOProjectParam {
     /// 项目经理
     #[serde(default)]
     pub pm: Option<String>,
-    /// 项目成员
+    /// 项目成员, 人员id或姓名, 匹配曾经入项的人员
     #[serde(default)]
     pub employee: Option<String>,
-    /// 项目成员
+    /// 剩余人天低于该值
     #[serde(default)]
     pub low_days: Option<i32>,
-    /// 项目成员
+    /// 剩余人天占预算的百分比低于该值, 0 到 100
     #[serde(default)]
     pub low_percent: Option<f32>,
-    /// 项目成员
+    /// 发布日期, 模糊匹配
     #[serde(default)]
     pub release_date_fuzzy: Option<String>,
-    /// 项目成员
+    /// 计划交付日期, 模糊匹配
     #[serde(default)]
     pub plan_delivery_date_fuzzy: Option<String>,
     /// 报价
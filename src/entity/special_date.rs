@@ -1,9 +1,9 @@
-use chrono::NaiveDate;
+use chrono::{DateTime, Local, NaiveDate};
 use serde::{Deserialize, Serialize};
 
 use crate::serde_custom::date_format::{date_format, date_format_option};
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub enum SpecialDateType {
     /// 视为节假日
     Include,
@@ -12,7 +12,7 @@ pub enum SpecialDateType {
 }
 
 /// 特殊日期, 记录周末以外的节假日, 或是不应记为节假日的周末
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct EntitySpecialDate {
     pub id: String,
     /// 开始时间
@@ -24,6 +24,12 @@ pub struct EntitySpecialDate {
     pub end_time: Option<NaiveDate>,
     /// 日期类型, 计入假日/从假日排除
     pub date_type: SpecialDateType,
+    /// 版本号, 每次修改加一, 用于乐观并发控制
+    #[serde(default)]
+    pub version: u64,
+    /// 删除时间, 不为空时表示记录已移入回收站
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Local>>,
 }
 
 /// 特殊日期创建参数
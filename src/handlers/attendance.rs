@@ -1,170 +1,231 @@
-use axum::{
-    Extension, Json,
-    extract::{Path, Query},
-};
-use uuid::Uuid;
+use anyhow::Result;
+use axum::{Extension, extract::Path};
 
 use crate::{
-    entity::attendance::{DTOAttendanceCreate, DTOAttendanceParam, EntityAttendance},
-    repo::db::{DB, DBType},
+    entity::attendance::{
+        DTOAttendanceCreate, DTOAttendanceParam, DTOAttendanceUpdate, EntityAttendance,
+    },
+    repo::{
+        calendar::WorkCalendar,
+        index::{IndexKey, date_key},
+        leave, ledger,
+        query::KeyQuery,
+        store::{Store, StoreRead},
+        transaction::Transaction,
+    },
     result::response::{AppResponse, AppResult},
 };
 
-pub async fn create(
-    Extension(db): Extension<DBType<EntityAttendance>>,
-    Json(attendance): Json<DTOAttendanceCreate>,
-) -> AppResult {
-    let mut attendance_db = db.lock().unwrap();
-
-    let new_employee = EntityAttendance {
-        id: Uuid::new_v4().to_string(),
-        start_time: attendance.start_time,
-        end_time: attendance.end_time,
-        employee_id: attendance.employee_id,
-        date_type: attendance.date_type,
-        start_half: attendance.start_half,
-        end_half: attendance.end_half,
-    };
-
-    attendance_db.insert(0, new_employee);
-
-    EntityAttendance::store(&attendance_db)?;
-
-    AppResponse::ok(attendance_db.first())
-}
-
-pub async fn list(
-    Extension(db): Extension<DBType<EntityAttendance>>,
-    Query(attendance): Query<DTOAttendanceParam>,
-) -> AppResult {
-    let attendance_db = db.lock().unwrap();
-
-    let res: Vec<&EntityAttendance> = attendance_db
-        .iter()
-        .filter(|p| {
-            let mut pass = true;
-
-            if let Some(cur) = &attendance.id {
-                if p.id != *cur {
-                    pass = false;
-                }
-            }
-
-            if let Some(cur) = &attendance.start_time {
-                if p.start_time != *cur {
-                    pass = false;
-                }
-            }
-
-            if let Some(cur) = &attendance.end_time {
-                if p.end_time != Some(*cur) {
-                    pass = false;
-                }
-            }
-
-            if let Some(cur) = &attendance.employee_id {
-                if p.employee_id != *cur {
-                    pass = false;
-                }
-            }
-
-            if let Some(cur) = &attendance.date_type {
-                if p.date_type != *cur {
-                    pass = false;
-                }
-            }
-
-            if let Some(cur) = &attendance.start_half {
-                if p.start_half != *cur {
-                    pass = false;
-                }
-            }
-
-            if let Some(cur) = &attendance.end_half {
-                if p.end_half != *cur {
-                    pass = false;
-                }
-            }
-
-            pass
-        })
-        .collect();
-
-    AppResponse::ok(res)
-}
-
-pub async fn get(
-    Extension(db): Extension<DBType<EntityAttendance>>,
-    Path(id): Path<String>,
-) -> AppResult {
-    let attendance_db = db.lock().unwrap();
-
-    let cur = attendance_db.iter().find(|p| p.id == id);
-
-    AppResponse::ok(cur)
-}
-
-pub async fn delete(
-    Extension(db): Extension<DBType<EntityAttendance>>,
-    Path(id): Path<String>,
-) -> AppResult {
-    let mut attendance_db = db.lock().unwrap();
-
-    let ind = attendance_db.iter().position(|p| p.id == id);
-
-    if ind.is_none() {
-        return AppResponse::<()>::err("记录不存在");
+use super::resource::{ListContext, Resource};
+
+impl Resource for EntityAttendance {
+    type Create = DTOAttendanceCreate;
+    type Update = DTOAttendanceUpdate;
+    type Param = DTOAttendanceParam;
+    type View = EntityAttendance;
+
+    fn create(id: String, attendance: DTOAttendanceCreate) -> Self {
+        EntityAttendance {
+            id,
+            start_time: attendance.start_time,
+            end_time: attendance.end_time,
+            employee_id: Some(attendance.employee_id),
+            date_type: attendance.date_type,
+            start_half: attendance.start_half,
+            end_half: attendance.end_half,
+            version: 0,
+            deleted_at: None,
+        }
     }
 
-    let ind = ind.unwrap();
+    fn update(cur: &mut Self, attendance: DTOAttendanceUpdate) {
+        if let Some(val) = attendance.start_time {
+            cur.start_time = val;
+        }
 
-    EntityAttendance::store(&attendance_db)?;
+        if let Some(val) = attendance.end_time {
+            cur.end_time = Some(val);
+        }
 
-    AppResponse::ok(attendance_db.remove(ind))
-}
+        if let Some(val) = attendance.employee_id {
+            cur.employee_id = Some(val);
+        }
 
-pub async fn update(
-    Extension(db): Extension<DBType<EntityAttendance>>,
-    Path(id): Path<String>,
-    Json(employee): Json<DTOAttendanceParam>,
-) -> AppResult {
-    let mut employee_db = db.lock().unwrap();
+        if let Some(val) = attendance.date_type {
+            cur.date_type = val;
+        }
 
-    let cur = employee_db.iter_mut().find(|p| p.id == id);
+        if let Some(val) = attendance.start_half {
+            cur.start_half = val;
+        }
 
-    if cur.is_none() {
-        return AppResponse::<()>::err("记录不存在");
+        if let Some(val) = attendance.end_half {
+            cur.end_half = val;
+        }
     }
 
-    let cur: &mut EntityAttendance = cur.unwrap();
-
-    if let Some(val) = employee.start_time {
-        cur.start_time = val;
+    fn matches(_ctx: &ListContext, attendance: &DTOAttendanceParam, p: &Self) -> bool {
+        let mut pass = true;
+
+        if let Some(cur) = &attendance.id
+            && p.id != *cur
+        {
+            pass = false;
+        }
+
+        if let Some(cur) = &attendance.start_time
+            && p.start_time != *cur
+        {
+            pass = false;
+        }
+
+        if let Some(cur) = &attendance.end_time
+            && p.end_time != Some(*cur)
+        {
+            pass = false;
+        }
+
+        if let Some(cur) = &attendance.employee_id
+            && p.employee_id.as_ref() != Some(cur)
+        {
+            pass = false;
+        }
+
+        if let Some(cur) = &attendance.date_type
+            && p.date_type != *cur
+        {
+            pass = false;
+        }
+
+        if let Some(cur) = &attendance.start_half
+            && p.start_half != *cur
+        {
+            pass = false;
+        }
+
+        if let Some(cur) = &attendance.end_half
+            && p.end_half != *cur
+        {
+            pass = false;
+        }
+
+        if let Some(cur) = &attendance.from
+            && p.end_time.unwrap_or(p.start_time).max(p.start_time) < *cur
+        {
+            pass = false;
+        }
+
+        if let Some(cur) = &attendance.to
+            && p.start_time > *cur
+        {
+            pass = false;
+        }
+
+        pass
     }
 
-    if let Some(val) = employee.end_time {
-        cur.end_time = Some(val);
+    /// 日期索引包含记录覆盖的每一天, 按日期范围查询时可以找到与范围有交集的记录
+    fn lookup(attendance: &DTOAttendanceParam) -> Option<KeyQuery> {
+        if let Some(cur) = &attendance.employee_id {
+            Some(KeyQuery::Eq(IndexKey::EmployeeId, cur.clone()))
+        } else if let Some(cur) = attendance.start_time {
+            Some(KeyQuery::Eq(IndexKey::Date, date_key(cur)))
+        } else if attendance.from.is_some() || attendance.to.is_some() {
+            Some(KeyQuery::Range(
+                IndexKey::Date,
+                attendance.from.map(date_key),
+                attendance.to.map(date_key),
+            ))
+        } else {
+            None
+        }
     }
 
-    if let Some(val) = employee.employee_id {
-        cur.employee_id = val;
+    fn warnings(tx: &Transaction, record: &Self) -> Result<Vec<String>> {
+        leave::quota_warnings(tx, record)
     }
 
-    if let Some(val) = employee.date_type {
-        cur.date_type = val;
+    fn view(_all: &StoreRead, p: &Self) -> EntityAttendance {
+        p.clone()
     }
+}
 
-    if let Some(val) = employee.start_half {
-        cur.start_half = val;
-    }
+/// 人员的加班与调休余额及考勤台账
+pub async fn balance(
+    Extension(store): Extension<Store>,
+    Path(employee_id): Path<String>,
+) -> AppResult {
+    let all = store.read_all().await;
 
-    if let Some(val) = employee.end_half {
-        cur.end_half = val;
+    if all.employee.get(&employee_id).is_none() {
+        return AppResponse::<()>::err("人员不存在");
     }
 
-    let res = AppResponse::ok(cur);
+    let calendar = WorkCalendar::new(&all.special_date);
+    let records = all
+        .attendance
+        .list_by(IndexKey::EmployeeId, &employee_id, |_| true);
 
-    EntityAttendance::store(&employee_db)?;
+    AppResponse::ok(ledger::balance(&calendar, &employee_id, records))
+}
 
-    res
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::tests::TestApp;
+
+    #[tokio::test]
+    async fn oversized_or_reversed_ranges_are_rejected() {
+        let app = TestApp::new().await;
+
+        let (_, res) = app
+            .send(
+                "POST",
+                "/employee/create",
+                &[],
+                Some(json!({ "name": "人员", "position": "dev" })),
+            )
+            .await;
+        let employee_id = res["data"]["id"].as_str().unwrap();
+
+        for (start, end) in [("0001-01-01", "9999-12-31"), ("2025-01-02", "2025-01-01")] {
+            let (_, res) = app
+                .send(
+                    "POST",
+                    "/attendance/create",
+                    &[],
+                    Some(json!({
+                        "start_time": start,
+                        "end_time": end,
+                        "employee_id": employee_id,
+                        "date_type": "Overtime",
+                        "start_half": false,
+                        "end_half": false,
+                    })),
+                )
+                .await;
+            assert_eq!(res["code"], "Err", "{}", res);
+
+            let (_, res) = app
+                .send(
+                    "POST",
+                    "/special_date/create",
+                    &[],
+                    Some(json!({ "start_time": start, "end_time": end, "date_type": "Include" })),
+                )
+                .await;
+            assert_eq!(res["code"], "Err", "{}", res);
+        }
+
+        assert!(app.store.attendance.read().await.list(|_| true).is_empty());
+        assert!(
+            app.store
+                .special_date
+                .read()
+                .await
+                .list(|_| true)
+                .is_empty()
+        );
+    }
 }
@@ -0,0 +1,39 @@
+use axum::{Extension, extract::Query};
+
+use crate::{
+    entity::audit::DTOAuditParam,
+    repo::{filter::FilterParam, store::Store},
+    result::{
+        page::PageParam,
+        response::{AppResponse, AppResult},
+    },
+};
+
+/// 分页查询审计记录, 默认新记录在前
+///
+/// 审计记录从文件读取, 不排序且不使用游标时只保留当前页及之前的记录
+pub async fn list(
+    Extension(store): Extension<Store>,
+    Query(param): Query<DTOAuditParam>,
+    Query(filter): Query<FilterParam>,
+    Query(page): Query<PageParam>,
+) -> AppResult {
+    let expr = filter.parse()?;
+    let limit = page.prefix_len();
+
+    let (rows, total) = tokio::task::spawn_blocking(move || {
+        store.audit.query(
+            &param,
+            |p| expr.as_ref().is_none_or(|expr| expr.matches(p)),
+            limit,
+        )
+    })
+    .await??;
+
+    let page = match limit {
+        Some(_) => page.paginate_prefix(rows, total, "id")?,
+        None => page.paginate(rows, "id")?,
+    };
+
+    AppResponse::ok(page)
+}
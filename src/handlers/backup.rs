@@ -0,0 +1,45 @@
+use axum::{
+    Extension,
+    extract::{Path, Query},
+};
+
+use crate::{
+    repo::{backup::BackupManager, filter::FilterParam},
+    result::{
+        page::PageParam,
+        response::{AppResponse, AppResult},
+    },
+};
+
+pub async fn create(Extension(backup): Extension<BackupManager>) -> AppResult {
+    let manifest = backup.create("manual").await?;
+
+    AppResponse::ok(manifest)
+}
+
+/// 分页查询备份, 默认新备份在前
+pub async fn list(
+    Extension(backup): Extension<BackupManager>,
+    Query(filter): Query<FilterParam>,
+    Query(page): Query<PageParam>,
+) -> AppResult {
+    let expr = filter.parse()?;
+
+    let mut list = backup.list().await?;
+    if let Some(expr) = &expr {
+        list.retain(|p| expr.matches(p));
+    }
+
+    AppResponse::ok(page.paginate(list, "name")?)
+}
+
+/// 恢复指定备份, 返回恢复前自动创建的备份, 可用于撤销本次恢复
+pub async fn restore(
+    Extension(backup): Extension<BackupManager>,
+    Path(name): Path<String>,
+) -> AppResult {
+    match backup.restore(&name).await? {
+        Some(pre_restore) => AppResponse::ok(pre_restore),
+        None => AppResponse::<()>::err("备份不存在"),
+    }
+}
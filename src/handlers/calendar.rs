@@ -0,0 +1,94 @@
+use axum::{Extension, extract::Query};
+
+use crate::{
+    entity::{
+        calendar::{
+            DTOCalendarAdd, DTOCalendarAddParam, DTOCalendarDateParam, DTOCalendarDay,
+            DTOCalendarRangeParam, DTOCalendarWorkdays,
+        },
+        special_date::EntitySpecialDate,
+    },
+    repo::{calendar::WorkCalendar, db::DBType},
+    result::response::{AppResponse, AppResult},
+};
+
+/// 逐日列出日期类型时允许的最大天数
+const MAX_LIST_DAYS: usize = 366;
+
+/// 查询日期是否为工作日
+pub async fn day(
+    Extension(db): Extension<DBType<EntitySpecialDate>>,
+    Query(param): Query<DTOCalendarDateParam>,
+) -> AppResult {
+    let calendar = WorkCalendar::between(&*db.read().await, param.date, param.date);
+
+    let kind = calendar.day_kind(param.date);
+
+    AppResponse::ok(DTOCalendarDay {
+        date: param.date,
+        kind,
+        workday: kind.is_workday(),
+    })
+}
+
+/// 逐日列出日期范围内每一天的类型
+pub async fn days(
+    Extension(db): Extension<DBType<EntitySpecialDate>>,
+    Query(param): Query<DTOCalendarRangeParam>,
+) -> AppResult {
+    if param.from > param.to {
+        return AppResponse::<()>::err("开始日期不能晚于结束日期");
+    }
+
+    if (param.to - param.from).num_days() as usize >= MAX_LIST_DAYS {
+        return AppResponse::<()>::err(format!("日期范围不能超过 {} 天", MAX_LIST_DAYS));
+    }
+
+    let calendar = WorkCalendar::between(&*db.read().await, param.from, param.to);
+
+    let res: Vec<DTOCalendarDay> = param
+        .from
+        .iter_days()
+        .take_while(|d| *d <= param.to)
+        .map(|date| {
+            let kind = calendar.day_kind(date);
+            DTOCalendarDay {
+                date,
+                kind,
+                workday: kind.is_workday(),
+            }
+        })
+        .collect();
+
+    AppResponse::ok(res)
+}
+
+/// 统计日期范围内 (包含两端) 的工作日天数
+pub async fn workdays(
+    Extension(db): Extension<DBType<EntitySpecialDate>>,
+    Query(param): Query<DTOCalendarRangeParam>,
+) -> AppResult {
+    if param.from > param.to {
+        return AppResponse::<()>::err("开始日期不能晚于结束日期");
+    }
+
+    let calendar = WorkCalendar::between(&*db.read().await, param.from, param.to);
+
+    AppResponse::ok(DTOCalendarWorkdays {
+        from: param.from,
+        to: param.to,
+        workdays: calendar.workdays(param.from, param.from_half, param.to, param.to_half),
+    })
+}
+
+/// 推算日期之后 (或之前) 第 N 个工作日
+pub async fn add(
+    Extension(db): Extension<DBType<EntitySpecialDate>>,
+    Query(param): Query<DTOCalendarAddParam>,
+) -> AppResult {
+    let calendar = WorkCalendar::new(&*db.read().await);
+
+    let (date, half) = calendar.add_workdays(param.date, param.days)?;
+
+    AppResponse::ok(DTOCalendarAdd { date, half })
+}
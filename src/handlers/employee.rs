@@ -1,136 +1,81 @@
-use axum::{
-    Extension, Json,
-    extract::{Path, Query},
-};
-use uuid::Uuid;
+use anyhow::Result;
 
 use crate::{
+    config::IntegrityConfig,
     entity::employee::{
         DTOEmployee, DTOEmployeeCreate, DTOEmployeeParam, EmployeeStatus, EntityEmployee,
         get_employee_status_meaning,
     },
-    repo::db::{DB, DBType},
-    result::response::{AppResponse, AppResult},
+    repo::{integrity::delete_employee, store::StoreRead, transaction::Transaction},
 };
 
-pub async fn create(
-    Extension(db): Extension<DBType<EntityEmployee>>,
-    Json(employee): Json<DTOEmployeeCreate>,
-) -> AppResult {
-    let mut employee_db = db.lock().unwrap();
-
-    let new_employee = EntityEmployee {
-        id: Uuid::new_v4().to_string(),
-        name: employee.name,
-        status: employee.status.unwrap_or(EmployeeStatus::Working),
-        position: employee.position,
-    };
-
-    employee_db.insert(0, new_employee);
-
-    EntityEmployee::store(&employee_db)?;
+use super::resource::{ListContext, Resource};
+
+impl Resource for EntityEmployee {
+    type Create = DTOEmployeeCreate;
+    type Update = DTOEmployeeParam;
+    type Param = DTOEmployeeParam;
+    type View = DTOEmployee;
+
+    fn create(id: String, employee: DTOEmployeeCreate) -> Self {
+        EntityEmployee {
+            id,
+            name: employee.name,
+            status: employee.status.unwrap_or(EmployeeStatus::Working),
+            position: employee.position,
+            version: 0,
+            deleted_at: None,
+        }
+    }
 
-    AppResponse::ok(employee_db.first())
-}
+    fn update(cur: &mut Self, employee: DTOEmployeeParam) {
+        if let Some(name) = employee.name {
+            cur.name = name;
+        }
+        if let Some(code) = employee.status {
+            cur.status = code;
+        }
+        if let Some(position) = employee.position {
+            cur.position = position;
+        }
+    }
 
-pub async fn list(
-    Extension(db): Extension<DBType<EntityEmployee>>,
-    Query(employee): Query<DTOEmployeeParam>,
-) -> AppResult {
-    let employee_db = db.lock().unwrap();
+    fn matches(_ctx: &ListContext, employee: &DTOEmployeeParam, p: &Self) -> bool {
+        let mut pass = true;
 
-    let res: Vec<DTOEmployee> = employee_db
-        .iter()
-        .filter(|p| {
-            let mut pass = true;
+        if let Some(cur) = &employee.id
+            && p.id != *cur
+        {
+            pass = false;
+        }
 
-            if let Some(cur) = &employee.id {
-                if p.id != *cur {
-                    pass = false;
-                }
-            }
+        if let Some(cur) = &employee.name
+            && !p.name.contains(cur)
+        {
+            pass = false;
+        }
 
-            if let Some(cur) = &employee.name {
-                if !p.name.contains(cur) {
-                    pass = false;
-                }
-            }
+        if let Some(cur) = &employee.status
+            && p.status != *cur
+        {
+            pass = false;
+        }
 
-            if let Some(cur) = &employee.status {
-                if p.status != *cur {
-                    pass = false;
-                }
-            }
+        pass
+    }
 
-            pass
-        })
-        .map(|p| DTOEmployee {
+    fn view(_all: &StoreRead, p: &Self) -> DTOEmployee {
+        DTOEmployee {
             id: p.id.clone(),
             name: p.name.clone(),
             status: p.status.clone(),
             status_meaning: get_employee_status_meaning(&p.status),
             position: p.position.clone(),
-        })
-        .collect();
-
-    AppResponse::ok(res)
-}
-
-pub async fn get(
-    Extension(db): Extension<DBType<EntityEmployee>>,
-    Path(id): Path<String>,
-) -> AppResult {
-    let employee_db = db.lock().unwrap();
-
-    let cur = employee_db.iter().find(|p| p.id == id);
-
-    AppResponse::ok(cur)
-}
-
-pub async fn delete(
-    Extension(db): Extension<DBType<EntityEmployee>>,
-    Path(id): Path<String>,
-) -> AppResult {
-    let mut employee_db = db.lock().unwrap();
-
-    let ind = employee_db.iter().position(|p| p.id == id);
-
-    if ind.is_none() {
-        return AppResponse::<()>::err("记录不存在");
+        }
     }
 
-    let ind = ind.unwrap();
-
-    EntityEmployee::store(&employee_db)?;
-
-    AppResponse::ok(employee_db.remove(ind))
-}
-
-pub async fn update(
-    Extension(db): Extension<DBType<EntityEmployee>>,
-    Path(id): Path<String>,
-    Json(employee): Json<DTOEmployeeParam>,
-) -> AppResult {
-    let mut employee_db = db.lock().unwrap();
-
-    let cur = employee_db.iter_mut().find(|p| p.id == id);
-
-    if cur.is_none() {
-        return AppResponse::<()>::err("记录不存在");
+    /// 删除人员, 引用他的入项记录和考勤记录按配置处理, 参考 [delete_employee]
+    fn delete(tx: &mut Transaction, rules: &IntegrityConfig, id: &str) -> Result<Option<Self>> {
+        delete_employee(tx, rules, id)
     }
-
-    let cur: &mut EntityEmployee = cur.unwrap();
-
-    if let Some(name) = employee.name {
-        cur.name = name;
-    }
-    if let Some(code) = employee.status {
-        cur.status = code;
-    }
-
-    let res = AppResponse::ok(cur);
-
-    EntityEmployee::store(&employee_db)?;
-
-    res
 }
@@ -0,0 +1,23 @@
+use axum::{Extension, Json};
+
+use crate::{
+    entity::holiday::DTOHolidayImport,
+    repo::holiday::HolidayImporter,
+    result::response::{AppResponse, AppResult},
+};
+
+/// 节假日目录下可导入的文件
+pub async fn files(Extension(holiday): Extension<HolidayImporter>) -> AppResult {
+    AppResponse::ok(holiday.files().await?)
+}
+
+/// 从节假日文件导入特殊日期, preview 为 true 时只返回导入结果
+pub async fn import(
+    Extension(holiday): Extension<HolidayImporter>,
+    Json(param): Json<DTOHolidayImport>,
+) -> AppResult {
+    match holiday.import(&param).await? {
+        Some(res) => AppResponse::ok(res),
+        None => AppResponse::<()>::err("节假日文件不存在"),
+    }
+}
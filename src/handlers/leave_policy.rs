@@ -0,0 +1,158 @@
+use axum::{
+    Extension,
+    extract::{Path, Query},
+};
+use chrono::Local;
+
+use crate::{
+    entity::leave_policy::{
+        DTOLeaveBalanceParam, DTOLeavePolicyCreate, DTOLeavePolicyParam, DTOLeavePolicyUpdate,
+        EntityLeavePolicy,
+    },
+    repo::{
+        calendar::WorkCalendar,
+        index::IndexKey,
+        leave::{effective_start, leave_balance, policy_for},
+        query::KeyQuery,
+        store::{Store, StoreRead},
+    },
+    result::response::{AppResponse, AppResult},
+};
+
+use super::resource::{ListContext, Resource};
+
+impl Resource for EntityLeavePolicy {
+    type Create = DTOLeavePolicyCreate;
+    type Update = DTOLeavePolicyUpdate;
+    type Param = DTOLeavePolicyParam;
+    type View = EntityLeavePolicy;
+
+    fn create(id: String, policy: DTOLeavePolicyCreate) -> Self {
+        EntityLeavePolicy {
+            id,
+            name: policy.name,
+            annual_quota: policy.annual_quota,
+            accrual_per_month: policy.accrual_per_month,
+            carry_over_cap: policy.carry_over_cap,
+            carry_over_expiry: policy.carry_over_expiry,
+            start_date: Some(
+                policy
+                    .start_date
+                    .unwrap_or_else(|| Local::now().date_naive()),
+            ),
+            employee_ids: policy.employee_ids,
+            positions: policy.positions,
+            version: 0,
+            deleted_at: None,
+        }
+    }
+
+    fn update(cur: &mut Self, policy: DTOLeavePolicyUpdate) {
+        if let Some(val) = policy.name {
+            cur.name = val;
+        }
+
+        if let Some(val) = policy.annual_quota {
+            cur.annual_quota = val;
+        }
+
+        if let Some(val) = policy.accrual_per_month {
+            cur.accrual_per_month = val;
+        }
+
+        if let Some(val) = policy.carry_over_cap {
+            cur.carry_over_cap = val;
+        }
+
+        if let Some(val) = policy.carry_over_expiry {
+            cur.carry_over_expiry = (!val.is_empty()).then_some(val);
+        }
+
+        if let Some(val) = policy.start_date {
+            cur.start_date = Some(val);
+        }
+
+        if let Some(val) = policy.employee_ids {
+            cur.employee_ids = val;
+        }
+
+        if let Some(val) = policy.positions {
+            cur.positions = val;
+        }
+    }
+
+    fn matches(_ctx: &ListContext, policy: &DTOLeavePolicyParam, p: &Self) -> bool {
+        let mut pass = true;
+
+        if let Some(cur) = &policy.id
+            && p.id != *cur
+        {
+            pass = false;
+        }
+
+        if let Some(cur) = &policy.name
+            && !p.name.contains(cur.as_str())
+        {
+            pass = false;
+        }
+
+        if let Some(cur) = &policy.employee_id
+            && !p.employee_ids.contains(cur)
+        {
+            pass = false;
+        }
+
+        if let Some(cur) = &policy.position
+            && !p.positions.contains(cur)
+        {
+            pass = false;
+        }
+
+        pass
+    }
+
+    fn lookup(policy: &DTOLeavePolicyParam) -> Option<KeyQuery> {
+        policy
+            .employee_id
+            .clone()
+            .map(|cur| KeyQuery::Eq(IndexKey::EmployeeId, cur))
+    }
+
+    fn view(_all: &StoreRead, p: &Self) -> EntityLeavePolicy {
+        p.clone()
+    }
+}
+
+/// 人员截至指定日期 (默认当天) 的当年年假余额
+pub async fn balance(
+    Extension(store): Extension<Store>,
+    Path(employee_id): Path<String>,
+    Query(param): Query<DTOLeaveBalanceParam>,
+) -> AppResult {
+    let all = store.read_all().await;
+
+    let Some(employee) = all.employee.get(&employee_id) else {
+        return AppResponse::<()>::err("人员不存在");
+    };
+
+    let Some(policy) = policy_for(&all.leave_policy, employee) else {
+        return AppResponse::<()>::err("该人员没有适用的年假政策");
+    };
+
+    let calendar = WorkCalendar::new(&all.special_date);
+    let records = all
+        .attendance
+        .list_by(IndexKey::EmployeeId, &employee_id, |_| true);
+
+    let date = param.date.unwrap_or_else(|| Local::now().date_naive());
+    let start = effective_start(&all.employee_change, policy, &employee_id);
+
+    AppResponse::ok(leave_balance(
+        &calendar,
+        policy,
+        &employee_id,
+        &records,
+        start,
+        date,
+    ))
+}
@@ -1,5 +1,14 @@
 pub mod attendance;
+pub mod audit;
+pub mod backup;
+pub mod calendar;
 pub mod employee;
 pub mod employee_change;
+pub mod holiday;
+pub mod leave_policy;
 pub mod project;
+pub mod reload;
+pub mod resource;
 pub mod special_date;
+pub mod trash;
+pub mod undo;
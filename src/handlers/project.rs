@@ -1,177 +1,266 @@
-use axum::{
-    Extension, Json,
-    extract::{Path, Query},
-};
-use uuid::Uuid;
+use anyhow::Result;
+use chrono::Local;
 
 use crate::{
+    config::IntegrityConfig,
     entity::project::{DTOProjectCreate, DTOProjectParam, DTOProjectUpdate, EntityProject},
-    repo::db::{DB, DBType},
-    result::response::{AppResponse, AppResult},
-    serde_custom::date_format::{date_format::DATE_FORMAT, datetime_format::DATETIME_FORMAT},
+    repo::{
+        effort::project_effort, index::IndexKey, integrity::delete_project, store::StoreRead,
+        transaction::Transaction,
+    },
+    serde_custom::date_format::date_format::DATE_FORMAT,
 };
 
-pub async fn create(
-    Extension(db): Extension<DBType<EntityProject>>,
-    Json(project): Json<DTOProjectCreate>,
-) -> AppResult {
-    let mut project_db = db.lock().unwrap();
-
-    let new_project = EntityProject {
-        id: Uuid::new_v4().to_string(),
-        name: project.name,
-        code: project.code,
-        release_date: project.release_date,
-        plan_delivery_date: project.plan_delivery_date,
-        tech_days: project.tech_days,
-        test_days: project.test_days,
-        price: project.price,
-        pm: project.pm,
-    };
-
-    project_db.insert(0, new_project);
-
-    EntityProject::store(&project_db)?;
+use super::resource::{ListContext, Resource};
+
+impl Resource for EntityProject {
+    type Create = DTOProjectCreate;
+    type Update = DTOProjectUpdate;
+    type Param = DTOProjectParam;
+    type View = EntityProject;
+
+    fn create(id: String, project: DTOProjectCreate) -> Self {
+        EntityProject {
+            id,
+            name: project.name,
+            code: project.code,
+            release_date: project.release_date,
+            plan_delivery_date: project.plan_delivery_date,
+            tech_days: project.tech_days,
+            test_days: project.test_days,
+            price: project.price,
+            pm: project.pm,
+            version: 0,
+            deleted_at: None,
+        }
+    }
 
-    AppResponse::ok(project_db.first())
-}
+    fn update(cur: &mut Self, project: DTOProjectUpdate) {
+        if let Some(name) = project.name {
+            cur.name = name;
+        }
+        if let Some(code) = project.code {
+            cur.code = code;
+        }
+        if let Some(release_date) = project.release_date {
+            cur.release_date = release_date;
+        }
+        if let Some(plan_delivery_date) = project.plan_delivery_date {
+            cur.plan_delivery_date = plan_delivery_date;
+        }
+        if let Some(tech_days) = project.tech_days {
+            cur.tech_days = tech_days;
+        }
+        if let Some(test_days) = project.test_days {
+            cur.test_days = test_days;
+        }
+        if let Some(price) = project.price {
+            cur.price = price;
+        }
+        if let Some(pm) = project.pm {
+            cur.pm = pm;
+        }
+    }
 
-pub async fn list(
-    Extension(db): Extension<DBType<EntityProject>>,
-    Query(project): Query<DTOProjectParam>,
-) -> AppResult {
-    let project_db = db.lock().unwrap();
-
-    let res: Vec<&EntityProject> = project_db
-        .iter()
-        .filter(|p| {
-            let mut pass = true;
-
-            if let Some(cur) = &project.id {
-                if p.id != *cur {
-                    pass = false;
-                }
+    /// employee 按人员id或姓名匹配曾经入项的人员, low_days / low_percent 按剩余人天筛选, 参考 [project_effort]
+    fn matches(ctx: &ListContext, project: &DTOProjectParam, p: &Self) -> bool {
+        let mut pass = true;
+
+        if let Some(cur) = &project.id
+            && p.id != *cur
+        {
+            pass = false;
+        }
+
+        if let Some(cur) = &project.name_or_code
+            && !p.name.contains(cur)
+            && !p.code.contains(cur)
+        {
+            pass = false;
+        }
+
+        if let Some(cur) = &project.pm
+            && !p.pm.contains(cur)
+        {
+            pass = false;
+        }
+
+        if let Some(cur) = &project.release_date_fuzzy {
+            let d_str = p.release_date.format(DATE_FORMAT).to_string();
+            if !d_str.contains(cur) {
+                pass = false;
             }
+        }
 
-            if let Some(cur) = &project.name_or_code {
-                if !p.name.contains(cur) && !p.code.contains(cur) {
-                    pass = false;
-                }
+        if let Some(cur) = &project.plan_delivery_date_fuzzy {
+            let d_str = p.plan_delivery_date.format(DATE_FORMAT).to_string();
+            if !d_str.contains(cur) {
+                pass = false;
             }
-
-            if let Some(cur) = &project.pm {
-                if !p.pm.contains(cur) {
-                    pass = false;
-                }
+        }
+
+        if let Some(cur) = &project.price
+            && p.price != *cur
+        {
+            pass = false;
+        }
+
+        if let Some(cur) = &project.days {
+            let days = p.tech_days + p.test_days;
+            if days != *cur {
+                pass = false;
             }
-
-            if let Some(cur) = &project.release_date_fuzzy {
-                let d_str = p.release_date.format(DATE_FORMAT).to_string();
-                if !d_str.contains(cur) {
-                    pass = false;
-                }
+        }
+
+        if let Some(cur) = &project.employee
+            && pass
+        {
+            let assigned = ctx
+                .employee_change
+                .list_by(IndexKey::ProjectId, &p.id, |_| true)
+                .into_iter()
+                .any(|change| {
+                    change.employee_id.as_ref() == Some(cur)
+                        || change
+                            .employee_id
+                            .as_deref()
+                            .and_then(|id| ctx.employee.find_by_id(id))
+                            .is_some_and(|e| e.name.contains(cur.as_str()))
+                });
+
+            if !assigned {
+                pass = false;
             }
+        }
 
-            if let Some(cur) = &project.plan_delivery_date_fuzzy {
-                let d_str = p.plan_delivery_date.format(DATE_FORMAT).to_string();
-                if !d_str.contains(cur) {
-                    pass = false;
-                }
-            }
+        if (project.low_days.is_some() || project.low_percent.is_some()) && pass {
+            let effort = project_effort(ctx, ctx.calendar(), p, Local::now().date_naive());
 
-            if let Some(cur) = &project.price {
-                if p.price != *cur {
-                    pass = false;
-                }
+            if let Some(cur) = project.low_days
+                && effort.remaining >= cur as f64
+            {
+                pass = false;
             }
 
-            if let Some(cur) = &project.days {
-                let days = p.tech_days + p.test_days;
-                if days != *cur {
-                    pass = false;
-                }
+            if let Some(cur) = project.low_percent
+                && effort.remaining_percent() >= cur as f64
+            {
+                pass = false;
             }
+        }
 
-            pass
-        })
-        .collect();
-
-    AppResponse::ok(res)
-}
-
-pub async fn get(
-    Extension(db): Extension<DBType<EntityProject>>,
-    Path(id): Path<String>,
-) -> AppResult {
-    let project_db = db.lock().unwrap();
+        pass
+    }
 
-    let cur = project_db.iter().find(|p| p.id == id);
+    fn view(_all: &StoreRead, p: &Self) -> EntityProject {
+        p.clone()
+    }
 
-    AppResponse::ok(cur)
+    /// 删除项目, 引用它的入项记录按配置处理, 参考 [delete_project]
+    fn delete(tx: &mut Transaction, rules: &IntegrityConfig, id: &str) -> Result<Option<Self>> {
+        delete_project(tx, rules, id)
+    }
 }
 
-pub async fn delete(
-    Extension(db): Extension<DBType<EntityProject>>,
-    Path(id): Path<String>,
-) -> AppResult {
-    let mut project_db = db.lock().unwrap();
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
 
-    let ind = project_db.iter().position(|p| p.id == id);
+    use crate::{
+        config::AppConfig,
+        repo::{crypto::Keyring, store::Store, transaction::Scope},
+    };
 
-    if ind.is_none() {
-        return AppResponse::<()>::err("记录不存在");
+    use super::*;
+
+    fn project(id: &str, tech_days: i32) -> EntityProject {
+        serde_json::from_value(json!({
+            "id": id,
+            "name": format!("项目{id}"),
+            "code": id,
+            "release_date": "2024-01-01",
+            "plan_delivery_date": "2024-06-01",
+            "tech_days": tech_days,
+            "test_days": 0,
+            "price": 1.0,
+            "pm": "pm",
+        }))
+        .unwrap()
     }
 
-    let ind = ind.unwrap();
-
-    EntityProject::store(&project_db)?;
-
-    AppResponse::ok(project_db.remove(ind))
-}
-
-pub async fn update(
-    Extension(db): Extension<DBType<EntityProject>>,
-    Path(id): Path<String>,
-    Json(project): Json<DTOProjectUpdate>,
-) -> AppResult {
-    let mut project_db = db.lock().unwrap();
-
-    let cur = project_db.iter_mut().find(|p| p.id == id);
+    /// 项目 a 预算 10 人天, 人员 e (张三) 在 2024-01-01 (周一) 到 2024-01-05 入项, 剩余 5 人天即 50%;
+    /// 项目 b 预算为 0, 项目 c 预算 100 人天且没有人入项
+    async fn store() -> (tempfile::TempDir, Store) {
+        let dir = tempfile::tempdir().unwrap();
+        let store =
+            Store::load(&AppConfig::with_data_dir(dir.path()), &Keyring::default()).unwrap();
+
+        store
+            .transaction(Scope::ALL, |tx| {
+                tx.project.put(project("a", 10))?;
+                tx.project.put(project("b", 0))?;
+                tx.project.put(project("c", 100))?;
+                tx.employee.put(serde_json::from_value(json!({
+                    "id": "e",
+                    "name": "张三",
+                    "status": "Working",
+                    "position": "dev",
+                }))?)?;
+                tx.employee_change.put(serde_json::from_value(json!({
+                    "id": "c1",
+                    "employee_id": "e",
+                    "project_id": "a",
+                    "in_time": "2024-01-01",
+                    "out_time": "2024-01-05",
+                }))?)?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        (dir, store)
+    }
 
-    if cur.is_none() {
-        return AppResponse::<()>::err("项目不存在");
+    /// 符合查询参数的项目id, 按id排序
+    fn matched(ctx: &ListContext, param: Value) -> Vec<String> {
+        let param: DTOProjectParam = serde_json::from_value(param).unwrap();
+
+        let mut ids: Vec<String> = ctx
+            .project
+            .list(|p| EntityProject::matches(ctx, &param, p))
+            .into_iter()
+            .map(|p| p.id.clone())
+            .collect();
+        ids.sort();
+        ids
     }
 
-    let cur: &mut EntityProject = cur.unwrap();
+    #[tokio::test]
+    async fn employee_filter_matches_id_or_name() {
+        let (_dir, store) = store().await;
+        let ctx = ListContext::new(store.read_all().await);
 
-    if let Some(name) = project.name {
-        cur.name = name;
-    }
-    if let Some(code) = project.code {
-        cur.code = code;
-    }
-    if let Some(release_date) = project.release_date {
-        cur.release_date = release_date;
-    }
-    if let Some(plan_delivery_date) = project.plan_delivery_date {
-        cur.plan_delivery_date = plan_delivery_date;
-    }
-    if let Some(tech_days) = project.tech_days {
-        cur.tech_days = tech_days;
-    }
-    if let Some(test_days) = project.test_days {
-        cur.test_days = test_days;
-    }
-    if let Some(price) = project.price {
-        cur.price = price;
-    }
-    if let Some(pm) = project.pm {
-        cur.pm = pm;
+        assert_eq!(matched(&ctx, json!({ "employee": "e" })), ["a"]);
+        assert_eq!(matched(&ctx, json!({ "employee": "张" })), ["a"]);
+        assert!(matched(&ctx, json!({ "employee": "李" })).is_empty());
     }
 
-    let res = AppResponse::ok(cur);
+    #[tokio::test]
+    async fn low_days_and_low_percent_filter_by_remaining() {
+        let (_dir, store) = store().await;
+        let ctx = ListContext::new(store.read_all().await);
 
-    EntityProject::store(&project_db)?;
+        assert_eq!(matched(&ctx, json!({ "low_days": 6 })), ["a", "b"]);
+        assert_eq!(matched(&ctx, json!({ "low_days": 5 })), ["b"]);
 
-    res
+        // 预算为 0 时剩余百分比按 0 计
+        assert_eq!(matched(&ctx, json!({ "low_percent": 60.0 })), ["a", "b"]);
+        assert_eq!(matched(&ctx, json!({ "low_percent": 50.0 })), ["b"]);
+        assert!(matched(&ctx, json!({ "low_percent": 0.0 })).is_empty());
+
+        assert_eq!(
+            matched(&ctx, json!({ "low_days": 6, "low_percent": 50.0 })),
+            ["b"]
+        );
+    }
 }
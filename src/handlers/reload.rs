@@ -0,0 +1,15 @@
+use axum::Extension;
+
+use crate::{
+    repo::store::Store,
+    result::response::{AppResponse, AppResult},
+};
+
+/// 立即检查数据文件是否在外部被修改并重新加载, 返回重新加载的集合名
+///
+/// 关闭定时检查 (hot_reload.interval_ms 为 0) 时, 手动修改数据文件后需要调用此接口, 否则该集合的写入会一直失败
+pub async fn reload(Extension(store): Extension<Store>) -> AppResult {
+    let reloaded = tokio::task::spawn_blocking(move || store.reload_external()).await??;
+
+    AppResponse::ok(reloaded)
+}
@@ -0,0 +1,404 @@
+use std::{ops::Deref, sync::OnceLock};
+
+use anyhow::Result;
+use axum::{
+    Extension, Json, Router,
+    extract::{Path, Query},
+    routing::{self, post},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use uuid::Uuid;
+
+use crate::{
+    config::IntegrityConfig,
+    repo::{
+        calendar::WorkCalendar,
+        db::{DB, DBType, Record},
+        filter::FilterParam,
+        query::{KeyQuery, Query as RecordQuery},
+        store::{Store, StoreRead},
+        transaction::Transaction,
+    },
+    result::{
+        etag::{IfMatch, with_etag},
+        page::PageParam,
+        response::{AppResponse, AppResult},
+    },
+};
+
+use super::trash;
+
+/// 通过 [routes] 提供增删改查接口的实体
+///
+/// 新增实体只需实现 [DB] 和本 trait, 再在路由中合并 [routes]
+pub trait Resource: DB + 'static {
+    /// 创建参数
+    type Create: DeserializeOwned + Send + 'static;
+    /// 更新参数
+    type Update: DeserializeOwned + Send + 'static;
+    /// 查询参数
+    type Param: DeserializeOwned + Send + Sync + 'static;
+    /// 列表中每条记录的返回格式
+    type View: Serialize;
+
+    /// 由创建参数构造新记录
+    fn create(id: String, dto: Self::Create) -> Self::Entity;
+
+    /// 将更新参数应用到记录, id 和删除时间不应修改
+    fn update(record: &mut Self::Entity, dto: Self::Update);
+
+    /// 记录是否满足查询条件, 条件可以涉及其他集合
+    fn matches(ctx: &ListContext, param: &Self::Param, record: &Self::Entity) -> bool;
+
+    /// 查询条件中可以通过索引缩小范围的条件, sqlite 后端直接在数据库中按该条件查询
+    fn lookup(_param: &Self::Param) -> Option<KeyQuery> {
+        None
+    }
+
+    /// 列表中记录的返回格式, 可以关联其他集合
+    fn view(all: &StoreRead, record: &Self::Entity) -> Self::View;
+
+    /// 保存记录后需要提示但不阻止保存的问题, 在响应的 msg 中返回
+    fn warnings(_tx: &Transaction, _record: &Self::Entity) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    /// 删除记录, 默认移入回收站, 记录不存在时返回 None
+    fn delete(
+        tx: &mut Transaction,
+        _rules: &IntegrityConfig,
+        id: &str,
+    ) -> Result<Option<Self::Entity>> {
+        Ok(Self::staged(tx).soft_delete(id)?.cloned())
+    }
+}
+
+/// 一次列表查询中各记录共用的数据, 可以通过 Deref 读取全部集合
+///
+/// 工作日历等需要由整个集合构造的数据在第一次使用时构造, 同一请求中只构造一次
+pub struct ListContext<'a> {
+    all: StoreRead<'a>,
+    calendar: OnceLock<WorkCalendar>,
+}
+
+impl<'a> ListContext<'a> {
+    pub fn new(all: StoreRead<'a>) -> Self {
+        ListContext {
+            all,
+            calendar: OnceLock::new(),
+        }
+    }
+
+    /// 由全部特殊日期构造的工作日历
+    pub fn calendar(&self) -> &WorkCalendar {
+        self.calendar
+            .get_or_init(|| WorkCalendar::new(&self.all.special_date))
+    }
+}
+
+impl<'a> Deref for ListContext<'a> {
+    type Target = StoreRead<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.all
+    }
+}
+
+/// 实体的增删改查及回收站路由, 路径前缀为集合名
+pub fn routes<R: Resource>() -> Router {
+    let name = R::collection_name();
+
+    Router::new()
+        .route(&format!("/{}/create", name), post(create::<R>))
+        .route(&format!("/{}/list", name), routing::get(list::<R>))
+        .route(&format!("/{}/get/{{id}}", name), routing::get(get::<R>))
+        .route(&format!("/{}/delete/{{id}}", name), post(delete::<R>))
+        .route(&format!("/{}/update/{{id}}", name), post(update::<R>))
+        .route(&format!("/{}/trash", name), routing::get(trash::list::<R>))
+        .route(
+            &format!("/{}/restore/{{id}}", name),
+            post(trash::restore::<R>),
+        )
+        .route(&format!("/{}/purge/{{id}}", name), post(trash::purge::<R>))
+}
+
+/// 新增记录, 记录引用的其他记录需要存在
+pub async fn create<R: Resource>(
+    Extension(store): Extension<Store>,
+    Json(dto): Json<R::Create>,
+) -> AppResult {
+    let record = R::create(Uuid::new_v4().to_string(), dto);
+
+    let (cur, warnings) = store
+        .transaction(R::scope(), |tx| {
+            R::check_references(tx, &record)?;
+            R::validate(tx, &record)?;
+            let cur = R::staged(tx).put(record)?.clone();
+            let warnings = R::warnings(tx, &cur)?;
+            Ok((cur, warnings))
+        })
+        .await?;
+
+    with_warnings(cur, warnings)
+}
+
+/// 分页查询满足条件的记录, 默认新记录在前
+pub async fn list<R: Resource>(
+    Extension(store): Extension<Store>,
+    Query(param): Query<R::Param>,
+    Query(filter): Query<FilterParam>,
+    Query(page): Query<PageParam>,
+) -> AppResult {
+    let expr = filter.parse()?;
+
+    let ctx = ListContext::new(store.read_all().await);
+
+    // 有索引的条件先通过索引缩小范围, 过滤条件与索引一起交给存储后端执行
+    let rows = R::read(&ctx)
+        .query(RecordQuery::active(R::lookup(&param)).filter(expr))
+        .await?;
+
+    let res: Vec<R::View> = rows
+        .iter()
+        .filter(|p| R::matches(&ctx, &param, p))
+        .map(|p| R::view(&ctx, p))
+        .collect();
+
+    AppResponse::ok(page.paginate(res, "id")?)
+}
+
+pub async fn get<R: Resource>(
+    Extension(db): Extension<DBType<R::Entity>>,
+    Path(id): Path<String>,
+) -> AppResult {
+    let collection = db.read().await;
+
+    let cur = collection.get(&id);
+
+    with_etag(AppResponse::ok(cur), cur.map(|p| p.version()))
+}
+
+/// 删除记录, 参考 [Resource::delete]
+pub async fn delete<R: Resource>(
+    Extension(store): Extension<Store>,
+    Path(id): Path<String>,
+    if_match: IfMatch,
+) -> AppResult {
+    let removed = store
+        .transaction(R::scope(), |tx| {
+            if let Some(cur) = R::staged(tx).read()?.get(&id) {
+                if_match.check(cur)?;
+            }
+
+            R::delete(tx, &store.integrity, &id)
+        })
+        .await?;
+
+    match removed {
+        Some(removed) => AppResponse::ok(removed),
+        None => AppResponse::<()>::err("记录不存在"),
+    }
+}
+
+/// 更新记录, 记录引用的其他记录需要存在
+pub async fn update<R: Resource>(
+    Extension(store): Extension<Store>,
+    Path(id): Path<String>,
+    if_match: IfMatch,
+    Json(dto): Json<R::Update>,
+) -> AppResult {
+    let cur = store
+        .transaction(R::scope(), |tx| {
+            let Some(cur) = R::staged(tx).read()?.get(&id) else {
+                return Ok(None);
+            };
+
+            if_match.check(cur)?;
+
+            let mut cur = cur.clone();
+            R::update(&mut cur, dto);
+
+            R::check_references(tx, &cur)?;
+            R::validate(tx, &cur)?;
+            let cur = R::staged(tx).put(cur)?.clone();
+            let warnings = R::warnings(tx, &cur)?;
+            Ok(Some((cur, warnings)))
+        })
+        .await?;
+
+    match cur {
+        Some((cur, warnings)) => {
+            let version = cur.version();
+            with_etag(with_warnings(cur, warnings), Some(version))
+        }
+        None => AppResponse::<()>::err("记录不存在"),
+    }
+}
+
+/// 成功响应, 有提示时以分号连接后放在 msg 中
+fn with_warnings<T: Serialize>(data: T, warnings: Vec<String>) -> AppResult {
+    AppResponse::new()
+        .msg(warnings.join("; "))
+        .data(data)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use crate::{
+        repo::{crypto::Keyring, persist::DURABLE_HEADER},
+        tests::TestApp,
+    };
+
+    use super::*;
+
+    async fn create_employee(app: &TestApp, name: &str) -> String {
+        let (_, res) = app
+            .send(
+                "POST",
+                "/employee/create",
+                &[],
+                Some(json!({ "name": name, "position": "dev" })),
+            )
+            .await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+
+        res["data"]["id"].as_str().unwrap().to_string()
+    }
+
+    /// 列表中的记录id, 新记录在前
+    async fn list_ids(app: &TestApp, uri: &str) -> Vec<String> {
+        let (_, res) = app.send("GET", uri, &[], None).await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+
+        res["data"]["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["id"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn delete_moves_record_to_trash_and_persists() {
+        let app = TestApp::new().await;
+        let kept = create_employee(&app, "保留").await;
+        let id = create_employee(&app, "删除").await;
+
+        let (_, res) = app
+            .send(
+                "POST",
+                &format!("/employee/delete/{}", id),
+                &[(DURABLE_HEADER, "true")],
+                None,
+            )
+            .await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+        assert_eq!(res["data"]["id"], id.as_str());
+
+        let (_, res) = app
+            .send("GET", &format!("/employee/get/{}", id), &[], None)
+            .await;
+        assert_eq!(res["data"], Value::Null);
+
+        // 列表不包含回收站中的记录
+        assert_eq!(list_ids(&app, "/employee/list").await, [kept.as_str()]);
+        assert_eq!(list_ids(&app, "/employee/trash").await, [id.as_str()]);
+
+        // 重新加载后仍在回收站中
+        let store = Store::load(&app.config, &Keyring::default()).unwrap();
+        let employees = store.employee.read().await;
+        assert!(employees.get(&id).is_none());
+        assert!(employees.get(&kept).is_some());
+        let trashed: Vec<&str> = employees.trash().iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(trashed, [id.as_str()]);
+
+        // 再次删除时记录已不存在
+        let (_, res) = app
+            .send("POST", &format!("/employee/delete/{}", id), &[], None)
+            .await;
+        assert_eq!(res["code"], "Err", "{}", res);
+        assert_eq!(res["msg"], "记录不存在");
+    }
+
+    #[tokio::test]
+    async fn update_missing_record_is_an_error() {
+        let app = TestApp::new().await;
+        create_employee(&app, "人员").await;
+
+        let (_, res) = app
+            .send(
+                "POST",
+                "/employee/update/missing",
+                &[],
+                Some(json!({ "name": "新名字" })),
+            )
+            .await;
+        assert_eq!(res["code"], "Err", "{}", res);
+        assert_eq!(res["msg"], "记录不存在");
+        assert!(app.store.employee.read().await.get("missing").is_none());
+    }
+
+    #[tokio::test]
+    async fn warnings_are_joined_into_msg() {
+        let app = TestApp::new().await;
+        let employee_id = create_employee(&app, "人员").await;
+
+        let (_, res) = app
+            .send(
+                "POST",
+                "/leave_policy/create",
+                &[],
+                Some(json!({
+                    "name": "默认",
+                    "annual_quota": 1.0,
+                    "employee_ids": [employee_id],
+                })),
+            )
+            .await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+
+        let leave = |start: &str, end: &str| {
+            json!({
+                "start_time": start,
+                "end_time": end,
+                "employee_id": employee_id,
+                "date_type": "Leave",
+                "start_half": false,
+                "end_half": false,
+            })
+        };
+
+        // 额度内没有提示
+        let (_, res) = app
+            .send(
+                "POST",
+                "/attendance/create",
+                &[],
+                Some(leave("2026-03-02", "2026-03-02")),
+            )
+            .await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+        assert_eq!(res["msg"], "");
+
+        // 跨年的请假两年都超出额度, 提示以分号连接, 记录仍然保存
+        let (_, res) = app
+            .send(
+                "POST",
+                "/attendance/create",
+                &[],
+                Some(leave("2026-12-30", "2027-01-05")),
+            )
+            .await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+        assert!(res["data"]["id"].is_string());
+
+        let msg = res["msg"].as_str().unwrap();
+        let warnings: Vec<&str> = msg.split("; ").collect();
+        assert_eq!(warnings.len(), 2, "{}", msg);
+        assert!(warnings[0].starts_with("2026 年"), "{}", msg);
+        assert!(warnings[1].starts_with("2027 年"), "{}", msg);
+    }
+}
@@ -0,0 +1,60 @@
+use axum::{
+    Extension,
+    extract::{Path, Query},
+};
+
+use crate::{
+    repo::{
+        db::{DB, DBType},
+        filter::FilterParam,
+        query::Query as RecordQuery,
+        store::Store,
+        trash,
+    },
+    result::{
+        page::PageParam,
+        response::{AppResponse, AppResult},
+    },
+};
+
+/// 分页查询集合回收站中的记录
+pub async fn list<D: DB>(
+    Extension(db): Extension<DBType<D::Entity>>,
+    Query(filter): Query<FilterParam>,
+    Query(page): Query<PageParam>,
+) -> AppResult {
+    let expr = filter.parse()?;
+
+    let collection = db.read().await;
+
+    let rows = collection.query(RecordQuery::trash().filter(expr)).await?;
+
+    AppResponse::ok(page.paginate(rows, "id")?)
+}
+
+/// 从回收站恢复记录, 记录引用的其他记录需要存在 (未被删除)
+pub async fn restore<D: DB>(
+    Extension(store): Extension<Store>,
+    Path(id): Path<String>,
+) -> AppResult {
+    let restored = store
+        .transaction(D::scope(), |tx| trash::restore::<D>(tx, &id))
+        .await?;
+
+    match restored {
+        Some(restored) => AppResponse::ok(restored),
+        None => AppResponse::<()>::err("回收站中不存在该记录"),
+    }
+}
+
+/// 彻底删除回收站中的记录, 仍被引用时拒绝删除
+pub async fn purge<D: DB>(Extension(store): Extension<Store>, Path(id): Path<String>) -> AppResult {
+    let purged = store
+        .transaction(D::scope(), |tx| trash::purge::<D>(tx, &id))
+        .await?;
+
+    match purged {
+        Some(purged) => AppResponse::ok(purged),
+        None => AppResponse::<()>::err("回收站中不存在该记录"),
+    }
+}
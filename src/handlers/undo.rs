@@ -0,0 +1,30 @@
+use axum::Extension;
+
+use crate::{
+    repo::{
+        audit::current_actor,
+        store::Store,
+        undo::{self, Direction, skip_tracking},
+    },
+    result::response::{AppResponse, AppResult},
+};
+
+/// 撤销当前操作人最近一次请求的全部变更
+pub async fn undo(Extension(store): Extension<Store>) -> AppResult {
+    skip_tracking();
+
+    match undo::apply(&store, &current_actor(), Direction::Undo).await? {
+        Some(step) => AppResponse::ok(step),
+        None => AppResponse::<()>::err("没有可撤销的操作"),
+    }
+}
+
+/// 重做当前操作人最近一次撤销的变更
+pub async fn redo(Extension(store): Extension<Store>) -> AppResult {
+    skip_tracking();
+
+    match undo::apply(&store, &current_actor(), Direction::Redo).await? {
+        Some(step) => AppResponse::ok(step),
+        None => AppResponse::<()>::err("没有可重做的操作"),
+    }
+}
@@ -1,17 +1,34 @@
 use axum::{
     Extension, Router,
-    middleware::from_fn,
+    middleware::{from_fn, from_fn_with_state},
     routing::{get, post},
 };
+use config::{AppConfig, Command, StorageKind};
 use entity::{
     attendance::EntityAttendance, employee::EntityEmployee, employee_change::EntityEmployeeChange,
-    project::EntityProject, special_date::EntitySpecialDate,
+    leave_policy::EntityLeavePolicy, project::EntityProject, special_date::EntitySpecialDate,
+};
+use handlers::{
+    attendance, audit, backup, calendar, holiday, leave_policy, reload, resource::routes, undo,
+};
+use log::error;
+use repo::{
+    audit::audit_actor,
+    backup::BackupManager,
+    crypto,
+    holiday::HolidayImporter,
+    key_rotation, migration,
+    persist::{durability_ack, spawn_persister},
+    reload::spawn_hot_reload,
+    store::Store,
+    trash::spawn_auto_purge,
+    undo::track_undo,
 };
-use handlers::{attendance, employee, employee_change, project, special_date};
-use repo::db::DB;
 use result::response::text_response_process;
+use std::time::Duration;
 use tower::ServiceBuilder;
 
+mod config;
 mod entity;
 
 mod handlers;
@@ -21,56 +38,247 @@ mod serde_custom;
 
 #[tokio::main]
 async fn main() {
-    let project = EntityProject::new();
-    let employee = EntityEmployee::new();
-    let employee_change = EntityEmployeeChange::new();
-    let attendance = EntityAttendance::new();
-    let special_date = EntitySpecialDate::new();
-
-    // build our application with a single route
-    let app = Router::new()
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let (config, command) = exit_on_err("加载配置失败", AppConfig::load());
+
+    exit_on_err("初始化数据目录失败", config.prepare_data_dir());
+
+    if let Command::RotateKey { new_key_file } = &command {
+        exit_on_err(
+            "更换密钥失败",
+            key_rotation::run(&config, new_key_file).await,
+        );
+        return;
+    }
+
+    let keyring = exit_on_err("加载密钥失败", crypto::init(&config).await);
+
+    if let Command::Migrate { dry_run } = command {
+        exit_on_err("迁移失败", migration::run(&config, &keyring, dry_run));
+        return;
+    }
+
+    let store = exit_on_err("加载数据失败", Store::load(&config, &keyring));
+
+    spawn_persister(store.clone());
+
+    if config.trash.purge_after_days > 0 {
+        spawn_auto_purge(store.clone(), config.trash.purge_after_days);
+    }
+
+    if config.hot_reload.interval_ms > 0 && config.storage == StorageKind::Json {
+        spawn_hot_reload(
+            store.clone(),
+            Duration::from_millis(config.hot_reload.interval_ms),
+        );
+    }
+
+    let backup = BackupManager::new(&config, store.clone());
+    let holiday = HolidayImporter::new(&config, store.clone());
+
+    if config.backup.interval_minutes > 0 {
+        backup
+            .clone()
+            .spawn_schedule(Duration::from_secs(config.backup.interval_minutes * 60));
+    }
+
+    let app = app(&store, backup, holiday);
+
+    let listener = exit_on_err(
+        "监听地址失败",
+        tokio::net::TcpListener::bind(&config.bind_addr)
+            .await
+            .map_err(anyhow::Error::from),
+    );
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .unwrap();
+
+    // 退出前写入尚未落盘的变更
+    exit_on_err("写入数据失败", store.flush_async().await);
+}
+
+/// 全部接口路由及中间件
+fn app(store: &Store, backup: BackupManager, holiday: HolidayImporter) -> Router {
+    Router::new()
         .route("/", get(|| async { "Hello, World!" }))
-        .route("/project/create", post(project::create))
-        .route("/project/list", get(project::list))
-        .route("/project/get/{id}", get(project::get))
-        .route("/project/delete/{id}", post(project::delete))
-        .route("/project/update/{id}", post(project::update))
-        .route("/employee/create", post(employee::create))
-        .route("/employee/list", get(employee::list))
-        .route("/employee/get/{id}", get(employee::get))
-        .route("/employee/delete/{id}", post(employee::delete))
-        .route("/employee/update/{id}", post(employee::update))
-        .route("/employee_change/create", post(employee_change::create))
-        .route("/employee_change/list", get(employee_change::list))
-        .route("/employee_change/get/{id}", get(employee_change::get))
+        .merge(routes::<EntityProject>())
+        .merge(routes::<EntityEmployee>())
+        .merge(routes::<EntityEmployeeChange>())
+        .merge(routes::<EntityAttendance>())
         .route(
-            "/employee_change/delete/{id}",
-            post(employee_change::delete),
+            "/attendance/balance/{employee_id}",
+            get(attendance::balance),
         )
+        .merge(routes::<EntitySpecialDate>())
+        .merge(routes::<EntityLeavePolicy>())
         .route(
-            "/employee_change/update/{id}",
-            post(employee_change::update),
+            "/leave_policy/balance/{employee_id}",
+            get(leave_policy::balance),
         )
-        .route("/attendance/create", post(attendance::create))
-        .route("/attendance/list", get(attendance::list))
-        .route("/attendance/get/{id}", get(attendance::get))
-        .route("/attendance/delete/{id}", post(attendance::delete))
-        .route("/attendance/update/{id}", post(attendance::update))
-        .route("/special_date/create", post(special_date::create))
-        .route("/special_date/list", get(special_date::list))
-        .route("/special_date/get/{id}", get(special_date::get))
-        .route("/special_date/delete/{id}", post(special_date::delete))
-        .route("/special_date/update/{id}", post(special_date::update))
+        .route("/calendar/day", get(calendar::day))
+        .route("/calendar/days", get(calendar::days))
+        .route("/calendar/workdays", get(calendar::workdays))
+        .route("/calendar/add", get(calendar::add))
+        .route("/audit/list", get(audit::list))
+        .route("/undo", post(undo::undo))
+        .route("/redo", post(undo::redo))
+        .route("/admin/backup/create", post(backup::create))
+        .route("/admin/backup/list", get(backup::list))
+        .route("/admin/backup/restore/{name}", post(backup::restore))
+        .route("/admin/holiday/files", get(holiday::files))
+        .route("/admin/holiday/import", post(holiday::import))
+        .route("/admin/reload", post(reload::reload))
         .layer(
             ServiceBuilder::new()
                 .layer(from_fn(text_response_process))
-                .layer(Extension(project))
-                .layer(Extension(employee))
-                .layer(Extension(employee_change))
-                .layer(Extension(attendance))
-                .layer(Extension(special_date)),
-        );
+                .layer(from_fn_with_state(store.persister.clone(), durability_ack))
+                .layer(from_fn(audit_actor))
+                .layer(from_fn_with_state(store.clone(), track_undo))
+                .layer(Extension(store.project.clone()))
+                .layer(Extension(store.employee.clone()))
+                .layer(Extension(store.employee_change.clone()))
+                .layer(Extension(store.attendance.clone()))
+                .layer(Extension(store.special_date.clone()))
+                .layer(Extension(store.leave_policy.clone()))
+                .layer(Extension(store.clone()))
+                .layer(Extension(backup))
+                .layer(Extension(holiday)),
+        )
+}
+
+/// 收到 Ctrl+C 或 SIGTERM 时停止服务
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.ok();
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// 启动阶段的错误直接输出诊断信息并退出, 而不是 panic
+fn exit_on_err<T>(context: &str, res: anyhow::Result<T>) -> T {
+    match res {
+        Ok(val) => val,
+        Err(err) => {
+            error!("{}: {:#}", context, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use axum::{
+        body::Body,
+        http::{HeaderMap, Request},
+    };
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    use crate::repo::crypto::Keyring;
+
+    use super::*;
+
+    /// 使用临时数据目录的完整服务, 请求经过与正式服务相同的中间件
+    pub struct TestApp {
+        pub config: AppConfig,
+        pub store: Store,
+        app: Router,
+        _dir: TempDir,
+    }
+
+    impl TestApp {
+        pub async fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let config = AppConfig::with_data_dir(dir.path());
+            let store = Store::load(&config, &Keyring::default()).unwrap();
+
+            spawn_persister(store.clone());
+
+            let backup = BackupManager::new(&config, store.clone());
+            let holiday = HolidayImporter::new(&config, store.clone());
+
+            TestApp {
+                app: app(&store, backup, holiday),
+                config,
+                store,
+                _dir: dir,
+            }
+        }
+
+        /// 发送请求, 返回响应头和响应体, headers 为额外的请求头
+        pub async fn send(
+            &self,
+            method: &str,
+            uri: &str,
+            headers: &[(&str, &str)],
+            body: Option<Value>,
+        ) -> (HeaderMap, Value) {
+            let mut request = Request::builder().method(method).uri(uri);
+
+            for (k, v) in headers {
+                request = request.header(*k, *v);
+            }
+
+            let request = match body {
+                Some(body) => request
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string())),
+                None => request.body(Body::empty()),
+            }
+            .unwrap();
+
+            let response = self.app.clone().oneshot(request).await.unwrap();
+            let headers = response.headers().clone();
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+
+            (headers, serde_json::from_slice(&bytes).unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn created_record_can_be_read_back() {
+        let app = TestApp::new().await;
+
+        let (_, res) = app
+            .send(
+                "POST",
+                "/employee/create",
+                &[],
+                Some(serde_json::json!({
+                    "name": "人员",
+                    "status": "Working",
+                    "position": "dev",
+                })),
+            )
+            .await;
+        assert_eq!(res["code"], "Ok", "{}", res);
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+        let id = res["data"]["id"].as_str().unwrap();
+        let (_, res) = app
+            .send("GET", &format!("/employee/get/{}", id), &[], None)
+            .await;
+        assert_eq!(res["data"]["name"], "人员");
+    }
 }
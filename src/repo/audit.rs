@@ -0,0 +1,487 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+};
+
+use anyhow::{Context, Result};
+use axum::{extract::Request, middleware::Next, response::Response};
+use chrono::Local;
+use log::warn;
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::entity::audit::{AuditOp, DTOAuditParam, EntityAudit, FieldChange};
+
+use super::{crypto::Keyring, db::write_atomic};
+
+/// 审计日志文件名, 位于数据目录下
+pub const AUDIT_FILE: &str = "audit.jsonl";
+
+/// 请求头, 标识操作人
+pub const ACTOR_HEADER: &str = "x-actor";
+
+/// 请求未指定操作人时使用
+pub const ANONYMOUS_ACTOR: &str = "anonymous";
+
+/// 后台任务等非请求触发的变更使用的操作人
+pub const SYSTEM_ACTOR: &str = "system";
+
+/// 当前请求的信息
+struct RequestContext {
+    /// 请求id, 用于关联同一请求中的多条变更
+    id: String,
+    /// 操作人
+    actor: String,
+}
+
+tokio::task_local! {
+    static REQUEST: RequestContext;
+}
+
+/// 当前操作人, 不在请求中时为 system
+pub fn current_actor() -> String {
+    REQUEST
+        .try_with(|ctx| ctx.actor.clone())
+        .unwrap_or_else(|_| SYSTEM_ACTOR.to_string())
+}
+
+/// 当前请求id, 不在请求中时为 None
+pub fn current_request_id() -> Option<String> {
+    REQUEST.try_with(|ctx| ctx.id.clone()).ok()
+}
+
+/// 从请求头 x-actor 读取操作人, 在处理请求期间可以通过 [current_actor] 获取
+pub async fn audit_actor(request: Request, next: Next) -> Response {
+    let actor = request
+        .headers()
+        .get(ACTOR_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .unwrap_or(ANONYMOUS_ACTOR)
+        .to_string();
+
+    let ctx = RequestContext {
+        id: Uuid::new_v4().to_string(),
+        actor,
+    };
+
+    REQUEST.scope(ctx, next.run(request)).await
+}
+
+/// 内存中保留的最近写入文件的记录数, 用于查找刚完成的请求产生的变更, 更早的记录从文件读取
+const TAIL_LEN: usize = 1000;
+
+/// 审计记录中的版本号字段, 由集合在写入时维护, 不作为字段变化记录
+const VERSION_FIELD: &str = "version";
+
+/// 审计日志, 记录每次变更的操作人及字段变化, 只追加不修改
+///
+/// 新记录先保存在内存中, 随数据一起由后台任务追加写入 audit.jsonl; 写入后内存中只保留最近的一部分, 查询时从文件读取
+pub struct AuditLog {
+    path: PathBuf,
+    keyring: Keyring,
+    /// 写入文件时持有写锁, 读取文件时持有读锁, 保证读到的文件内容与内存中尚未写入的记录不重叠也不遗漏
+    file: RwLock<()>,
+    state: Mutex<AuditState>,
+}
+
+struct AuditState {
+    /// 尚未写入文件的记录及其序号, 旧记录在前
+    pending: Vec<(u64, EntityAudit)>,
+    /// 最近写入文件的记录及其序号, 最多 [TAIL_LEN] 条, 旧记录在前
+    tail: VecDeque<(u64, EntityAudit)>,
+    /// 序号不小于它的记录都在内存中
+    retained: u64,
+    /// 下一条记录的序号, 序号只增不减, 丢弃记录后也不会复用; 重启后从 0 开始, 不写入文件
+    next: u64,
+}
+
+impl AuditLog {
+    /// 打开审计日志, 已有的记录在查询时从文件读取
+    pub fn load(path: PathBuf, keyring: Keyring) -> Result<Self> {
+        Ok(AuditLog {
+            path,
+            keyring,
+            file: RwLock::new(()),
+            state: Mutex::new(AuditState {
+                pending: Vec::new(),
+                tail: VecDeque::new(),
+                retained: 0,
+                next: 0,
+            }),
+        })
+    }
+
+    /// 记录一次变更, before / after 为变更前后的记录, 不存在时为 None, 返回记录的序号
+    pub fn record<T: Serialize>(
+        &self,
+        entity: &str,
+        record_id: &str,
+        op: AuditOp,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) -> u64 {
+        let to_value = |record: Option<&T>| {
+            record
+                .and_then(|r| serde_json::to_value(r).ok())
+                .unwrap_or(Value::Null)
+        };
+
+        let entry = EntityAudit {
+            id: Uuid::new_v4().to_string(),
+            time: Local::now().naive_local(),
+            actor: current_actor(),
+            request_id: current_request_id().unwrap_or_default(),
+            entity: entity.to_string(),
+            record_id: record_id.to_string(),
+            op,
+            changes: diff(to_value(before), to_value(after)),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let seq = state.next;
+        state.next += 1;
+        state.pending.push((seq, entry));
+
+        seq
+    }
+
+    /// 下一条记录的序号, 之后记录的序号都不小于它
+    pub fn next_seq(&self) -> u64 {
+        self.state.lock().unwrap().next
+    }
+
+    /// 丢弃指定序号的记录, 用于事务回滚; 事务期间不会写入文件, 已写入的记录不受影响
+    pub fn discard(&self, seqs: &[u64]) {
+        if seqs.is_empty() {
+            return;
+        }
+
+        self.state
+            .lock()
+            .unwrap()
+            .pending
+            .retain(|(seq, _)| !seqs.contains(seq));
+    }
+
+    /// 将序号小于 end 的记录中尚未写入的部分追加到文件, 失败时保留在内存中, 下次重试
+    pub fn flush(&self, end: u64) -> Result<()> {
+        let _file = self.file.write().unwrap();
+
+        let pending: Vec<EntityAudit> = {
+            let state = self.state.lock().unwrap();
+            state
+                .pending
+                .iter()
+                .take_while(|(seq, _)| *seq < end)
+                .map(|(_, entry)| entry.clone())
+                .collect()
+        };
+
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = String::new();
+        for entry in &pending {
+            lines.push_str(&self.keyring.seal_line(&serde_json::to_string(entry)?)?);
+            lines.push('\n');
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("打开审计日志失败: {}", self.path.display()))?;
+
+        file.write_all(lines.as_bytes())?;
+        file.sync_all()?;
+
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        // 写入期间被丢弃的记录不在 pending 中, 写入的记录仍按顺序位于开头
+        let written = state.pending.partition_point(|(seq, _)| *seq < end);
+        state.tail.extend(state.pending.drain(..written));
+
+        while state.tail.len() > TAIL_LEN {
+            if let Some((seq, _)) = state.tail.pop_front() {
+                state.retained = seq + 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 序号不小于 start 且属于指定请求的记录, 按变更顺序排列
+    ///
+    /// 这些记录已不全在内存中时从文件查找, 请求id唯一, 文件中属于该请求的记录序号都不小于 start
+    pub fn request_entries(&self, start: u64, request_id: &str) -> Result<Vec<EntityAudit>> {
+        let matches = |p: &EntityAudit| p.request_id == request_id;
+
+        {
+            let state = self.state.lock().unwrap();
+
+            if start >= state.retained {
+                return Ok(state
+                    .tail
+                    .iter()
+                    .chain(&state.pending)
+                    .filter(|(seq, entry)| *seq >= start && matches(entry))
+                    .map(|(_, entry)| entry.clone())
+                    .collect());
+            }
+        }
+
+        let mut entries = Vec::new();
+        self.scan(|entry| {
+            if matches(&entry) {
+                entries.push(entry);
+            }
+        })?;
+
+        Ok(entries)
+    }
+
+    /// 使用当前密钥重写文件中的全部记录, 用于更换密钥, 读取时新旧密钥都可以使用
+    pub fn rewrite(&self) -> Result<()> {
+        let _file = self.file.write().unwrap();
+
+        if !self.path.exists() {
+            return Ok(());
+        }
+
+        let mut lines = String::new();
+        for entry in read_file(&self.path, &self.keyring)? {
+            lines.push_str(&self.keyring.seal_line(&serde_json::to_string(&entry)?)?);
+            lines.push('\n');
+        }
+
+        write_atomic(&self.path, lines.as_bytes())
+            .with_context(|| format!("重写审计日志失败: {}", self.path.display()))
+    }
+
+    /// 查询满足条件的审计记录, 新记录在前, 同时返回满足条件的记录总数
+    ///
+    /// limit 不为空时只返回最新的 limit 条, 其余只计数; 从文件逐行读取, 内存中只保留需要返回的记录
+    pub fn query(
+        &self,
+        param: &DTOAuditParam,
+        filter: impl Fn(&EntityAudit) -> bool,
+        limit: Option<usize>,
+    ) -> Result<(Vec<EntityAudit>, usize)> {
+        let mut rows = VecDeque::new();
+        let mut total = 0;
+
+        self.scan(|entry| {
+            if !matches(param, &entry) || !filter(&entry) {
+                return;
+            }
+
+            total += 1;
+            rows.push_back(entry);
+
+            if limit.is_some_and(|limit| rows.len() > limit) {
+                rows.pop_front();
+            }
+        })?;
+
+        Ok((rows.into_iter().rev().collect(), total))
+    }
+
+    /// 按变更顺序遍历全部记录, 先读取文件, 再遍历尚未写入的记录
+    fn scan(&self, mut f: impl FnMut(EntityAudit)) -> Result<()> {
+        let _file = self.file.read().unwrap();
+
+        let pending: Vec<EntityAudit> = self
+            .state
+            .lock()
+            .unwrap()
+            .pending
+            .iter()
+            .map(|(_, entry)| entry.clone())
+            .collect();
+
+        if self.path.exists() {
+            for entry in read_file(&self.path, &self.keyring)? {
+                f(entry);
+            }
+        }
+
+        pending.into_iter().for_each(f);
+
+        Ok(())
+    }
+}
+
+/// 逐行读取审计日志文件, 无法解析的行会被跳过
+fn read_file(path: &Path, keyring: &Keyring) -> Result<impl Iterator<Item = EntityAudit>> {
+    let file = File::open(path).with_context(|| format!("打开审计日志失败: {}", path.display()))?;
+    let path = path.to_path_buf();
+    let keyring = keyring.clone();
+
+    Ok(BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter_map(move |(ind, line)| {
+            let line = line.ok()?;
+
+            if line.trim().is_empty() {
+                return None;
+            }
+
+            match keyring
+                .open_line(&line)
+                .and_then(|line| Ok(serde_json::from_str(&line)?))
+            {
+                Ok(entry) => Some(entry),
+                Err(err) => {
+                    warn!(
+                        "审计记录无法解析, 已跳过: {} 第 {} 行: {}",
+                        path.display(),
+                        ind + 1,
+                        err
+                    );
+                    None
+                }
+            }
+        }))
+}
+
+/// 记录是否满足查询参数
+fn matches(param: &DTOAuditParam, p: &EntityAudit) -> bool {
+    let mut pass = true;
+
+    if let Some(cur) = &param.entity
+        && p.entity != *cur
+    {
+        pass = false;
+    }
+
+    if let Some(cur) = &param.record_id
+        && p.record_id != *cur
+    {
+        pass = false;
+    }
+
+    if let Some(cur) = &param.field
+        && !p.changes.contains_key(cur)
+    {
+        pass = false;
+    }
+
+    if let Some(cur) = &param.actor
+        && p.actor != *cur
+    {
+        pass = false;
+    }
+
+    if let Some(cur) = &param.op
+        && p.op != *cur
+    {
+        pass = false;
+    }
+
+    if let Some(cur) = &param.from
+        && p.time.date() < *cur
+    {
+        pass = false;
+    }
+
+    if let Some(cur) = &param.to
+        && p.time.date() > *cur
+    {
+        pass = false;
+    }
+
+    pass
+}
+
+/// 比较变更前后的记录, 返回值不同的字段, 不包括版本号
+fn diff(before: Value, after: Value) -> BTreeMap<String, FieldChange> {
+    let empty = serde_json::Map::new();
+    let before_map = before.as_object().unwrap_or(&empty);
+    let after_map = after.as_object().unwrap_or(&empty);
+
+    before_map
+        .keys()
+        .chain(after_map.keys())
+        .filter(|k| *k != VERSION_FIELD && before_map.get(*k) != after_map.get(*k))
+        .map(|k| {
+            (
+                k.clone(),
+                FieldChange {
+                    before: before_map.get(k).cloned().unwrap_or(Value::Null),
+                    after: after_map.get(k).cloned().unwrap_or(Value::Null),
+                },
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn param() -> DTOAuditParam {
+        serde_json::from_value(json!({})).unwrap()
+    }
+
+    #[test]
+    fn diff_skips_version() {
+        let changes = diff(
+            json!({ "id": "p1", "name": "a", "version": 1 }),
+            json!({ "id": "p1", "name": "b", "version": 2 }),
+        );
+
+        assert_eq!(changes.keys().collect::<Vec<_>>(), ["name"]);
+    }
+
+    #[test]
+    fn flushed_entries_are_read_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit = AuditLog::load(dir.path().join(AUDIT_FILE), Keyring::default()).unwrap();
+
+        let before = json!({ "n": 0 });
+        for n in 0..TAIL_LEN + 10 {
+            let after = json!({ "n": n + 1 });
+            audit.record(
+                "project",
+                &n.to_string(),
+                AuditOp::Update,
+                Some(&before),
+                Some(&after),
+            );
+        }
+        audit.flush(audit.next_seq()).unwrap();
+        audit.record("project", "last", AuditOp::Create, None, Some(&before));
+
+        {
+            let state = audit.state.lock().unwrap();
+            assert_eq!(state.pending.len(), 1);
+            assert_eq!(state.tail.len(), TAIL_LEN);
+            assert_eq!(state.retained, 10);
+        }
+
+        let (rows, total) = audit.query(&param(), |_| true, Some(3)).unwrap();
+        assert_eq!(total, TAIL_LEN + 11);
+        let ids: Vec<&str> = rows.iter().map(|p| p.record_id.as_str()).collect();
+        assert_eq!(ids, ["last", "1009", "1008"]);
+
+        let (rows, total) = audit.query(&param(), |p| p.record_id == "0", None).unwrap();
+        assert_eq!((rows.len(), total), (1, 1));
+
+        // 请求之外的记录 request_id 为空, 起始序号已不在内存中时从文件查找
+        assert_eq!(audit.request_entries(0, "").unwrap().len(), TAIL_LEN + 11);
+        assert_eq!(
+            audit.request_entries(TAIL_LEN as u64, "").unwrap().len(),
+            11
+        );
+    }
+}
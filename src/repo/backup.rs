@@ -0,0 +1,490 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use chrono::Local;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::config::AppConfig;
+
+use super::{
+    crypto::Keyring,
+    db::write_atomic,
+    migration::{Envelope, latest_version, migrate_record},
+    recovery::read_versioned,
+    store::Store,
+    transaction::Scope,
+};
+
+/// 备份清单文件名
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// 一份备份的信息, 保存在备份目录的 manifest.json 中
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackupManifest {
+    /// 备份名, 即备份目录名
+    pub name: String,
+    /// 创建时间
+    pub created_at: String,
+    /// 创建原因: manual 手动 / scheduled 定时 / pre_restore 恢复前自动创建
+    pub reason: String,
+    /// 各集合的记录数
+    pub counts: BTreeMap<String, usize>,
+}
+
+/// 备份管理, 对全部集合创建一致的快照, 并支持整体恢复
+///
+/// 每份备份是备份目录下的一个子目录, 包含各集合的 json 文件 (与 json 后端格式一致) 及 manifest.json
+#[derive(Clone)]
+pub struct BackupManager {
+    dir: PathBuf,
+    keep: usize,
+    store: Store,
+}
+
+impl BackupManager {
+    pub fn new(config: &AppConfig, store: Store) -> Self {
+        BackupManager {
+            dir: config.backup_dir(),
+            keep: config.backup.keep,
+            store,
+        }
+    }
+
+    /// 创建备份, 导出期间持有全部集合的读锁, 保证各集合处于同一时刻
+    pub async fn create(&self, reason: &str) -> Result<BackupManifest> {
+        let files = {
+            let all = self.store.read_all().await;
+
+            vec![
+                export("project", &all.project)?,
+                export("employee", &all.employee)?,
+                export("employee_change", &all.employee_change)?,
+                export("attendance", &all.attendance)?,
+                export("special_date", &all.special_date)?,
+                export("leave_policy", &all.leave_policy)?,
+            ]
+        };
+
+        let now = Local::now();
+
+        let mut manifest = BackupManifest {
+            name: now.format("%Y%m%d-%H%M%S-%3f").to_string(),
+            created_at: now.format("%Y-%m-%d %H:%M:%S").to_string(),
+            reason: reason.to_string(),
+            counts: files
+                .iter()
+                .map(|(collection, _, count)| (collection.to_string(), *count))
+                .collect(),
+        };
+
+        // 文件写入及 fsync 会阻塞, 放到单独的线程中
+        let dir = self.dir.clone();
+        let keep = self.keep;
+        let keyring = self.store.keyring.clone();
+
+        tokio::task::spawn_blocking(move || {
+            write_backup(&dir, &mut manifest, &files, &keyring)?;
+            prune(&dir, keep, &manifest.name)?;
+            Ok(manifest)
+        })
+        .await?
+    }
+
+    /// 全部备份, 新备份在前
+    pub async fn list(&self) -> Result<Vec<BackupManifest>> {
+        let dir = self.dir.clone();
+
+        tokio::task::spawn_blocking(move || list_dir(&dir)).await?
+    }
+
+    /// 从指定备份恢复全部集合, 备份不存在时返回 None
+    ///
+    /// 恢复前会先完整读取并校验备份, 然后自动创建一份 pre_restore 备份, 返回该备份的信息, 便于撤销本次恢复
+    pub async fn restore(&self, name: &str) -> Result<Option<BackupManifest>> {
+        let dir = self.dir.clone();
+        let name = name.to_string();
+        let keyring = self.store.keyring.clone();
+
+        let snapshot = tokio::task::spawn_blocking(move || -> Result<_> {
+            // 只接受已存在的备份名, 避免拼接任意路径
+            if !list_dir(&dir)?.iter().any(|m| m.name == name) {
+                return Ok(None);
+            }
+
+            let dir = dir.join(name);
+
+            Ok(Some((
+                read_snapshot(&dir, "project", &keyring)?,
+                read_snapshot(&dir, "employee", &keyring)?,
+                read_snapshot(&dir, "employee_change", &keyring)?,
+                read_snapshot(&dir, "attendance", &keyring)?,
+                read_snapshot(&dir, "special_date", &keyring)?,
+                read_snapshot(&dir, "leave_policy", &keyring)?,
+            )))
+        })
+        .await??;
+
+        let Some((project, employee, employee_change, attendance, special_date, leave_policy)) =
+            snapshot
+        else {
+            return Ok(None);
+        };
+
+        let pre_restore = self.create("pre_restore").await?;
+
+        self.store
+            .transaction(Scope::ALL, |tx| {
+                tx.project.replace_all(project)?;
+                tx.employee.replace_all(employee)?;
+                tx.employee_change.replace_all(employee_change)?;
+                tx.attendance.replace_all(attendance)?;
+                tx.special_date.replace_all(special_date)?;
+                tx.leave_policy.replace_all(leave_policy)
+            })
+            .await?;
+
+        // 立即写入, 以便将写入错误返回给调用方
+        self.store.flush_async().await?;
+
+        Ok(Some(pre_restore))
+    }
+
+    /// 启动定时备份任务
+    pub fn spawn_schedule(self, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // 第一次 tick 立即返回, 跳过它以免启动时就备份
+            ticker.tick().await;
+
+            loop {
+                ticker.tick().await;
+
+                match self.create("scheduled").await {
+                    Ok(manifest) => info!("定时备份完成: {}", manifest.name),
+                    Err(err) => error!("定时备份失败: {:#}", err),
+                }
+            }
+        });
+    }
+}
+
+/// 将备份写入临时目录, 全部写完后再重命名, 避免留下不完整的备份
+///
+/// 同名备份已存在时 (如同一毫秒内创建了多份备份) 在 manifest.name 后追加序号, 保证不会覆盖已有的备份
+fn write_backup(
+    dir: &Path,
+    manifest: &mut BackupManifest,
+    files: &[(&'static str, String, usize)],
+    keyring: &Keyring,
+) -> Result<()> {
+    let tmp_dir = reserve_name(dir, manifest)?;
+
+    for (collection, content, _) in files {
+        write_atomic(
+            &tmp_dir.join(format!("{}.json", collection)),
+            &keyring.seal(content.as_bytes())?,
+        )?;
+    }
+
+    write_atomic(
+        &tmp_dir.join(MANIFEST_FILE),
+        serde_json::to_string_pretty(manifest)?.as_bytes(),
+    )?;
+
+    fs::rename(&tmp_dir, dir.join(&manifest.name))?;
+
+    Ok(())
+}
+
+/// 为备份选择未被占用的名称, 创建对应的临时目录并返回
+///
+/// 临时目录通过 create_dir 创建, 已存在时失败, 同时创建的备份因此不会选中同一个名称
+fn reserve_name(dir: &Path, manifest: &mut BackupManifest) -> Result<PathBuf> {
+    fs::create_dir_all(dir).with_context(|| format!("创建备份目录失败: {}", dir.display()))?;
+
+    let base = manifest.name.clone();
+
+    for seq in 0..100 {
+        let name = match seq {
+            0 => base.clone(),
+            _ => format!("{}-{:02}", base, seq),
+        };
+
+        let tmp_dir = dir.join(format!(".{}.tmp", name));
+
+        match fs::create_dir(&tmp_dir) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("创建备份目录失败: {}", tmp_dir.display()));
+            }
+        }
+
+        // 持有临时目录期间其他备份不会使用该名称, 此时再确认没有已完成的同名备份
+        if dir.join(&name).exists() {
+            fs::remove_dir(&tmp_dir)?;
+            continue;
+        }
+
+        manifest.name = name;
+
+        return Ok(tmp_dir);
+    }
+
+    bail!("无法为备份 {} 选择未被占用的名称", base)
+}
+
+/// 读取备份目录下的全部备份, 新备份在前
+fn list_dir(dir: &Path) -> Result<Vec<BackupManifest>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut list: Vec<BackupManifest> = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path().join(MANIFEST_FILE);
+
+        if !path.exists() {
+            continue;
+        }
+
+        match fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| Ok(serde_json::from_str(&content)?))
+        {
+            Ok(manifest) => list.push(manifest),
+            Err(err) => warn!("读取备份清单失败: {}: {:#}", path.display(), err),
+        }
+    }
+
+    list.sort_by(|a, b| b.name.cmp(&a.name));
+
+    Ok(list)
+}
+
+/// 按保留数量删除最旧的备份
+///
+/// 刚创建的备份 created 总是保留并计入保留数量; 最近一份 pre_restore 备份用于撤销最近一次恢复, 同样不会被删除
+fn prune(dir: &Path, keep: usize, created: &str) -> Result<()> {
+    let list = list_dir(dir)?;
+
+    let pre_restore = list
+        .iter()
+        .find(|m| m.reason == "pre_restore")
+        .map(|m| m.name.clone());
+
+    let protected = |m: &BackupManifest| m.name == created || Some(&m.name) == pre_restore.as_ref();
+
+    for manifest in list
+        .iter()
+        .filter(|m| !protected(m))
+        .skip(keep.saturating_sub(1))
+    {
+        fs::remove_dir_all(dir.join(&manifest.name))?;
+    }
+
+    Ok(())
+}
+
+/// 以最新版本的数据文件格式导出集合, 返回集合名, 内容及记录数
+fn export<T: Serialize>(
+    collection: &'static str,
+    rows: &[T],
+) -> Result<(&'static str, String, usize)> {
+    let content = serde_json::to_string_pretty(&Envelope {
+        version: latest_version(collection),
+        records: rows,
+    })?;
+
+    Ok((collection, content, rows.len()))
+}
+
+/// 读取备份中的集合, 旧版本数据会迁移到最新版本, 任何记录有误都视为失败
+fn read_snapshot<T: DeserializeOwned>(
+    dir: &Path,
+    collection: &str,
+    keyring: &Keyring,
+) -> Result<Vec<T>> {
+    let path = dir.join(format!("{}.json", collection));
+
+    // 早于该集合引入的备份中没有对应的文件
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let (version, records) = read_versioned(&path, keyring)?;
+
+    records
+        .into_iter()
+        .map(|mut record| {
+            migrate_record(collection, version, &mut record)?;
+            Ok(serde_json::from_value(record)?)
+        })
+        .collect::<Result<_>>()
+        .with_context(|| format!("备份数据有误: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        entity::special_date::EntitySpecialDate,
+        repo::{db::DB, tests::special_date, transaction::Transaction},
+    };
+
+    use super::*;
+
+    fn add(dir: &Path, name: &str, reason: &str) {
+        let mut manifest = BackupManifest {
+            name: name.to_string(),
+            created_at: String::new(),
+            reason: reason.to_string(),
+            counts: BTreeMap::new(),
+        };
+
+        write_backup(dir, &mut manifest, &[], &Keyring::default()).unwrap();
+    }
+
+    async fn modify(store: &Store, f: impl FnOnce(&mut Transaction) -> Result<()>) {
+        store
+            .transaction(EntitySpecialDate::scope(), f)
+            .await
+            .unwrap();
+    }
+
+    async fn special_date_ids(store: &Store) -> Vec<String> {
+        let collection = store.special_date.read().await;
+        collection.iter().map(|p| p.id.clone()).collect()
+    }
+
+    fn manager(dir: &Path, keep: usize) -> (Store, BackupManager) {
+        let mut config = AppConfig::with_data_dir(dir);
+        config.backup.keep = keep;
+
+        let store = Store::load(&config, &Keyring::default()).unwrap();
+        let backup = BackupManager::new(&config, store.clone());
+
+        (store, backup)
+    }
+
+    fn names(dir: &Path) -> Vec<String> {
+        list_dir(dir).unwrap().into_iter().map(|m| m.name).collect()
+    }
+
+    #[test]
+    fn prune_keeps_created_and_latest_pre_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        add(dir, "1", "manual");
+        add(dir, "2", "pre_restore");
+        add(dir, "3", "pre_restore");
+        add(dir, "4", "scheduled");
+        add(dir, "5", "manual");
+
+        prune(dir, 2, "5").unwrap();
+        assert_eq!(names(dir), ["5", "4", "3"]);
+
+        prune(dir, 1, "4").unwrap();
+        assert_eq!(names(dir), ["4", "3"]);
+    }
+
+    #[test]
+    fn same_name_backups_get_unique_names_in_creation_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        add(dir, "20240101-120000-000", "scheduled");
+        add(dir, "20240101-120000-000", "manual");
+        add(dir, "20240101-120000-000", "pre_restore");
+        add(dir, "20240101-120000-001", "manual");
+
+        let list = list_dir(dir).unwrap();
+
+        assert_eq!(
+            list.iter().map(|m| m.name.as_str()).collect::<Vec<_>>(),
+            [
+                "20240101-120000-001",
+                "20240101-120000-000-02",
+                "20240101-120000-000-01",
+                "20240101-120000-000",
+            ]
+        );
+        assert_eq!(
+            list.iter().map(|m| m.reason.as_str()).collect::<Vec<_>>(),
+            ["manual", "pre_restore", "manual", "scheduled"]
+        );
+    }
+
+    #[tokio::test]
+    async fn create_prunes_the_oldest_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_store, backup) = manager(dir.path(), 2);
+
+        let mut created = Vec::new();
+        for _ in 0..4 {
+            created.push(backup.create("manual").await.unwrap().name);
+        }
+
+        let names: Vec<String> = backup
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| m.name)
+            .collect();
+
+        assert_eq!(names, [created[3].clone(), created[2].clone()]);
+    }
+
+    #[tokio::test]
+    async fn restore_replaces_data_after_a_pre_restore_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let (store, backup) = manager(dir.path(), 10);
+
+        modify(&store, |tx| {
+            tx.special_date.put(special_date("a", "2024-01-01"))?;
+            Ok(())
+        })
+        .await;
+
+        let snapshot = backup.create("manual").await.unwrap();
+        assert_eq!(snapshot.counts["special_date"], 1);
+
+        modify(&store, |tx| {
+            tx.special_date.put(special_date("b", "2024-01-02"))?;
+            tx.special_date.remove_by_id("a")?;
+            Ok(())
+        })
+        .await;
+
+        assert!(backup.restore("missing").await.unwrap().is_none());
+        assert!(backup.restore("../backups").await.unwrap().is_none());
+
+        let pre_restore = backup.restore(&snapshot.name).await.unwrap().unwrap();
+
+        assert_eq!(special_date_ids(&store).await, ["a"]);
+        assert_eq!(pre_restore.reason, "pre_restore");
+
+        let list = backup.list().await.unwrap();
+        assert_eq!(list[0].name, pre_restore.name);
+        assert_eq!(list[1].name, snapshot.name);
+
+        // 恢复前的备份可以撤销本次恢复, 恢复结果已写入磁盘
+        backup.restore(&pre_restore.name).await.unwrap().unwrap();
+        assert_eq!(special_date_ids(&store).await, ["b"]);
+
+        drop(store);
+        let store =
+            Store::load(&AppConfig::with_data_dir(dir.path()), &Keyring::default()).unwrap();
+        assert_eq!(special_date_ids(&store).await, ["b"]);
+    }
+}
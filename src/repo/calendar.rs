@@ -0,0 +1,365 @@
+use std::collections::HashSet;
+
+use anyhow::{Result, bail};
+use chrono::{Datelike, Days, NaiveDate, Weekday};
+
+use crate::entity::{
+    calendar::DayKind,
+    special_date::{EntitySpecialDate, SpecialDateType},
+};
+
+use super::{
+    db::Collection,
+    index::{IndexKey, date_key},
+};
+
+/// 推算工作日时允许的最大天数
+pub const MAX_ADD_DAYS: f64 = 3660.0;
+
+/// 推算工作日时连续多少天没有工作日视为日历有误
+const MAX_GAP_DAYS: usize = 366;
+
+/// 考勤记录和特殊日期最多覆盖的天数, 日期索引和工作日历为覆盖的每一天各保存一项
+pub const MAX_SPAN_DAYS: i64 = 366;
+
+/// 工作日历: 周一至周五为工作日, 特殊日期中计入假日的日期为休息日, 从假日排除的日期 (调休上班的周末) 为工作日
+pub struct WorkCalendar {
+    /// 计入假日的日期
+    holidays: HashSet<NaiveDate>,
+    /// 从假日排除的日期
+    workdays: HashSet<NaiveDate>,
+}
+
+impl WorkCalendar {
+    /// 由未删除的特殊日期构造
+    pub fn new(special_dates: &Collection<EntitySpecialDate>) -> Self {
+        Self::build(special_dates.list(|_| true))
+    }
+
+    /// 由覆盖 from 到 to 之间 (包含两端) 某一天的未删除特殊日期构造, 通过日期索引查找, 只用于查询该范围内的日期
+    pub fn between(
+        special_dates: &Collection<EntitySpecialDate>,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Self {
+        Self::build(special_dates.list_between(
+            IndexKey::Date,
+            Some(&date_key(from)),
+            Some(&date_key(to)),
+            |_| true,
+        ))
+    }
+
+    fn build<'a>(special_dates: impl IntoIterator<Item = &'a EntitySpecialDate>) -> Self {
+        let mut calendar = WorkCalendar {
+            holidays: HashSet::new(),
+            workdays: HashSet::new(),
+        };
+
+        for special in special_dates {
+            let dates = match special.date_type {
+                SpecialDateType::Include => &mut calendar.holidays,
+                SpecialDateType::Exclude => &mut calendar.workdays,
+            };
+
+            let end = special.end_time.unwrap_or(special.start_time);
+            dates.extend(special.start_time.iter_days().take_while(|d| *d <= end));
+        }
+
+        calendar
+    }
+
+    /// 日期类型, 从假日排除优先于计入假日
+    pub fn day_kind(&self, date: NaiveDate) -> DayKind {
+        let weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+
+        if self.workdays.contains(&date) {
+            if weekend {
+                DayKind::AdjustedWorkday
+            } else {
+                DayKind::Workday
+            }
+        } else if self.holidays.contains(&date) {
+            DayKind::Holiday
+        } else if weekend {
+            DayKind::Weekend
+        } else {
+            DayKind::Workday
+        }
+    }
+
+    /// 是否为工作日
+    pub fn is_workday(&self, date: NaiveDate) -> bool {
+        self.day_kind(date).is_workday()
+    }
+
+    /// from 到 to 之间 (包含两端) 的工作日天数
+    pub fn workdays_between(&self, from: NaiveDate, to: NaiveDate) -> usize {
+        from.iter_days()
+            .take_while(|d| *d <= to)
+            .filter(|d| self.is_workday(*d))
+            .count()
+    }
+
+    /// from 到 to 之间 (包含两端) 的工作日天数, 两端标记为半天且为工作日时计 0.5
+    pub fn workdays(&self, from: NaiveDate, from_half: bool, to: NaiveDate, to_half: bool) -> f64 {
+        span_days(from, from_half, to, to_half)
+            .into_iter()
+            .filter(|(d, _)| self.is_workday(*d))
+            .map(|(_, days)| days)
+            .sum()
+    }
+
+    /// 从 date 之后 (不含当天) 数 days 个工作日, days 为负数时向前数
+    ///
+    /// 返回最后一个工作日, 以及该日是否只用到半天; days 为 0 时返回 date 本身
+    pub fn add_workdays(&self, date: NaiveDate, days: f64) -> Result<(NaiveDate, bool)> {
+        if !days.is_finite() || days.abs() > MAX_ADD_DAYS {
+            bail!("工作日天数应在 -{0} 到 {0} 之间", MAX_ADD_DAYS);
+        }
+
+        let halves = days.abs() * 2.0;
+        if halves.fract() != 0.0 {
+            bail!("工作日天数应为 0.5 的整数倍");
+        }
+
+        let halves = halves as usize;
+        let count = halves.div_ceil(2);
+        let half = halves % 2 == 1;
+
+        let step = |d: NaiveDate| {
+            if days < 0.0 {
+                d.checked_sub_days(Days::new(1))
+            } else {
+                d.checked_add_days(Days::new(1))
+            }
+        };
+
+        let mut cur = date;
+        for _ in 0..count {
+            let mut gap = 0;
+            loop {
+                cur = match step(cur) {
+                    Some(next) => next,
+                    None => bail!("日期超出范围"),
+                };
+
+                if self.is_workday(cur) {
+                    break;
+                }
+
+                gap += 1;
+                if gap >= MAX_GAP_DAYS {
+                    bail!("连续 {} 天没有工作日, 请检查特殊日期", MAX_GAP_DAYS);
+                }
+            }
+        }
+
+        Ok((cur, half))
+    }
+}
+
+/// 校验日期范围: 结束日期不早于开始日期, 且覆盖的天数不超过 [MAX_SPAN_DAYS], end 为空时只有 start 一天
+pub fn check_span(start: NaiveDate, end: Option<NaiveDate>) -> Result<()> {
+    let Some(end) = end else {
+        return Ok(());
+    };
+
+    if end < start {
+        bail!("结束日期 {} 早于开始日期 {}", end, start);
+    }
+
+    if (end - start).num_days() >= MAX_SPAN_DAYS {
+        bail!("日期范围不能超过 {} 天: {} ~ {}", MAX_SPAN_DAYS, start, end);
+    }
+
+    Ok(())
+}
+
+/// start 到 end 之间 (包含两端) 的每一天及天数, 两端标记为半天时计 0.5, 同一天两端都是半天时也只计 0.5
+pub fn span_days(
+    start: NaiveDate,
+    start_half: bool,
+    end: NaiveDate,
+    end_half: bool,
+) -> Vec<(NaiveDate, f64)> {
+    start
+        .iter_days()
+        .take_while(|d| *d <= end)
+        .map(|d| {
+            let half = (d == start && start_half) || (d == end && end_half);
+            (d, if half { 0.5 } else { 1.0 })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::repo::tests::date;
+
+    use super::*;
+
+    fn special(start: &str, end: Option<&str>, date_type: &str) -> EntitySpecialDate {
+        serde_json::from_value(json!({
+            "id": format!("{}-{}", start, date_type),
+            "start_time": start,
+            "end_time": end,
+            "date_type": date_type,
+        }))
+        .unwrap()
+    }
+
+    /// 2025 年国庆: 10-01 (周三) 至 10-08 放假, 09-28 (周日) 和 10-11 (周六) 补班;
+    /// 另将周五 09-26 同时标记为假日和补班, 补班优先
+    fn national_day() -> WorkCalendar {
+        WorkCalendar::build(&[
+            special("2025-10-01", Some("2025-10-08"), "Include"),
+            special("2025-09-28", None, "Exclude"),
+            special("2025-10-11", None, "Exclude"),
+            special("2025-09-26", None, "Include"),
+            special("2025-09-26", None, "Exclude"),
+        ])
+    }
+
+    #[test]
+    fn day_kind_at_boundaries() {
+        let calendar = national_day();
+
+        let cases = [
+            ("2025-09-25", DayKind::Workday),
+            ("2025-09-26", DayKind::Workday),
+            ("2025-09-27", DayKind::Weekend),
+            ("2025-09-28", DayKind::AdjustedWorkday),
+            ("2025-09-30", DayKind::Workday),
+            ("2025-10-01", DayKind::Holiday),
+            // 假日期间的周末同样为假日
+            ("2025-10-04", DayKind::Holiday),
+            ("2025-10-08", DayKind::Holiday),
+            ("2025-10-09", DayKind::Workday),
+            ("2025-10-11", DayKind::AdjustedWorkday),
+            ("2025-10-12", DayKind::Weekend),
+        ];
+
+        for (day, kind) in cases {
+            assert_eq!(calendar.day_kind(date(day)), kind, "{}", day);
+        }
+    }
+
+    #[test]
+    fn workdays_between_dates() {
+        let calendar = national_day();
+
+        let cases = [
+            // 09-28, 09-29, 09-30, 10-09, 10-10, 10-11
+            ("2025-09-27", "2025-10-12", 6),
+            ("2025-10-01", "2025-10-08", 0),
+            ("2025-09-28", "2025-09-28", 1),
+            ("2025-10-09", "2025-10-09", 1),
+            ("2025-10-12", "2025-10-12", 0),
+            // 开始日期晚于结束日期
+            ("2025-10-12", "2025-10-09", 0),
+        ];
+
+        for (from, to, expected) in cases {
+            assert_eq!(
+                calendar.workdays_between(date(from), date(to)),
+                expected,
+                "{} ~ {}",
+                from,
+                to
+            );
+        }
+    }
+
+    #[test]
+    fn half_day_starts_and_ends() {
+        let calendar = national_day();
+
+        let cases = [
+            ("2025-09-30", true, "2025-10-09", true, 1.0),
+            ("2025-09-28", true, "2025-09-30", false, 2.5),
+            // 半天落在假日时不计
+            ("2025-10-01", true, "2025-10-09", false, 1.0),
+            ("2025-10-09", true, "2025-10-09", true, 0.5),
+            ("2025-10-11", false, "2025-10-13", true, 1.5),
+            ("2025-10-04", true, "2025-10-05", true, 0.0),
+        ];
+
+        for (from, from_half, to, to_half, expected) in cases {
+            assert_eq!(
+                calendar.workdays(date(from), from_half, date(to), to_half),
+                expected,
+                "{} ~ {}",
+                from,
+                to
+            );
+        }
+    }
+
+    #[test]
+    fn add_workdays_skips_holidays_and_counts_make_up_days() {
+        let calendar = national_day();
+
+        let cases = [
+            ("2025-09-30", 1.0, "2025-10-09", false),
+            ("2025-09-30", 0.5, "2025-10-09", true),
+            ("2025-09-30", 1.5, "2025-10-10", true),
+            ("2025-09-27", 1.0, "2025-09-28", false),
+            ("2025-10-10", 1.0, "2025-10-11", false),
+            ("2025-10-11", 1.0, "2025-10-13", false),
+            ("2025-10-09", -1.0, "2025-09-30", false),
+            ("2025-10-01", -2.0, "2025-09-29", false),
+            ("2025-09-29", -1.0, "2025-09-28", false),
+            ("2025-10-05", 0.0, "2025-10-05", false),
+        ];
+
+        for (from, days, expected, half) in cases {
+            assert_eq!(
+                calendar.add_workdays(date(from), days).unwrap(),
+                (date(expected), half),
+                "{} + {}",
+                from,
+                days
+            );
+        }
+    }
+
+    #[test]
+    fn add_workdays_rejects_invalid_days() {
+        let calendar = national_day();
+
+        for days in [0.3, f64::NAN, f64::INFINITY, MAX_ADD_DAYS + 1.0] {
+            assert!(calendar.add_workdays(date("2025-09-30"), days).is_err());
+        }
+
+        // 全年都是假日时视为日历有误
+        let calendar = WorkCalendar::build(&[special("2025-01-01", Some("2026-12-31"), "Include")]);
+        let err = calendar.add_workdays(date("2025-01-01"), 1.0).unwrap_err();
+        assert!(err.to_string().contains("没有工作日"));
+    }
+
+    #[test]
+    fn check_span_limits_range() {
+        let cases = [
+            ("2025-01-01", None, true),
+            ("2025-01-01", Some("2025-01-01"), true),
+            // 闰年整年 366 天
+            ("2024-01-01", Some("2024-12-31"), true),
+            ("2024-01-01", Some("2025-01-01"), false),
+            ("2025-01-02", Some("2025-01-01"), false),
+            ("0001-01-01", Some("9999-12-31"), false),
+        ];
+
+        for (start, end, ok) in cases {
+            assert_eq!(
+                check_span(date(start), end.map(date)).is_ok(),
+                ok,
+                "{} ~ {:?}",
+                start,
+                end
+            );
+        }
+    }
+}
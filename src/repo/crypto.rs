@@ -0,0 +1,359 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chacha20poly1305::{
+    AeadCore, ChaCha20Poly1305, KeyInit, Nonce,
+    aead::{Aead, OsRng},
+};
+
+use log::info;
+
+use crate::config::{AppConfig, StorageKind};
+
+use super::{db::write_atomic, key_rotation};
+
+/// 加密内容的前缀, 之后是 base64 编码的 nonce + 密文
+pub const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// nonce 长度
+const NONCE_LEN: usize = 12;
+
+/// 密钥长度
+const KEY_LEN: usize = 32;
+
+/// 密钥校验文件名, 位于数据目录下, 内容为加密后的 [KEY_CHECK_TEXT]
+pub const KEY_CHECK_FILE: &str = "encryption.check";
+
+const KEY_CHECK_TEXT: &[u8] = b"po_manager";
+
+/// 更换密钥期间存在的标记文件, 位于数据目录下, 存在时说明上次更换未完成
+pub const ROTATING_FILE: &str = "encryption.rotating";
+
+/// 使用 ChaCha20-Poly1305 的认证加密, 密文被篡改或密钥不正确时解密失败
+pub struct Cipher(ChaCha20Poly1305);
+
+impl Cipher {
+    /// 由 base64 编码的 32 字节密钥创建
+    pub fn from_base64(key: &str) -> Result<Self> {
+        let key = STANDARD
+            .decode(key.trim())
+            .context("密钥不是合法的 base64")?;
+
+        if key.len() != KEY_LEN {
+            bail!("密钥长度应为 {} 字节, 实际为 {} 字节", KEY_LEN, key.len());
+        }
+
+        Ok(Cipher(ChaCha20Poly1305::new(key.as_slice().into())))
+    }
+
+    /// 生成新的随机密钥, 返回 base64 编码
+    pub fn generate_key() -> String {
+        STANDARD.encode(ChaCha20Poly1305::generate_key(&mut OsRng))
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Result<String> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(
+            self.0
+                .encrypt(&nonce, plain)
+                .map_err(|_| anyhow!("加密失败"))?,
+        );
+
+        Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(sealed)))
+    }
+
+    fn decrypt(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+
+        let (nonce, data) = sealed.split_at(NONCE_LEN);
+
+        self.0.decrypt(Nonce::from_slice(nonce), data).ok()
+    }
+}
+
+/// 当前使用的密钥, 以及只用于解密的旧密钥
+#[derive(Default)]
+struct Keys {
+    current: Option<Cipher>,
+    previous: Vec<Cipher>,
+    /// 配置了密钥时是否仍接受明文, 只在重新加密加密前的数据时开启
+    allow_plain: bool,
+}
+
+/// 数据文件读写使用的密钥, 克隆后共享同一组密钥, 各数据文件的读写通过 [Keyring::seal] [Keyring::open] 透明地加解密
+///
+/// 启动时由 [init] 按配置创建; 未设置密钥时 (如 [Keyring::default]) 数据以明文读写
+#[derive(Clone, Default)]
+pub struct Keyring(Arc<RwLock<Keys>>);
+
+/// 按配置创建密钥, 未配置密钥时数据以明文写入
+///
+/// 通过数据目录下的密钥校验文件确认密钥与已加密的数据一致, 密钥不正确时直接返回错误, 而不是把无法解密的数据当作损坏处理.
+/// 首次启用加密时与 rotate-key 相同, 先将已有的全部明文数据 (包括 .bak, 日志, 隔离文件, 审计日志及备份) 重新加密, 再创建该文件;
+/// 之后明文内容一律视为被替换, 不再接受
+pub async fn init(config: &AppConfig) -> Result<Keyring> {
+    let key = config.encryption.load_key()?;
+    let current = key.as_deref().map(Cipher::from_base64).transpose()?;
+
+    if current.is_some() && config.storage != StorageKind::Json {
+        bail!("加密仅支持 json 存储后端");
+    }
+
+    let check_path = config.data_dir.join(KEY_CHECK_FILE);
+
+    // 首次启用加密中途失败时校验文件尚未创建, 重新启动即可继续
+    if config.data_dir.join(ROTATING_FILE).exists() && (check_path.exists() || key.is_none()) {
+        bail!("上次更换密钥未完成, 请使用原密钥和同一个新密钥文件重新执行 rotate-key");
+    }
+
+    let keyring = Keyring::new(current, Vec::new());
+
+    match (check_path.exists(), key) {
+        (true, Some(_)) => keyring.verify_key_check(&check_path)?,
+        (true, None) => bail!("数据已加密, 但未配置密钥"),
+        (false, Some(key)) => {
+            let backups = key_rotation::rotate(config, None, &key).await?;
+            info!("已启用加密, 已加密全部数据 (包括 {} 份备份)", backups);
+        }
+        (false, None) => {}
+    }
+
+    Ok(keyring)
+}
+
+impl Keyring {
+    /// previous 中的密钥只用于解密
+    pub fn new(current: Option<Cipher>, previous: Vec<Cipher>) -> Self {
+        Keyring(Arc::new(RwLock::new(Keys {
+            current,
+            previous,
+            allow_plain: false,
+        })))
+    }
+
+    /// 配置了密钥时仍接受明文, 用于重新加密加密前写入的数据
+    pub fn allowing_plain(self) -> Self {
+        self.0.write().unwrap().allow_plain = true;
+        self
+    }
+
+    /// 替换密钥, 共享该密钥的各数据文件之后都使用新的密钥
+    pub fn install(&self, current: Option<Cipher>, previous: Vec<Cipher>) {
+        let mut keys = self.0.write().unwrap();
+        keys.current = current;
+        keys.previous = previous;
+    }
+
+    /// 确认当前的密钥 (包括旧密钥) 可以解密密钥校验文件
+    pub fn verify_key_check(&self, path: &Path) -> Result<()> {
+        let data = fs::read(path)?;
+
+        if !data.starts_with(ENCRYPTED_PREFIX.as_bytes())
+            || self
+                .open(&data)
+                .ok()
+                .is_none_or(|text| text != KEY_CHECK_TEXT)
+        {
+            bail!("密钥不正确, 与加密数据时使用的密钥不一致");
+        }
+
+        Ok(())
+    }
+
+    /// 使用当前密钥写入密钥校验文件
+    pub fn write_key_check(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &self.seal(KEY_CHECK_TEXT)?)
+    }
+
+    /// 使用当前密钥加密, 结果为一行文本; 未配置密钥时原样返回
+    pub fn seal(&self, plain: &[u8]) -> Result<Vec<u8>> {
+        match &self.0.read().unwrap().current {
+            Some(cipher) => Ok(cipher.encrypt(plain)?.into_bytes()),
+            None => Ok(plain.to_vec()),
+        }
+    }
+
+    /// 解密 [Keyring::seal] 的结果, 依次尝试当前密钥和旧密钥
+    ///
+    /// 不是加密内容时, 未配置密钥则原样返回; 配置了密钥则视为被替换的内容返回错误, 除非通过 [Keyring::allowing_plain] 允许
+    pub fn open(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let text = data.trim_ascii();
+        let keys = self.0.read().unwrap();
+
+        let Some(encoded) = text.strip_prefix(ENCRYPTED_PREFIX.as_bytes()) else {
+            if keys.current.is_some() && !keys.allow_plain {
+                bail!(
+                    "内容未加密, 已启用加密时不接受明文; 启用加密前写入的数据请执行 rotate-key 重新加密"
+                );
+            }
+
+            return Ok(data.to_vec());
+        };
+
+        let sealed = STANDARD
+            .decode(encoded)
+            .context("加密内容不是合法的 base64")?;
+
+        keys.current
+            .iter()
+            .chain(keys.previous.iter())
+            .find_map(|cipher| cipher.decrypt(&sealed))
+            .ok_or_else(|| {
+                if keys.current.is_none() {
+                    anyhow!("数据已加密, 但未配置密钥")
+                } else {
+                    anyhow!("解密失败, 密钥不正确或数据已被篡改")
+                }
+            })
+    }
+
+    /// 加密一行文本, 用于按行追加的日志
+    pub fn seal_line(&self, line: &str) -> Result<String> {
+        Ok(String::from_utf8(self.seal(line.as_bytes())?)?)
+    }
+
+    /// 解密 [Keyring::seal_line] 的结果
+    pub fn open_line(&self, line: &str) -> Result<String> {
+        Ok(String::from_utf8(self.open(line.as_bytes())?)?)
+    }
+
+    /// 读取文件并解密
+    pub fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+        let data = fs::read(path).with_context(|| format!("读取文件失败: {}", path.display()))?;
+
+        self.open(&data)
+            .with_context(|| format!("解密文件失败: {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher(key: &str) -> Cipher {
+        Cipher::from_base64(key).unwrap()
+    }
+
+    #[test]
+    fn sealed_content_can_be_opened_by_current_or_previous_key() {
+        let (k1, k2) = (Cipher::generate_key(), Cipher::generate_key());
+
+        let old = Keyring::new(Some(cipher(&k1)), Vec::new());
+        let sealed = old.seal(b"data").unwrap();
+        assert!(sealed.starts_with(ENCRYPTED_PREFIX.as_bytes()));
+        assert_eq!(old.open(&sealed).unwrap(), b"data");
+
+        // 更换密钥后旧密钥只用于解密, 新写入的内容旧密钥无法解密
+        let rotated = Keyring::new(Some(cipher(&k2)), vec![cipher(&k1)]);
+        assert_eq!(rotated.open(&sealed).unwrap(), b"data");
+        assert!(old.open(&rotated.seal(b"data").unwrap()).is_err());
+
+        let line = rotated.seal_line("{\"id\":\"a\"}").unwrap();
+        assert!(!line.contains('\n'));
+        assert_eq!(rotated.open_line(&line).unwrap(), "{\"id\":\"a\"}");
+    }
+
+    #[test]
+    fn wrong_or_missing_key_fails_to_open() {
+        let sealed = Keyring::new(Some(cipher(&Cipher::generate_key())), Vec::new())
+            .seal(b"data")
+            .unwrap();
+
+        let wrong = Keyring::new(Some(cipher(&Cipher::generate_key())), Vec::new());
+        assert!(
+            wrong
+                .open(&sealed)
+                .unwrap_err()
+                .to_string()
+                .contains("解密失败")
+        );
+
+        let none = Keyring::default();
+        assert!(
+            none.open(&sealed)
+                .unwrap_err()
+                .to_string()
+                .contains("未配置密钥")
+        );
+    }
+
+    #[test]
+    fn plain_content_is_rejected_once_a_key_is_set() {
+        let keyring = Keyring::new(Some(cipher(&Cipher::generate_key())), Vec::new());
+        assert!(
+            keyring
+                .open(b"[]")
+                .unwrap_err()
+                .to_string()
+                .contains("未加密")
+        );
+        assert!(keyring.open_line("{}").is_err());
+
+        // 重新加密加密前的数据时接受明文
+        let keyring = keyring.allowing_plain();
+        assert_eq!(keyring.open(b"[]").unwrap(), b"[]");
+
+        let plain = Keyring::default();
+        assert_eq!(plain.seal(b"[]").unwrap(), b"[]");
+        assert_eq!(plain.open(b"[]").unwrap(), b"[]");
+    }
+
+    async fn init_err(config: &AppConfig) -> String {
+        init(config).await.err().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn init_writes_key_check_and_rejects_wrong_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = AppConfig::with_data_dir(dir.path());
+        let check_path = dir.path().join(KEY_CHECK_FILE);
+
+        let key = Cipher::generate_key();
+        config.encryption.key = Some(key.clone());
+        let keyring = init(&config).await.unwrap();
+        keyring.verify_key_check(&check_path).unwrap();
+
+        // 同一密钥可以再次启动
+        init(&config).await.unwrap();
+
+        config.encryption.key = Some(Cipher::generate_key());
+        assert!(init_err(&config).await.contains("密钥不正确"));
+
+        config.encryption.key = None;
+        assert!(init_err(&config).await.contains("未配置密钥"));
+
+        // 校验文件被替换为明文时同样拒绝
+        config.encryption.key = Some(key.clone());
+        let sealed = fs::read(&check_path).unwrap();
+        fs::write(&check_path, KEY_CHECK_TEXT).unwrap();
+        assert!(init_err(&config).await.contains("密钥不正确"));
+        fs::write(&check_path, sealed).unwrap();
+
+        fs::write(dir.path().join(ROTATING_FILE), b"").unwrap();
+        assert!(init_err(&config).await.contains("rotate-key"));
+    }
+
+    #[tokio::test]
+    async fn interrupted_first_enable_resumes_on_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = AppConfig::with_data_dir(dir.path());
+        config.encryption.key = Some(Cipher::generate_key());
+
+        fs::write(dir.path().join(ROTATING_FILE), b"").unwrap();
+
+        let keyring = init(&config).await.unwrap();
+        assert!(!dir.path().join(ROTATING_FILE).exists());
+        keyring
+            .verify_key_check(&dir.path().join(KEY_CHECK_FILE))
+            .unwrap();
+    }
+}
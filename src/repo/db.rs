@@ -1,48 +1,671 @@
 use std::{
-    fs::{self, File, exists},
+    fs::{self, File},
     io::Write,
-    path::Path,
-    sync::{Arc, Mutex},
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
 };
 
-use axum::response::IntoResponse;
-use serde::{Deserialize, Serialize};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use log::info;
+use serde::{Serialize, de::DeserializeOwned};
+use tokio::sync::RwLock;
 
-use crate::result::response::AppResult;
+use crate::{
+    config::{AppConfig, StorageKind},
+    entity::audit::AuditOp,
+};
+
+use super::{
+    audit::AuditLog,
+    crypto::Keyring,
+    index::{Index, IndexKey},
+    journal::JournalEntry,
+    persist::Persister,
+    query::{KeyQuery, Query},
+    recovery::with_suffix,
+    storage::{Storage, json::JsonStorage, sqlite::SqliteStorage},
+    store::StoreRead,
+    transaction::{CollectionId, CommitRecord, Scope, Staged, Transaction, commit_entries},
+};
+
+/// 集合句柄, 读请求之间互不阻塞
+///
+/// 需要同时访问多个集合时, 必须按 [Store](super::store::Store) 中约定的顺序加锁
+pub type DBType<T> = Arc<RwLock<Collection<T>>>;
+
+/// 可持久化的记录, 通过 id 唯一标识
+///
+/// 删除默认为软删除, 只设置删除时间, 记录移入回收站, 之后可以恢复或彻底删除;
+/// 每次变更都会按序列化后的字段比较前后差异, 记入审计日志
+pub trait Record: Serialize {
+    fn id(&self) -> &str;
+
+    /// 版本号, 由 [Collection::put] 维护, 每次写入加一
+    fn version(&self) -> u64;
+
+    fn set_version(&mut self, version: u64);
+
+    /// 删除时间, 未删除时为 None
+    fn deleted_at(&self) -> Option<DateTime<Local>>;
+
+    fn set_deleted_at(&mut self, deleted_at: Option<DateTime<Local>>);
+
+    /// 是否已移入回收站
+    fn is_deleted(&self) -> bool {
+        self.deleted_at().is_some()
+    }
+
+    /// 二级索引的值, 参考 [Index]
+    fn index_keys(&self) -> Vec<(IndexKey, String)> {
+        Vec::new()
+    }
+}
+
+/// 内存中的集合数据, 持久化交由配置的存储后端完成
+///
+/// 通过 Deref 可以直接当作 Vec<T> 读取, 变更需要通过 [Collection::put] [Collection::remove_by_id] 进行,
+/// 变更只修改内存并记录到待写入列表, 由后台任务通过 [Store::flush](super::store::Store::flush) 批量写入后端
+///
+/// 按 id 及 [IndexKey] 的查询通过索引完成, 索引随每次变更更新;
+/// 通过 [Collection::query] 的查询在后端数据与内存一致时交给支持索引的后端完成
+pub struct Collection<T> {
+    rows: Vec<T>,
+    index: Index,
+    /// 集合名, 用于审计记录
+    name: &'static str,
+    /// 尚未写入后端的变更
+    pending: Vec<JournalEntry<T>>,
+    /// 存储后端, 单独加锁, 写入期间不阻塞对内存数据的读写
+    storage: Arc<Mutex<Box<dyn Storage<T>>>>,
+    /// 是否有已取出但尚未写入后端的变更, 参考 [Collection::take_batch]
+    inflight: Arc<AtomicBool>,
+    /// 事务中的撤销日志, 参考 [Collection::begin]
+    undo: Option<UndoLog<T>>,
+    persister: Arc<Persister>,
+    audit: Arc<AuditLog>,
+}
+
+impl<T> Deref for Collection<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.rows
+    }
+}
+
+impl<T: Record + Clone> Collection<T> {
+    /// 集合名
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// 按 id 查询, 包含回收站中的记录
+    pub fn find_by_id(&self, id: &str) -> Option<&T> {
+        self.index
+            .position(id, self.rows.len())
+            .map(|ind| &self.rows[ind])
+    }
+
+    /// 按 id 查询, 不包含回收站中的记录
+    pub fn get(&self, id: &str) -> Option<&T> {
+        self.find_by_id(id).filter(|p| !p.is_deleted())
+    }
+
+    /// 查询满足条件的全部记录, 新记录在前, 不包含回收站中的记录
+    pub fn list(&self, filter: impl Fn(&T) -> bool) -> Vec<&T> {
+        self.rows
+            .iter()
+            .filter(|p| !p.is_deleted() && filter(p))
+            .collect()
+    }
+
+    /// 通过索引查询索引值等于 value 且满足条件的记录, 新记录在前, 不包含回收站中的记录
+    pub fn list_by(&self, key: IndexKey, value: &str, filter: impl Fn(&T) -> bool) -> Vec<&T> {
+        self.resolve(self.index.lookup(key, value), |p| {
+            !p.is_deleted() && filter(p)
+        })
+    }
+
+    /// 通过索引查询索引值等于 value 的记录, 新记录在前, 包含回收站中的记录
+    pub fn find_by(&self, key: IndexKey, value: &str) -> Vec<&T> {
+        self.resolve(self.index.lookup(key, value), |_| true)
+    }
+
+    /// 通过索引查询索引值在 from 和 to 之间 (包含两端) 且满足条件的记录, 新记录在前, 不包含回收站中的记录
+    pub fn list_between(
+        &self,
+        key: IndexKey,
+        from: Option<&str>,
+        to: Option<&str>,
+        filter: impl Fn(&T) -> bool,
+    ) -> Vec<&T> {
+        self.resolve(self.index.range(key, from, to), |p| {
+            !p.is_deleted() && filter(p)
+        })
+    }
+
+    /// 执行查询, 返回满足条件的记录, 新记录在前
+    ///
+    /// 后端支持查询且没有尚未写入后端的变更时在后端执行 (会阻塞, 放到单独的线程中), 否则通过内存中的索引完成
+    pub async fn query(&self, query: Query) -> Result<Vec<T>>
+    where
+        T: Send + 'static,
+    {
+        if self.pending.is_empty() && !self.inflight.load(Ordering::Acquire) {
+            let storage = self.storage.clone();
+            let q = query.clone();
+
+            let rows =
+                tokio::task::spawn_blocking(move || storage.lock().unwrap().query(&q)).await??;
+
+            if let Some(rows) = rows {
+                return Ok(rows);
+            }
+        }
+
+        let rows = match &query.key {
+            None => self.rows.iter().filter(|p| query.matches(*p)).collect(),
+            Some(KeyQuery::Eq(key, value)) => {
+                self.resolve(self.index.lookup(*key, value), |p| query.matches(p))
+            }
+            Some(KeyQuery::Range(key, from, to)) => self.resolve(
+                self.index.range(*key, from.as_deref(), to.as_deref()),
+                |p| query.matches(p),
+            ),
+        };
+
+        Ok(rows.into_iter().cloned().collect())
+    }
+
+    /// 将索引查到的 id 转换为记录, 按在集合中的顺序排列, 按范围查询时同一记录可能出现多次, 只保留一条
+    fn resolve(&self, ids: Vec<&str>, filter: impl Fn(&T) -> bool) -> Vec<&T> {
+        let mut positions: Vec<usize> = ids
+            .into_iter()
+            .filter_map(|id| self.index.position(id, self.rows.len()))
+            .collect();
+        positions.sort_unstable();
+        positions.dedup();
+
+        positions
+            .into_iter()
+            .map(|ind| &self.rows[ind])
+            .filter(|p| filter(p))
+            .collect()
+    }
+
+    /// 按 id 查询回收站中的记录
+    pub fn get_deleted(&self, id: &str) -> Option<&T> {
+        self.find_by_id(id).filter(|p| p.is_deleted())
+    }
+
+    /// 回收站中的全部记录, 新记录在前
+    pub fn trash(&self) -> Vec<&T> {
+        self.rows.iter().filter(|p| p.is_deleted()).collect()
+    }
+
+    /// 新增记录, id 已存在时整条替换, 新增的记录插入到最前面
+    ///
+    /// 记录的版本号设置为原记录的版本号加一, 新增的记录为 1
+    pub fn put(&mut self, mut record: T) -> &T {
+        let id = record.id().to_string();
+
+        let version = self.find_by_id(&id).map_or(0, |p| p.version());
+        record.set_version(version + 1);
+
+        self.apply(JournalEntry::Upsert { record });
+
+        self.find_by_id(&id).unwrap()
+    }
+
+    /// 将记录移入回收站, 返回移入后的记录, 不存在或已在回收站中时返回 None
+    pub fn soft_delete(&mut self, id: &str) -> Option<&T> {
+        let mut record = self.get(id)?.clone();
+        record.set_deleted_at(Some(Local::now()));
+
+        Some(self.put(record))
+    }
+
+    /// 将记录从回收站中恢复, 返回恢复后的记录, 回收站中不存在时返回 None
+    pub fn restore(&mut self, id: &str) -> Option<&T> {
+        let mut record = self.get_deleted(id)?.clone();
+        record.set_deleted_at(None);
+
+        Some(self.put(record))
+    }
+
+    /// 彻底删除指定记录 (包括回收站中的记录), 返回被删除的记录, 不存在时返回 None
+    pub fn remove_by_id(&mut self, id: &str) -> Option<T> {
+        self.find_by_id(id)?;
+
+        self.apply(JournalEntry::Delete { id: id.to_string() })
+    }
+
+    /// 将当前数据整体重写到后端, 数据本身不变, 不记入审计日志, 用于更换密钥
+    pub fn rewrite_all(&mut self) {
+        self.pending.push(JournalEntry::Replace {
+            records: self.rows.clone(),
+        });
+        self.persister.mark_dirty();
+    }
+
+    /// 用给定数据整体替换集合, 新记录在前
+    pub fn replace_all(&mut self, rows: Vec<T>) {
+        self.apply(JournalEntry::Replace { records: rows });
+    }
+
+    /// 将变更应用到内存并加入待写入列表, 同时记入审计日志; 在事务中时记录撤销所需的原值
+    fn apply(&mut self, entry: JournalEntry<T>) -> Option<T> {
+        let seq = self.record_audit(&entry);
+        self.pending.push(entry.clone());
+        self.persister.mark_dirty();
+
+        if let Some(undo) = &mut self.undo {
+            undo.audit.push(seq);
+        }
+
+        match entry {
+            JournalEntry::Upsert { record } => {
+                match self.index.position(record.id(), self.rows.len()) {
+                    Some(ind) => {
+                        self.index.update(&self.rows[ind], &record);
+                        let before = std::mem::replace(&mut self.rows[ind], record);
+                        self.log_undo(|| Undo::Put(before.clone()));
+                        Some(before)
+                    }
+                    None => {
+                        self.log_undo(|| Undo::Remove(record.id().to_string()));
+                        self.index.insert(&record, self.rows.len());
+                        self.rows.insert(0, record);
+                        None
+                    }
+                }
+            }
+            JournalEntry::Delete { id } => {
+                let ind = self.index.position(&id, self.rows.len())?;
+                let removed = self.rows.remove(ind);
+                self.index.remove(&removed, &self.rows[..ind]);
+                self.log_undo(|| Undo::Insert {
+                    record: removed.clone(),
+                    ind,
+                });
+                Some(removed)
+            }
+            JournalEntry::Replace { records } => {
+                let index = std::mem::replace(&mut self.index, Index::build(&records));
+                let rows = std::mem::replace(&mut self.rows, records);
+                self.log_undo(|| Undo::Replace { rows, index });
+                None
+            }
+        }
+    }
+
+    /// 在事务中时记录一步撤销
+    fn log_undo(&mut self, undo: impl FnOnce() -> Undo<T>) {
+        if let Some(log) = &mut self.undo {
+            log.steps.push(undo());
+        }
+    }
+
+    /// 检查存储后端的数据是否在程序之外被修改, 修改时重新加载, 返回是否重新加载
+    ///
+    /// 尚未写入的变更在重新加载的数据之上重新应用; 与内存数据的差异记入审计日志,
+    /// 内容发生变化的记录版本号加一, 合并后的数据随下一批变更写回后端
+    pub fn reload_external(&mut self) -> Result<bool> {
+        let Some(mut rows) = self.storage.lock().unwrap().reload_external()? else {
+            return Ok(false);
+        };
 
-pub type DBType<T> = Arc<Mutex<Vec<T>>>;
+        for entry in self.pending.iter().cloned() {
+            entry.apply_to(&mut rows);
+        }
+
+        let mut changed = rows.len() != self.rows.len();
+
+        for record in rows.iter_mut() {
+            let before = self.find_by_id(record.id());
+
+            if let Some(before) = before {
+                if serde_json::to_value(before)? == serde_json::to_value(&*record)? {
+                    continue;
+                }
+
+                record.set_version(record.version().max(before.version()) + 1);
+            }
+
+            changed = true;
+
+            self.record_audit(&JournalEntry::Upsert {
+                record: record.clone(),
+            });
+        }
+
+        let reloaded = Index::build(&rows);
+
+        for before in self.rows.iter() {
+            if reloaded.position(before.id(), rows.len()).is_none() {
+                changed = true;
+                self.record_audit(&JournalEntry::Delete {
+                    id: before.id().to_string(),
+                });
+            }
+        }
+
+        if changed {
+            self.rows = rows;
+            self.index = reloaded;
+            self.pending.push(JournalEntry::Replace {
+                records: self.rows.clone(),
+            });
+            self.persister.mark_dirty();
+        }
+
+        Ok(true)
+    }
+
+    /// 按变更前后的记录判断变更类型并记入审计日志, 返回审计记录的序号
+    fn record_audit(&self, entry: &JournalEntry<T>) -> u64 {
+        match entry {
+            JournalEntry::Upsert { record } => {
+                let before = self.find_by_id(record.id());
+
+                let op = match before {
+                    None => AuditOp::Create,
+                    Some(before) => match (before.is_deleted(), record.is_deleted()) {
+                        (false, true) => AuditOp::Delete,
+                        (true, false) => AuditOp::Restore,
+                        _ => AuditOp::Update,
+                    },
+                };
+
+                self.audit
+                    .record(self.name, record.id(), op, before, Some(record))
+            }
+            JournalEntry::Delete { id } => {
+                let before = self.find_by_id(id);
+
+                self.audit
+                    .record(self.name, id, AuditOp::Purge, before, None)
+            }
+            JournalEntry::Replace { .. } => {
+                self.audit
+                    .record::<T>(self.name, "", AuditOp::Replace, None, None)
+            }
+        }
+    }
+
+    /// 开始记录撤销日志, 之后的变更可以通过 [Collection::rollback] 撤销
+    ///
+    /// 只记录被修改记录的原值, 不复制整个集合
+    pub fn begin(&mut self) {
+        self.undo = Some(UndoLog {
+            steps: Vec::new(),
+            audit: Vec::new(),
+            pending: self.pending.len(),
+        });
+    }
+
+    /// 撤销 [Collection::begin] 之后的全部变更, 这些变更不会写入后端, 对应的审计记录也一并丢弃
+    pub fn rollback(&mut self) {
+        let Some(log) = self.undo.take() else {
+            return;
+        };
+
+        for undo in log.steps.into_iter().rev() {
+            match undo {
+                Undo::Remove(id) => {
+                    if let Some(ind) = self.index.position(&id, self.rows.len()) {
+                        let removed = self.rows.remove(ind);
+                        self.index.remove(&removed, &self.rows[..ind]);
+                    }
+                }
+                Undo::Put(before) => {
+                    if let Some(ind) = self.index.position(before.id(), self.rows.len()) {
+                        self.index.update(&self.rows[ind], &before);
+                        self.rows[ind] = before;
+                    }
+                }
+                Undo::Insert { record, ind } => {
+                    let rev = self.rows.len() - ind;
+                    self.index.insert_at(&record, &self.rows[..ind], rev);
+                    self.rows.insert(ind, record);
+                }
+                Undo::Replace { rows, index } => {
+                    self.rows = rows;
+                    self.index = index;
+                }
+            }
+        }
+
+        self.pending.truncate(log.pending);
+        self.audit.discard(&log.audit);
+    }
+
+    /// 确认 [Collection::begin] 之后的变更, 丢弃撤销日志
+    pub fn release(&mut self) {
+        self.undo = None;
+    }
+
+    /// [Collection::begin] 之后被修改过的记录, 包括修改前和修改后的值 (含回收站中的记录), 期间有整体替换时返回 None
+    pub fn changed_records(&self) -> Option<Vec<&T>> {
+        let Some(log) = &self.undo else {
+            return Some(Vec::new());
+        };
+
+        let mut res = Vec::new();
+
+        for undo in &log.steps {
+            let id = match undo {
+                Undo::Remove(id) => id.as_str(),
+                Undo::Put(before) | Undo::Insert { record: before, .. } => {
+                    res.push(before);
+                    before.id()
+                }
+                Undo::Replace { .. } => return None,
+            };
+
+            res.extend(self.find_by_id(id));
+        }
+
+        Some(res)
+    }
+
+    /// 取出待写入的变更及当前完整数据, 没有变更时返回 None
+    ///
+    /// 取出的变更写入后端之前, 查询不会交给后端执行
+    pub fn take_batch(&mut self) -> Option<Batch<T>> {
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        self.inflight.store(true, Ordering::Release);
+
+        Some(Batch {
+            entries: std::mem::take(&mut self.pending),
+            rows: self.rows.clone(),
+            storage: self.storage.clone(),
+            inflight: self.inflight.clone(),
+        })
+    }
+
+    /// 写入失败的变更放回待写入列表的最前面, 下次重试
+    pub fn requeue(&mut self, batch: Batch<T>) {
+        self.inflight.store(false, Ordering::Release);
+
+        let newer = std::mem::replace(&mut self.pending, batch.entries);
+        self.pending.extend(newer);
+    }
+}
+
+/// 事务中的撤销日志, 参考 [Collection::begin]
+struct UndoLog<T> {
+    /// 按变更顺序记录的撤销步骤
+    steps: Vec<Undo<T>>,
+    /// 事务中产生的审计记录序号
+    audit: Vec<u64>,
+    /// 开始时待写入变更的数量
+    pending: usize,
+}
+
+/// 撤销一次变更的方式
+enum Undo<T> {
+    /// 撤销新增: 移除该记录
+    Remove(String),
+    /// 撤销更新: 恢复原记录
+    Put(T),
+    /// 撤销删除: 将记录插回原位置, ind 为删除前的下标
+    Insert { record: T, ind: usize },
+    /// 撤销整体替换: 恢复原数据及索引
+    Replace { rows: Vec<T>, index: Index },
+}
+
+/// 从集合中取出的一批待写入变更
+pub struct Batch<T> {
+    pub entries: Vec<JournalEntry<T>>,
+    /// 应用这批变更后的完整数据
+    rows: Vec<T>,
+    storage: Arc<Mutex<Box<dyn Storage<T>>>>,
+    inflight: Arc<AtomicBool>,
+}
+
+impl<T> Batch<T> {
+    /// 写入存储后端, 会阻塞当前线程
+    pub fn write(&self) -> Result<()> {
+        self.storage
+            .lock()
+            .unwrap()
+            .write(&self.entries, &self.rows)?;
+
+        self.inflight.store(false, Ordering::Release);
+
+        Ok(())
+    }
+}
+
+/// 原子写入文件: 写入临时文件并 fsync 后 rename 覆盖目标文件
+///
+/// 任何时刻目标文件要么是旧内容要么是新内容, 不会出现写了一半的文件
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let tmp_path = with_suffix(path, ".tmp");
+
+    let mut file = File::create(&tmp_path)
+        .with_context(|| format!("创建临时文件失败: {}", tmp_path.display()))?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp_path, path).with_context(|| format!("替换文件失败: {}", path.display()))?;
+
+    // rename 本身需要同步所在目录才能保证持久化
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
 
 pub trait DB {
     /// 对应的实体
-    type Entity: Serialize + for<'de> Deserialize<'de>;
+    type Entity: Record + Clone + Serialize + DeserializeOwned + Send + Sync + 'static;
 
-    fn get_path() -> impl AsRef<Path>;
+    /// 对应的集合
+    const ID: CollectionId;
 
-    /// 初始化数据文件, 如果文件不存在则创建, 存在则进行序列化读取
-    fn new() -> DBType<Self::Entity> {
-        if !exists(Self::get_path()).unwrap() {
-            let mut file = File::create_new(Self::get_path()).unwrap();
+    /// 集合名, 同时用作 sqlite 表名
+    fn collection_name() -> &'static str;
 
-            file.write_all(b"[]").unwrap();
+    /// 增删改及回收站操作的事务需要访问的集合, 包括校验和删除规则涉及的集合, 默认只写入本集合
+    fn scope() -> Scope {
+        Scope::new().write(Self::ID)
+    }
 
-            return Arc::new(Mutex::new(Vec::new()));
-        }
+    /// 数据文件名, 由配置提供
+    fn file_name(config: &AppConfig) -> &str;
 
-        let content = fs::read_to_string(Self::get_path()).unwrap();
+    fn get_path(config: &AppConfig) -> PathBuf {
+        config.data_dir.join(Self::file_name(config))
+    }
 
-        let d: Vec<Self::Entity> = serde_json::from_str(content.as_str()).unwrap();
+    /// 事务中对应的集合
+    fn staged<'a, 'b>(tx: &'b mut Transaction<'a>) -> &'b mut Staged<'a, Self::Entity>;
 
-        Arc::new(Mutex::new(d))
+    /// 全部集合的读锁中对应的集合
+    fn read<'a, 'b>(all: &'b StoreRead<'a>) -> &'b Collection<Self::Entity>;
+
+    /// 校验记录引用的其他记录存在, 参考 [integrity](super::integrity)
+    fn check_references(_tx: &Transaction, _record: &Self::Entity) -> Result<()> {
+        Ok(())
+    }
+
+    /// 保存记录前的业务规则校验, 在 [DB::check_references] 之后调用
+    fn validate(_tx: &Transaction, _record: &Self::Entity) -> Result<()> {
+        Ok(())
+    }
+
+    /// 校验没有记录 (包括回收站中的记录) 引用指定记录, 用于不经过删除规则彻底移除记录的场景, 如彻底删除和撤销新增
+    fn check_unreferenced(_tx: &Transaction, _id: &str) -> Result<()> {
+        Ok(())
     }
 
-    /// 将指定数据写入当前json文件中
-    fn store(db: &Vec<Self::Entity>) -> AppResult {
-        let content = serde_json::to_string_pretty(db)?;
+    /// 按配置创建存储后端并加载数据, 变更由 persister 调度写入, 并记入 audit, 数据文件使用 keyring 加解密
+    ///
+    /// commits 为事务日志中尚未完整写入的提交, 加载后会将其中属于本集合的变更重放并写入后端
+    fn new(
+        config: &AppConfig,
+        persister: Arc<Persister>,
+        audit: Arc<AuditLog>,
+        keyring: &Keyring,
+        commits: &[CommitRecord],
+    ) -> Result<DBType<Self::Entity>> {
+        let mut storage: Box<dyn Storage<Self::Entity>> = match config.storage {
+            StorageKind::Json => Box::new(
+                JsonStorage::new(Self::get_path(config), Self::collection_name(), keyring)
+                    .allow_empty(config.recovery.allow_empty),
+            ),
+            StorageKind::Sqlite => Box::new(SqliteStorage::open(
+                &config.sqlite_path(),
+                Self::collection_name(),
+                Self::get_path(config),
+            )?),
+        };
 
-        let path = Self::get_path();
+        let mut rows = storage.load()?;
 
-        fs::write(path, content)?;
+        let entries = commit_entries::<Self::Entity>(commits, Self::collection_name())?;
+
+        if !entries.is_empty() {
+            for entry in entries.iter().cloned() {
+                entry.apply_to(&mut rows);
+            }
+
+            storage.write(&entries, &rows)?;
+
+            info!(
+                "已从事务日志恢复 {} 条变更: {}",
+                entries.len(),
+                Self::collection_name()
+            );
+        }
 
-        Ok(().into_response())
+        Ok(Arc::new(RwLock::new(Collection {
+            index: Index::build(&rows),
+            rows,
+            name: Self::collection_name(),
+            pending: Vec::new(),
+            storage: Arc::new(Mutex::new(storage)),
+            inflight: Arc::new(AtomicBool::new(false)),
+            undo: None,
+            persister,
+            audit,
+        })))
     }
 }
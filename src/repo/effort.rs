@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::entity::{attendance::AttendanceType, project::EntityProject};
+
+use super::{calendar::WorkCalendar, index::IndexKey, ledger::counted_days, store::StoreRead};
+
+/// 项目人天的预算及消耗
+#[derive(Serialize, Debug)]
+pub struct ProjectEffort {
+    /// 预算人天, 技术人天 + 测试人天
+    pub budget: f64,
+    /// 已消耗人天
+    pub consumed: f64,
+    /// 剩余人天, 超支时为负数
+    pub remaining: f64,
+}
+
+impl ProjectEffort {
+    /// 剩余人天占预算的百分比, 预算为 0 时为 0
+    pub fn remaining_percent(&self) -> f64 {
+        if self.budget > 0.0 {
+            self.remaining / self.budget * 100.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// 计算项目截至 today 的人天消耗, calendar 由全部特殊日期构造, 计算多个项目时共用
+///
+/// 每条入项记录在入项到离项 (未离项时为 today) 期间的每个工作日计一人天,
+/// 期间工作日的请假和调休扣除, 休息日的加班计入, 半天计 0.5; 同一人员重叠的入项期间合并后计算, 每天只计一次
+pub fn project_effort(
+    all: &StoreRead,
+    calendar: &WorkCalendar,
+    project: &EntityProject,
+    today: NaiveDate,
+) -> ProjectEffort {
+    let mut consumed = 0.0;
+    let mut periods: BTreeMap<&str, Vec<(NaiveDate, NaiveDate)>> = BTreeMap::new();
+
+    for change in all
+        .employee_change
+        .list_by(IndexKey::ProjectId, &project.id, |_| true)
+    {
+        let from = change.in_time;
+        let to = change.out_time.unwrap_or(today).min(today);
+
+        if from > to {
+            continue;
+        }
+
+        match &change.employee_id {
+            Some(employee_id) => periods.entry(employee_id).or_default().push((from, to)),
+            // 没有人员的入项记录无法与其他记录合并, 也没有考勤
+            None => consumed += calendar.workdays_between(from, to) as f64,
+        }
+    }
+
+    for (employee_id, periods) in periods {
+        let periods = merge_periods(periods);
+
+        for (from, to) in &periods {
+            consumed += calendar.workdays_between(*from, *to) as f64;
+        }
+
+        for attendance in all
+            .attendance
+            .list_by(IndexKey::EmployeeId, employee_id, |_| true)
+        {
+            for (date, days) in counted_days(calendar, attendance) {
+                if !periods
+                    .iter()
+                    .any(|(from, to)| *from <= date && date <= *to)
+                {
+                    continue;
+                }
+
+                match attendance.date_type {
+                    AttendanceType::Leave | AttendanceType::CompensatoryLeave => consumed -= days,
+                    AttendanceType::Overtime => consumed += days,
+                }
+            }
+        }
+    }
+
+    let budget = (project.tech_days + project.test_days) as f64;
+
+    ProjectEffort {
+        budget,
+        consumed,
+        remaining: budget - consumed,
+    }
+}
+
+/// 合并重叠的期间 (包含两端), 结果按开始日期排序且互不重叠
+fn merge_periods(mut periods: Vec<(NaiveDate, NaiveDate)>) -> Vec<(NaiveDate, NaiveDate)> {
+    periods.sort();
+
+    let mut merged: Vec<(NaiveDate, NaiveDate)> = Vec::with_capacity(periods.len());
+
+    for (from, to) in periods {
+        match merged.last_mut() {
+            Some(last) if from <= last.1 => last.1 = last.1.max(to),
+            _ => merged.push((from, to)),
+        }
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        config::AppConfig,
+        repo::{crypto::Keyring, store::Store, tests::date, transaction::Scope},
+    };
+
+    use super::*;
+
+    struct Case {
+        name: &'static str,
+        /// 入项记录的入项和离项日期
+        changes: &'static [(&'static str, Option<&'static str>)],
+        /// 单日考勤记录的日期, 类型, 是否半天
+        attendance: &'static [(&'static str, &'static str, bool)],
+        consumed: f64,
+    }
+
+    /// 项目 p 及人员 e, e 按 case 入项并登记考勤, 返回截至 2026-10-18 (周日) 的消耗
+    async fn consumed(case: &Case) -> f64 {
+        let dir = tempfile::tempdir().unwrap();
+        let store =
+            Store::load(&AppConfig::with_data_dir(dir.path()), &Keyring::default()).unwrap();
+
+        store
+            .transaction(Scope::ALL, |tx| {
+                tx.project.put(serde_json::from_value(json!({
+                    "id": "p",
+                    "name": "项目",
+                    "code": "P",
+                    "release_date": "2026-01-01",
+                    "plan_delivery_date": "2026-12-01",
+                    "tech_days": 10,
+                    "test_days": 0,
+                    "price": 1.0,
+                    "pm": "pm",
+                }))?)?;
+                tx.employee.put(serde_json::from_value(json!({
+                    "id": "e",
+                    "name": "人员",
+                    "status": "Working",
+                    "position": "dev",
+                }))?)?;
+
+                for (ind, (in_time, out_time)) in case.changes.iter().enumerate() {
+                    tx.employee_change.put(serde_json::from_value(json!({
+                        "id": format!("c{ind}"),
+                        "employee_id": "e",
+                        "project_id": "p",
+                        "in_time": in_time,
+                        "out_time": out_time,
+                    }))?)?;
+                }
+
+                for (ind, (day, date_type, half)) in case.attendance.iter().enumerate() {
+                    tx.attendance.put(serde_json::from_value(json!({
+                        "id": format!("a{ind}"),
+                        "start_time": day,
+                        "employee_id": "e",
+                        "date_type": date_type,
+                        "start_half": half,
+                        "end_half": false,
+                    }))?)?;
+                }
+
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        let all = store.read_all().await;
+        let calendar = WorkCalendar::new(&all.special_date);
+        let project = all.project.get("p").unwrap().clone();
+
+        project_effort(&all, &calendar, &project, date("2026-10-18")).consumed
+    }
+
+    #[tokio::test]
+    async fn consumed_days_per_case() {
+        // 2026-10-12 为周一, 2026-10-17 和 2026-10-18 为周末
+        let cases = [
+            Case {
+                name: "工作日及休息日",
+                changes: &[("2026-10-16", Some("2026-10-17"))],
+                attendance: &[],
+                consumed: 1.0,
+            },
+            Case {
+                name: "半天请假",
+                changes: &[("2026-10-12", Some("2026-10-16"))],
+                attendance: &[("2026-10-14", "Leave", true)],
+                consumed: 4.5,
+            },
+            Case {
+                name: "休息日加班, 入项期间外的考勤不计",
+                changes: &[("2026-10-12", Some("2026-10-17"))],
+                attendance: &[
+                    ("2026-10-17", "Overtime", false),
+                    ("2026-10-18", "Overtime", false),
+                    ("2026-10-09", "Leave", false),
+                ],
+                consumed: 6.0,
+            },
+            Case {
+                name: "未离项时计到当天",
+                changes: &[("2026-10-15", None)],
+                attendance: &[],
+                consumed: 2.0,
+            },
+            Case {
+                name: "重叠的入项期间只计一次",
+                changes: &[
+                    ("2026-10-12", Some("2026-10-14")),
+                    ("2026-10-13", Some("2026-10-16")),
+                    ("2026-10-14", None),
+                ],
+                attendance: &[("2026-10-13", "Leave", false)],
+                consumed: 4.0,
+            },
+        ];
+
+        for case in &cases {
+            assert_eq!(consumed(case).await, case.consumed, "{}", case.name);
+        }
+    }
+
+    #[test]
+    fn remaining_percent_is_zero_without_budget() {
+        let effort = ProjectEffort {
+            budget: 0.0,
+            consumed: 2.0,
+            remaining: -2.0,
+        };
+        assert_eq!(effort.remaining_percent(), 0.0);
+
+        let effort = ProjectEffort {
+            budget: 8.0,
+            consumed: 2.0,
+            remaining: 6.0,
+        };
+        assert_eq!(effort.remaining_percent(), 75.0);
+    }
+}
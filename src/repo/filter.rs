@@ -0,0 +1,547 @@
+use std::cmp::Ordering;
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 括号及 not 的最大嵌套层数, 超过时拒绝解析, 避免过深的表达式耗尽栈空间
+const MAX_DEPTH: usize = 32;
+
+/// 列表接口通用的过滤条件参数, 与各接口自己的查询参数一起从查询字符串读取, 两者同时满足
+#[derive(Deserialize, Debug, Default)]
+pub struct FilterParam {
+    /// 过滤表达式, 参考 [Filter::parse]
+    #[serde(default)]
+    pub filter: Option<String>,
+}
+
+impl FilterParam {
+    /// 解析过滤表达式, 未指定时返回 None
+    pub fn parse(&self) -> Result<Option<Filter>> {
+        self.filter
+            .as_deref()
+            .filter(|p| !p.trim().is_empty())
+            .map(Filter::parse)
+            .transpose()
+    }
+}
+
+/// 比较运算符
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    /// 字符串包含
+    Contains,
+}
+
+impl Op {
+    fn symbol(&self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Contains => "~",
+        }
+    }
+}
+
+/// 表达式中的值, 比较时按记录中字段的类型转换
+#[derive(Clone, PartialEq, Debug)]
+pub enum Literal {
+    /// 不带引号的 null, 匹配不存在或为 null 的字段
+    Null,
+    Text(String),
+}
+
+/// 解析后的过滤条件, 按记录序列化后的字段求值, 因此可以用于任意实体的任意字段
+#[derive(Clone, PartialEq, Debug)]
+pub enum Filter {
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+    Compare {
+        field: String,
+        op: Op,
+        value: Literal,
+    },
+    In {
+        field: String,
+        values: Vec<Literal>,
+    },
+    Between {
+        field: String,
+        from: Literal,
+        to: Literal,
+    },
+}
+
+impl Filter {
+    /// 解析过滤表达式
+    ///
+    /// - 比较: `字段 运算符 值`, 运算符为 `=` `!=` `>` `>=` `<` `<=` `~` (包含)
+    /// - 列表: `字段 in (值, 值)`, `字段 not in (值, 值)`
+    /// - 范围: `字段 between 值, 值`, 包含两端
+    /// - 组合: `not` `and` `or` 及括号, 优先级依次降低
+    ///
+    /// 值中含有空格或运算符时使用单引号或双引号; 不带引号的 null 表示空值, 关键字不区分大小写;
+    /// 括号及 not 最多嵌套 32 层
+    pub fn parse(input: &str) -> Result<Filter> {
+        let tokens = tokenize(input)?;
+
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+        };
+        let filter = parser.or()?;
+
+        if let Some(token) = parser.peek() {
+            bail!("过滤条件有误: 多余的内容 {}", token);
+        }
+
+        Ok(filter)
+    }
+
+    /// 记录是否满足条件, 记录中不存在的字段视为 null
+    pub fn matches<T: Serialize>(&self, record: &T) -> bool {
+        serde_json::to_value(record).is_ok_and(|value| self.eval(&value))
+    }
+
+    fn eval(&self, record: &Value) -> bool {
+        match self {
+            Filter::And(items) => items.iter().all(|p| p.eval(record)),
+            Filter::Or(items) => items.iter().any(|p| p.eval(record)),
+            Filter::Not(item) => !item.eval(record),
+            Filter::Compare { field, op, value } => {
+                let actual = &record[field.as_str()];
+
+                match op {
+                    Op::Eq => compare(actual, value) == Some(Ordering::Equal),
+                    Op::Ne => compare(actual, value) != Some(Ordering::Equal),
+                    Op::Gt => compare(actual, value) == Some(Ordering::Greater),
+                    Op::Ge => compare(actual, value).is_some_and(Ordering::is_ge),
+                    Op::Lt => compare(actual, value) == Some(Ordering::Less),
+                    Op::Le => compare(actual, value).is_some_and(Ordering::is_le),
+                    Op::Contains => match (actual, value) {
+                        (Value::String(s), Literal::Text(t)) => s.contains(t.as_str()),
+                        _ => false,
+                    },
+                }
+            }
+            Filter::In { field, values } => {
+                let actual = &record[field.as_str()];
+                values
+                    .iter()
+                    .any(|p| compare(actual, p) == Some(Ordering::Equal))
+            }
+            Filter::Between { field, from, to } => {
+                let actual = &record[field.as_str()];
+                compare(actual, from).is_some_and(Ordering::is_ge)
+                    && compare(actual, to).is_some_and(Ordering::is_le)
+            }
+        }
+    }
+}
+
+/// 比较字段值与表达式中的值, 类型不匹配时返回 None
+fn compare(actual: &Value, literal: &Literal) -> Option<Ordering> {
+    match (actual, literal) {
+        (Value::Null, Literal::Null) => Some(Ordering::Equal),
+        (_, Literal::Null) | (Value::Null, _) => None,
+        (Value::Number(n), Literal::Text(t)) => n.as_f64()?.partial_cmp(&t.parse::<f64>().ok()?),
+        (Value::String(s), Literal::Text(t)) => Some(s.as_str().cmp(t.as_str())),
+        (Value::Bool(b), Literal::Text(t)) => Some(b.cmp(&t.parse::<bool>().ok()?)),
+        _ => None,
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    /// 字段名, 值或关键字, quoted 为 true 时不作为关键字
+    Word {
+        text: String,
+        quoted: bool,
+    },
+    Op(Op),
+    LParen,
+    RParen,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word { text, .. } => write!(f, "'{}'", text),
+            Token::Op(op) => write!(f, "'{}'", op.symbol()),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::Comma => write!(f, "','"),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            ',' => {
+                chars.next();
+                tokens.push(Token::Comma);
+            }
+            '"' | '\'' => {
+                chars.next();
+
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some(ch) if ch == c => break,
+                        Some(ch) => text.push(ch),
+                        None => bail!("过滤条件有误: 引号未闭合"),
+                    }
+                }
+
+                tokens.push(Token::Word { text, quoted: true });
+            }
+            '=' | '!' | '<' | '>' | '~' => {
+                chars.next();
+                let eq = chars.next_if_eq(&'=').is_some();
+
+                let op = match (c, eq) {
+                    ('=', _) => Op::Eq,
+                    ('!', true) => Op::Ne,
+                    ('<', false) => Op::Lt,
+                    ('<', true) => Op::Le,
+                    ('>', false) => Op::Gt,
+                    ('>', true) => Op::Ge,
+                    ('~', false) => Op::Contains,
+                    _ => bail!("过滤条件有误: 无法识别的运算符 {}", c),
+                };
+
+                tokens.push(Token::Op(op));
+            }
+            _ => {
+                let mut text = String::new();
+                while let Some(&ch) = chars.peek() {
+                    if ch.is_whitespace() || "(),\"'=!<>~".contains(ch) {
+                        break;
+                    }
+                    text.push(ch);
+                    chars.next();
+                }
+
+                tokens.push(Token::Word {
+                    text,
+                    quoted: false,
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// 当前所在的括号及 not 的层数
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// 下一个记号是指定关键字时跳过它并返回 true
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word {
+                text,
+                quoted: false,
+            }) if text.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => bail!("过滤条件有误: 应为 {}, 实际为 {}", expected, token),
+            None => bail!("过滤条件有误: 缺少 {}", expected),
+        }
+    }
+
+    fn or(&mut self) -> Result<Filter> {
+        let mut items = vec![self.and()?];
+
+        while self.keyword("or") {
+            items.push(self.and()?);
+        }
+
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            Filter::Or(items)
+        })
+    }
+
+    fn and(&mut self) -> Result<Filter> {
+        let mut items = vec![self.unary()?];
+
+        while self.keyword("and") {
+            items.push(self.unary()?);
+        }
+
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            Filter::And(items)
+        })
+    }
+
+    fn unary(&mut self) -> Result<Filter> {
+        if self.keyword("not") {
+            let inner = self.nested(Self::unary)?;
+            return Ok(Filter::Not(Box::new(inner)));
+        }
+
+        if self.peek() == Some(&Token::LParen) {
+            self.pos += 1;
+            let inner = self.nested(Self::or)?;
+            self.expect(Token::RParen)?;
+            return Ok(inner);
+        }
+
+        self.condition()
+    }
+
+    /// 进入一层括号或 not 后解析, 超过最大层数时返回错误
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Filter>) -> Result<Filter> {
+        if self.depth >= MAX_DEPTH {
+            bail!("过滤条件有误: 括号及 not 嵌套超过 {} 层", MAX_DEPTH);
+        }
+
+        self.depth += 1;
+        let res = parse(self);
+        self.depth -= 1;
+
+        res
+    }
+
+    fn condition(&mut self) -> Result<Filter> {
+        let field = match self.next() {
+            Some(Token::Word {
+                text,
+                quoted: false,
+            }) => text,
+            Some(token) => bail!("过滤条件有误: 应为字段名, 实际为 {}", token),
+            None => bail!("过滤条件有误: 缺少字段名"),
+        };
+
+        if let Some(Token::Op(op)) = self.peek().cloned() {
+            self.pos += 1;
+            let value = self.value()?;
+            return Ok(Filter::Compare { field, op, value });
+        }
+
+        let negated = self.keyword("not");
+
+        if self.keyword("in") {
+            self.expect(Token::LParen)?;
+
+            let mut values = vec![self.value()?];
+            while self.peek() == Some(&Token::Comma) {
+                self.pos += 1;
+                values.push(self.value()?);
+            }
+
+            self.expect(Token::RParen)?;
+
+            let filter = Filter::In { field, values };
+            return Ok(if negated {
+                Filter::Not(Box::new(filter))
+            } else {
+                filter
+            });
+        }
+
+        if !negated && self.keyword("between") {
+            let from = self.value()?;
+            self.expect(Token::Comma)?;
+            let to = self.value()?;
+            return Ok(Filter::Between { field, from, to });
+        }
+
+        match self.peek() {
+            Some(token) => bail!(
+                "过滤条件有误: 字段 {} 之后应为运算符, 实际为 {}",
+                field,
+                token
+            ),
+            None => bail!("过滤条件有误: 字段 {} 之后缺少运算符", field),
+        }
+    }
+
+    fn value(&mut self) -> Result<Literal> {
+        match self.next() {
+            Some(Token::Word {
+                text,
+                quoted: false,
+            }) if text.eq_ignore_ascii_case("null") => Ok(Literal::Null),
+            Some(Token::Word { text, .. }) => Ok(Literal::Text(text)),
+            Some(token) => bail!("过滤条件有误: 应为值, 实际为 {}", token),
+            None => bail!("过滤条件有误: 缺少值"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn matches(expr: &str, record: Value) -> bool {
+        Filter::parse(expr).unwrap().matches(&record)
+    }
+
+    fn error(expr: &str) -> String {
+        Filter::parse(expr).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let record = json!({ "a": "1", "b": "2", "c": "3" });
+
+        assert!(matches("a = 1 or b = 0 and c = 0", record.clone()));
+        assert!(!matches("(a = 1 or b = 0) and c = 0", record.clone()));
+        assert!(matches("not a = 0 and b = 2", record.clone()));
+        assert!(!matches("not (a = 1 and b = 2)", record));
+
+        assert_eq!(
+            Filter::parse("a = 1 or b = 2 and not c = 3").unwrap(),
+            Filter::Or(vec![
+                Filter::parse("a = 1").unwrap(),
+                Filter::And(vec![
+                    Filter::parse("b = 2").unwrap(),
+                    Filter::Not(Box::new(Filter::parse("c = 3").unwrap())),
+                ]),
+            ])
+        );
+    }
+
+    #[test]
+    fn in_not_in_and_between() {
+        let record = json!({ "status": "Leave", "date": "2026-03-15", "days": 3 });
+
+        assert!(matches("status in (Leave, Quit)", record.clone()));
+        assert!(!matches("status not in (Leave, Quit)", record.clone()));
+        assert!(matches("status NOT IN (Active)", record.clone()));
+        assert!(matches(
+            "date between 2026-03-01, 2026-03-31",
+            record.clone()
+        ));
+        assert!(matches("days between 3, 5", record.clone()));
+        assert!(!matches("days between 4, 5", record));
+    }
+
+    #[test]
+    fn quoted_values_are_not_keywords() {
+        let record = json!({ "name": "a b, (c)", "word": "and", "nothing": "null" });
+
+        assert!(matches(r#"name = "a b, (c)""#, record.clone()));
+        assert!(matches("name ~ 'b, ('", record.clone()));
+        assert!(matches("word = 'and'", record.clone()));
+        assert!(matches("nothing = 'null'", record.clone()));
+        assert!(!matches("nothing = null", record));
+    }
+
+    #[test]
+    fn null_matches_missing_and_null_fields() {
+        let record = json!({ "out_time": null, "name": "x" });
+
+        assert!(matches("out_time = null", record.clone()));
+        assert!(matches("missing = NULL", record.clone()));
+        assert!(matches("name != null", record.clone()));
+        assert!(!matches("out_time != null", record.clone()));
+        // 空值与任何值都不相等, 也不可比较
+        assert!(matches("out_time != 1", record.clone()));
+        assert!(!matches("out_time < 1", record));
+    }
+
+    #[test]
+    fn compares_by_field_type() {
+        let record = json!({ "price": 9, "code": "9", "open": true });
+
+        // 数字按数值比较, 字符串按字符串比较
+        assert!(matches("price < 10", record.clone()));
+        assert!(matches("price = 9.0", record.clone()));
+        assert!(!matches("code < 10", record.clone()));
+        assert!(matches("code > 10", record.clone()));
+        assert!(!matches("code = 9.0", record.clone()));
+        assert!(!matches("price = abc", record.clone()));
+        assert!(matches("price != abc", record.clone()));
+        assert!(matches("open = true", record.clone()));
+        assert!(matches("open > false", record.clone()));
+        assert!(!matches("price ~ 9", record));
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(error("name = 'a"), "过滤条件有误: 引号未闭合");
+        assert_eq!(error("name"), "过滤条件有误: 字段 name 之后缺少运算符");
+        assert_eq!(
+            error("name like a"),
+            "过滤条件有误: 字段 name 之后应为运算符, 实际为 'like'"
+        );
+        assert_eq!(error("name ="), "过滤条件有误: 缺少值");
+        assert_eq!(error("(name = a"), "过滤条件有误: 缺少 ')'");
+        assert_eq!(error("name = a b"), "过滤条件有误: 多余的内容 'b'");
+        assert_eq!(error("= a"), "过滤条件有误: 应为字段名, 实际为 '='");
+        assert_eq!(error("a in (1 2)"), "过滤条件有误: 应为 ')', 实际为 '2'");
+        assert_eq!(error("a ! 1"), "过滤条件有误: 无法识别的运算符 !");
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        let nested = |depth: usize| format!("{}a = 1{}", "(".repeat(depth), ")".repeat(depth));
+
+        assert!(Filter::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            error(&nested(MAX_DEPTH + 1)),
+            "过滤条件有误: 括号及 not 嵌套超过 32 层"
+        );
+        assert!(Filter::parse(&format!("{}a = 1", "not ".repeat(MAX_DEPTH + 1))).is_err());
+        assert!(Filter::parse(&nested(100_000)).is_err());
+    }
+}
@@ -0,0 +1,869 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    config::AppConfig,
+    entity::{
+        holiday::{DTOHolidayImport, DTOHolidayImportResult, DTOHolidayRange, DTOHolidaySkipped},
+        special_date::{EntitySpecialDate, SpecialDateType},
+    },
+    serde_custom::date_format::{date_format, date_format_option},
+};
+
+use super::{
+    calendar,
+    db::{Collection, DB},
+    store::Store,
+};
+
+/// 节假日文件格式, 按扩展名识别
+#[derive(Clone, Copy)]
+enum Format {
+    Ics,
+    Json,
+    Csv,
+}
+
+/// 导入节假日文件中的日期范围到特殊日期
+///
+/// 只能导入节假日目录下的文件, 支持:
+///
+/// - `.ics`: 每个 VEVENT 为一个范围, 全天日程的 DTEND 不包含当天; SUMMARY 含 "班" (如 "补班") 时为从假日排除, 否则为计入假日
+/// - `.json`: 数组, 每项包含 `start_time` (或 `start` / `date`), 可选的 `end_time` (或 `end`), `date_type` 及可选的 `name`
+/// - `.csv`: 每行 `开始日期,结束日期,日期类型,名称`, 结束日期和名称可以为空, 首行不是日期时视为表头, `#` 开头的行为注释
+///
+/// 日期格式为 `2026-10-01`, 日期类型可以为 `Include` / `holiday` / `休` / `假` 或 `Exclude` / `workday` / `班`
+#[derive(Clone)]
+pub struct HolidayImporter {
+    dir: PathBuf,
+    store: Store,
+}
+
+impl HolidayImporter {
+    pub fn new(config: &AppConfig, store: Store) -> Self {
+        HolidayImporter {
+            dir: config.holiday_dir(),
+            store,
+        }
+    }
+
+    /// 节假日目录下可导入的文件名
+    pub async fn files(&self) -> Result<Vec<String>> {
+        let dir = self.dir.clone();
+
+        tokio::task::spawn_blocking(move || list_dir(&dir)).await?
+    }
+
+    /// 导入节假日文件, 文件不存在时返回 None
+    ///
+    /// 已有特殊日期覆盖的日期不重复导入, 类型不同的作为冲突跳过; 替换模式下先将该年份已有的特殊日期移入回收站,
+    /// 跨年的特殊日期只截去该年份的部分
+    pub async fn import(&self, param: &DTOHolidayImport) -> Result<Option<DTOHolidayImportResult>> {
+        // 只接受目录下已存在的文件名, 避免拼接任意路径
+        if !self.files().await?.contains(&param.file) {
+            return Ok(None);
+        }
+
+        let bounds = match param.year {
+            Some(year) => match (
+                NaiveDate::from_ymd_opt(year, 1, 1),
+                NaiveDate::from_ymd_opt(year, 12, 31),
+            ) {
+                (Some(from), Some(to)) => Some((from, to)),
+                _ => bail!("年份有误: {}", year),
+            },
+            None if param.replace => bail!("替换模式需要指定 year"),
+            None => None,
+        };
+
+        // 文件读取会阻塞, 放到单独的线程中
+        let path = self.dir.join(&param.file);
+        let file = param.file.clone();
+
+        let ranges = tokio::task::spawn_blocking(move || {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("读取节假日文件失败: {}", path.display()))?;
+
+            parse(&file, &content)
+        })
+        .await??;
+
+        if param.preview {
+            let collection = self.store.special_date.read().await;
+            return Ok(Some(plan(&collection, ranges, bounds, param)));
+        }
+
+        let res = self
+            .store
+            .transaction(EntitySpecialDate::scope(), |tx| {
+                let mut res = plan(tx.special_date.read()?, ranges, bounds, param);
+
+                let mut removed = Vec::with_capacity(res.removed.len());
+                for p in &res.removed {
+                    if let Some(p) = tx.special_date.soft_delete(&p.id)? {
+                        removed.push(p.clone());
+                    }
+                }
+                res.removed = removed;
+
+                res.updated = res
+                    .updated
+                    .into_iter()
+                    .map(|p| Ok(tx.special_date.put(p)?.clone()))
+                    .collect::<Result<_>>()?;
+
+                res.created = res
+                    .created
+                    .into_iter()
+                    .map(|p| Ok(tx.special_date.put(p)?.clone()))
+                    .collect::<Result<_>>()?;
+
+                Ok(res)
+            })
+            .await?;
+
+        Ok(Some(res))
+    }
+}
+
+/// 目录下可导入的文件名, 目录不存在时为空
+fn list_dir(dir: &Path) -> Result<Vec<String>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if format_of(&name).is_some() {
+            files.push(name);
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+/// 导入范围中每一天的处理结果
+#[derive(PartialEq, Clone, Copy)]
+enum DayStatus {
+    /// 新增
+    New,
+    /// 已有相同类型的特殊日期
+    Exists,
+    /// 已有不同类型的特殊日期
+    Conflict,
+}
+
+/// 计算导入结果, 不修改集合
+fn plan(
+    existing: &Collection<EntitySpecialDate>,
+    ranges: Vec<DTOHolidayRange>,
+    bounds: Option<(NaiveDate, NaiveDate)>,
+    param: &DTOHolidayImport,
+) -> DTOHolidayImportResult {
+    let mut res = DTOHolidayImportResult {
+        preview: param.preview,
+        parsed: ranges.len(),
+        created: Vec::new(),
+        updated: Vec::new(),
+        removed: Vec::new(),
+        skipped: Vec::new(),
+    };
+
+    // 导入后特殊日期覆盖的每一天及其类型
+    let mut days: HashMap<NaiveDate, SpecialDateType> = HashMap::new();
+
+    for cur in existing.list(|_| true) {
+        let end = cur.end_time.unwrap_or(cur.start_time);
+
+        let Some((from, to)) =
+            bounds.filter(|(from, to)| param.replace && cur.start_time <= *to && end >= *from)
+        else {
+            mark(&mut days, cur.start_time, end, &cur.date_type);
+            continue;
+        };
+
+        // 该年份之前和之后的部分保留
+        let before = from.pred_opt().filter(|_| cur.start_time < from);
+        let after = to.succ_opt().filter(|_| end > to);
+
+        match (before, after) {
+            (None, None) => res.removed.push(cur.clone()),
+            (Some(before), after) => {
+                let mut updated = cur.clone();
+                updated.end_time = (before != cur.start_time).then_some(before);
+                res.updated.push(updated);
+
+                if let Some(after) = after {
+                    res.created
+                        .push(special_date(after, end, cur.date_type.clone()));
+                }
+            }
+            (None, Some(after)) => {
+                let mut updated = cur.clone();
+                updated.start_time = after;
+                updated.end_time = (after != end).then_some(end);
+                res.updated.push(updated);
+            }
+        }
+
+        if let Some(before) = before {
+            mark(&mut days, cur.start_time, before, &cur.date_type);
+        }
+
+        if let Some(after) = after {
+            mark(&mut days, after, end, &cur.date_type);
+        }
+    }
+
+    for range in ranges {
+        // 指定年份时只导入该年份的部分
+        let (start, end) = match bounds {
+            Some((from, to)) => (range.start_time.max(from), range.end_time.min(to)),
+            None => (range.start_time, range.end_time),
+        };
+
+        // 连续且处理结果相同的日期
+        let mut runs: Vec<(DayStatus, NaiveDate, NaiveDate)> = Vec::new();
+
+        for date in start.iter_days().take_while(|d| *d <= end) {
+            let status = match days.get(&date) {
+                None => DayStatus::New,
+                Some(date_type) if *date_type == range.date_type => DayStatus::Exists,
+                Some(_) => DayStatus::Conflict,
+            };
+
+            if status == DayStatus::New {
+                days.insert(date, range.date_type.clone());
+            }
+
+            match runs.last_mut() {
+                Some((last, _, last_end)) if *last == status => *last_end = date,
+                _ => runs.push((status, date, date)),
+            }
+        }
+
+        for (status, start_time, end_time) in runs {
+            let reason = match status {
+                DayStatus::New => {
+                    res.created
+                        .push(special_date(start_time, end_time, range.date_type.clone()));
+                    continue;
+                }
+                DayStatus::Exists => "已存在",
+                DayStatus::Conflict => "与已有特殊日期的类型冲突",
+            };
+
+            res.skipped.push(DTOHolidaySkipped {
+                range: DTOHolidayRange {
+                    start_time,
+                    end_time,
+                    date_type: range.date_type.clone(),
+                    name: range.name.clone(),
+                },
+                reason: reason.to_string(),
+            });
+        }
+    }
+
+    res
+}
+
+/// 记录 start 到 end 之间 (包含两端) 每一天的类型
+fn mark(
+    days: &mut HashMap<NaiveDate, SpecialDateType>,
+    start: NaiveDate,
+    end: NaiveDate,
+    date_type: &SpecialDateType,
+) {
+    for date in start.iter_days().take_while(|d| *d <= end) {
+        days.insert(date, date_type.clone());
+    }
+}
+
+/// 新的特殊日期记录, 只有一天时不设置结束时间
+fn special_date(start: NaiveDate, end: NaiveDate, date_type: SpecialDateType) -> EntitySpecialDate {
+    EntitySpecialDate {
+        id: Uuid::new_v4().to_string(),
+        start_time: start,
+        end_time: (end != start).then_some(end),
+        date_type,
+        version: 0,
+        deleted_at: None,
+    }
+}
+
+fn format_of(name: &str) -> Option<Format> {
+    let ext = Path::new(name).extension()?.to_str()?.to_ascii_lowercase();
+
+    match ext.as_str() {
+        "ics" => Some(Format::Ics),
+        "json" => Some(Format::Json),
+        "csv" => Some(Format::Csv),
+        _ => None,
+    }
+}
+
+/// 按文件扩展名解析节假日文件
+fn parse(name: &str, content: &str) -> Result<Vec<DTOHolidayRange>> {
+    match format_of(name) {
+        Some(Format::Ics) => parse_ics(content),
+        Some(Format::Json) => parse_json(content),
+        Some(Format::Csv) => parse_csv(content),
+        None => bail!("不支持的文件格式: {}, 可选 .ics / .json / .csv", name),
+    }
+}
+
+/// 日期类型, 支持枚举名及常用的中英文写法
+fn parse_date_type(text: &str) -> Option<SpecialDateType> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "include" | "holiday" | "休" | "假" => Some(SpecialDateType::Include),
+        "exclude" | "workday" | "班" => Some(SpecialDateType::Exclude),
+        _ => None,
+    }
+}
+
+/// 校验并构造日期范围, at 为出错时提示的位置
+///
+/// 范围长度按 [calendar::check_span] 限制, 预览和导入时会逐日展开范围
+fn make_range(
+    at: &str,
+    start_time: NaiveDate,
+    end_time: Option<NaiveDate>,
+    date_type: &str,
+    name: String,
+) -> Result<DTOHolidayRange> {
+    let Some(date_type) = parse_date_type(date_type) else {
+        bail!("{}: 无法识别的日期类型 {}", at, date_type);
+    };
+
+    let end_time = end_time.unwrap_or(start_time);
+    if let Err(err) = calendar::check_span(start_time, Some(end_time)) {
+        bail!("{}: {}", at, err);
+    }
+
+    Ok(DTOHolidayRange {
+        start_time,
+        end_time,
+        date_type,
+        name,
+    })
+}
+
+/// ics 中的日程
+#[derive(Default)]
+struct IcsEvent {
+    /// 日期, 及是否带有时间
+    start: Option<(NaiveDate, bool)>,
+    end: Option<(NaiveDate, bool)>,
+    summary: String,
+}
+
+fn parse_ics(content: &str) -> Result<Vec<DTOHolidayRange>> {
+    // 以空格或制表符开头的行是上一行的延续
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut ranges = Vec::new();
+    let mut event: Option<IcsEvent> = None;
+
+    for line in &lines {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+
+        let name = key.split(';').next().unwrap_or(key).to_ascii_uppercase();
+
+        match (name.as_str(), event.as_mut()) {
+            ("BEGIN", _) if value == "VEVENT" => event = Some(IcsEvent::default()),
+            ("END", Some(_)) if value == "VEVENT" => {
+                if let Some(ev) = event.take() {
+                    ranges.push(ics_range(ev)?);
+                }
+            }
+            ("DTSTART", Some(ev)) => ev.start = Some(ics_date(value)?),
+            ("DTEND", Some(ev)) => ev.end = Some(ics_date(value)?),
+            ("SUMMARY", Some(ev)) => {
+                ev.summary = value
+                    .replace("\\,", ",")
+                    .replace("\\;", ";")
+                    .replace("\\n", " ")
+            }
+            _ => {}
+        }
+    }
+
+    Ok(ranges)
+}
+
+/// 解析 ics 中的日期或日期时间, 返回日期及是否带有时间
+///
+/// 以 Z 结尾的 UTC 时间转换为本地时间后取日期, 其他日期时间 (浮动时间或带 TZID 的时间) 按本地时间取日期部分
+fn ics_date(value: &str) -> Result<(NaiveDate, bool)> {
+    let date = if value.len() == 8 {
+        NaiveDate::parse_from_str(value, "%Y%m%d").ok()
+    } else if let Some(utc) = value.strip_suffix('Z') {
+        NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(|p| p.and_utc().with_timezone(&Local).date_naive())
+    } else {
+        NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(|p| p.date())
+    };
+
+    match date {
+        Some(date) => Ok((date, value.len() > 8)),
+        None => bail!("ics 日期格式有误: {}", value),
+    }
+}
+
+fn ics_range(ev: IcsEvent) -> Result<DTOHolidayRange> {
+    let at = format!("日程 {}", ev.summary);
+
+    let Some((start, _)) = ev.start else {
+        bail!("{}: 缺少 DTSTART", at);
+    };
+
+    // 全天日程的 DTEND 是结束后的第一天
+    let end = match ev.end {
+        Some((end, false)) => end.pred_opt().map(|p| p.max(start)),
+        Some((end, true)) => Some(end.max(start)),
+        None => None,
+    };
+
+    let date_type = if ev.summary.contains('班') {
+        "Exclude"
+    } else {
+        "Include"
+    };
+
+    make_range(&at, start, end, date_type, ev.summary)
+}
+
+/// json 文件中的一项
+#[derive(Deserialize)]
+struct JsonHoliday {
+    #[serde(alias = "start", alias = "date", with = "date_format")]
+    start_time: NaiveDate,
+    #[serde(default, alias = "end", with = "date_format_option")]
+    end_time: Option<NaiveDate>,
+    date_type: String,
+    #[serde(default)]
+    name: String,
+}
+
+fn parse_json(content: &str) -> Result<Vec<DTOHolidayRange>> {
+    let items: Vec<JsonHoliday> = match serde_json::from_str(content) {
+        Ok(items) => items,
+        Err(err) => bail!("解析 json 节假日文件失败: {}", err),
+    };
+
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(ind, p)| {
+            make_range(
+                &format!("第 {} 项", ind + 1),
+                p.start_time,
+                p.end_time,
+                &p.date_type,
+                p.name,
+            )
+        })
+        .collect()
+}
+
+fn parse_csv(content: &str) -> Result<Vec<DTOHolidayRange>> {
+    let mut ranges = Vec::new();
+    let mut first = true;
+
+    for (ind, line) in content.lines().enumerate() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let is_first = std::mem::replace(&mut first, false);
+
+        let at = format!("第 {} 行", ind + 1);
+
+        let fields: Vec<&str> = line.splitn(4, ',').map(str::trim).collect();
+        let date = |p: &str| NaiveDate::parse_from_str(p, "%Y-%m-%d");
+
+        let start = match date(fields[0]) {
+            Ok(start) => start,
+            // 首个有效行不是日期时视为表头
+            Err(_) if is_first => continue,
+            Err(_) => bail!("{}: 开始日期有误 {}", at, fields[0]),
+        };
+
+        let end = match fields.get(1).filter(|p| !p.is_empty()) {
+            Some(p) => match date(p) {
+                Ok(end) => Some(end),
+                Err(_) => bail!("{}: 结束日期有误 {}", at, p),
+            },
+            None => None,
+        };
+
+        let Some(date_type) = fields.get(2) else {
+            bail!("{}: 缺少日期类型", at);
+        };
+
+        let name = fields.get(3).unwrap_or(&"").to_string();
+
+        ranges.push(make_range(&at, start, end, date_type, name)?);
+    }
+
+    Ok(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        config::AppConfig,
+        repo::{crypto::Keyring, tests::date},
+    };
+
+    use super::*;
+
+    /// 范围的开始日期, 结束日期及类型
+    fn spans(ranges: &[DTOHolidayRange]) -> Vec<(String, String, SpecialDateType)> {
+        ranges
+            .iter()
+            .map(|p| {
+                (
+                    p.start_time.to_string(),
+                    p.end_time.to_string(),
+                    p.date_type.clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// 特殊日期的开始日期及结束日期
+    fn dates(records: &[EntitySpecialDate]) -> Vec<(String, Option<String>)> {
+        let mut dates: Vec<_> = records
+            .iter()
+            .map(|p| (p.start_time.to_string(), p.end_time.map(|p| p.to_string())))
+            .collect();
+        dates.sort();
+        dates
+    }
+
+    fn range(start: &str, end: &str, date_type: SpecialDateType) -> DTOHolidayRange {
+        DTOHolidayRange {
+            start_time: date(start),
+            end_time: date(end),
+            date_type,
+            name: String::new(),
+        }
+    }
+
+    fn import_param(year: Option<i32>, replace: bool) -> DTOHolidayImport {
+        DTOHolidayImport {
+            file: String::new(),
+            year,
+            replace,
+            preview: true,
+        }
+    }
+
+    /// 加载空的数据目录并写入已有的特殊日期
+    async fn store_with(dir: &Path, existing: &[(&str, &str, Option<&str>)]) -> Store {
+        let store = Store::load(&AppConfig::with_data_dir(dir), &Keyring::default()).unwrap();
+
+        store
+            .transaction(EntitySpecialDate::scope(), |tx| {
+                for (id, start, end) in existing {
+                    tx.special_date.put(
+                        serde_json::from_value(json!({
+                            "id": id,
+                            "start_time": start,
+                            "end_time": end,
+                            "date_type": "Include",
+                        }))
+                        .unwrap(),
+                    )?;
+                }
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        store
+    }
+
+    #[test]
+    fn ics_all_day_end_is_exclusive() {
+        let content = "BEGIN:VCALENDAR\r\n\
+            BEGIN:VEVENT\r\n\
+            DTSTART;VALUE=DATE:20261001\r\n\
+            DTEND;VALUE=DATE:20261008\r\n\
+            SUMMARY:国庆节\r\n\
+            END:VEVENT\r\n\
+            BEGIN:VEVENT\r\n\
+            DTSTART;VALUE=DATE:20260928\r\n\
+            DTEND;VALUE=DATE:20260929\r\n\
+            SUMMARY:国庆节补班\r\n\
+            END:VEVENT\r\n\
+            BEGIN:VEVENT\r\n\
+            DTSTART:20261010T090000\r\n\
+            DTEND:20261010T180000\r\n\
+            SUMMARY:活动\r\n\
+            END:VEVENT\r\n\
+            END:VCALENDAR\r\n";
+
+        let ranges = parse("cn.ics", content).unwrap();
+
+        assert_eq!(
+            spans(&ranges),
+            [
+                (
+                    "2026-10-01".into(),
+                    "2026-10-07".into(),
+                    SpecialDateType::Include
+                ),
+                (
+                    "2026-09-28".into(),
+                    "2026-09-28".into(),
+                    SpecialDateType::Exclude
+                ),
+                (
+                    "2026-10-10".into(),
+                    "2026-10-10".into(),
+                    SpecialDateType::Include
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn ics_folded_lines_are_joined() {
+        let content = "BEGIN:VEVENT\r\n\
+            DTSTART;VALUE=DATE:2026\r\n 0101\r\n\
+            SUMMARY:元\r\n\t旦\\, 休息\r\n\
+            END:VEVENT\r\n";
+
+        let ranges = parse("cn.ics", content).unwrap();
+
+        assert_eq!(
+            spans(&ranges),
+            [(
+                "2026-01-01".into(),
+                "2026-01-01".into(),
+                SpecialDateType::Include
+            )]
+        );
+        assert_eq!(ranges[0].name, "元旦, 休息");
+    }
+
+    #[test]
+    fn csv_skips_header_and_comments() {
+        let content = "\u{feff}# 2026 年节假日\n\
+            开始日期,结束日期,日期类型,名称\n\
+            \n\
+            2026-01-01,,休,元旦\n\
+            # 春节\n\
+            2026-02-15,2026-02-23,Include,春节, 除夕\n\
+            2026-02-14,,班,补班\n";
+
+        let ranges = parse("cn.csv", content).unwrap();
+
+        assert_eq!(
+            spans(&ranges),
+            [
+                (
+                    "2026-01-01".into(),
+                    "2026-01-01".into(),
+                    SpecialDateType::Include
+                ),
+                (
+                    "2026-02-15".into(),
+                    "2026-02-23".into(),
+                    SpecialDateType::Include
+                ),
+                (
+                    "2026-02-14".into(),
+                    "2026-02-14".into(),
+                    SpecialDateType::Exclude
+                ),
+            ]
+        );
+        assert_eq!(ranges[1].name, "春节, 除夕");
+
+        // 只有首个有效行可以是表头
+        let err = parse("cn.csv", "2026-01-01,,休\n日期,,休\n").unwrap_err();
+        assert!(err.to_string().contains("第 2 行"));
+    }
+
+    #[test]
+    fn ranges_must_be_ordered_and_limited() {
+        let err = parse("cn.csv", "2026-01-02,2026-01-01,休\n").unwrap_err();
+        assert!(err.to_string().contains("第 1 行"), "{err}");
+        assert!(err.to_string().contains("早于"), "{err}");
+
+        // 过长的范围在逐日展开之前拒绝
+        let err = parse("cn.csv", "2026-01-01,,休\n0001-01-01,9999-12-31,休\n").unwrap_err();
+        assert!(err.to_string().contains("第 2 行"), "{err}");
+        assert!(err.to_string().contains("不能超过"), "{err}");
+
+        let content = "BEGIN:VEVENT\r\n\
+            DTSTART;VALUE=DATE:20260101\r\n\
+            DTEND;VALUE=DATE:20290101\r\n\
+            SUMMARY:过长\r\n\
+            END:VEVENT\r\n";
+        assert!(parse("cn.ics", content).is_err());
+    }
+
+    #[test]
+    fn ics_utc_datetime_uses_local_date() {
+        let content = "BEGIN:VEVENT\r\n\
+            DTSTART:20261009T170000Z\r\n\
+            DTEND:20261009T180000Z\r\n\
+            SUMMARY:活动\r\n\
+            END:VEVENT\r\n";
+
+        let ranges = parse("cn.ics", content).unwrap();
+
+        let local = |hour| {
+            NaiveDate::from_ymd_opt(2026, 10, 9)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap()
+                .and_utc()
+                .with_timezone(&Local)
+                .date_naive()
+        };
+        assert_eq!(ranges[0].start_time, local(17));
+        assert_eq!(ranges[0].end_time, local(18));
+
+        assert!(ics_date("20261009T1700").is_err());
+        assert!(ics_date("2026100").is_err());
+    }
+
+    #[tokio::test]
+    async fn replace_trims_ranges_across_year_boundary() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with(
+            dir.path(),
+            &[
+                ("before", "2025-12-30", Some("2026-01-02")),
+                ("inside", "2026-05-01", None),
+                ("after", "2026-12-31", Some("2027-01-01")),
+                ("across", "2025-12-31", Some("2027-01-02")),
+            ],
+        )
+        .await;
+
+        let collection = store.special_date.read().await;
+
+        let res = plan(
+            &collection,
+            vec![range("2025-12-31", "2026-01-01", SpecialDateType::Include)],
+            Some((date("2026-01-01"), date("2026-12-31"))),
+            &import_param(Some(2026), true),
+        );
+
+        assert_eq!(dates(&res.removed), [("2026-05-01".into(), None)]);
+        assert_eq!(
+            dates(&res.updated),
+            [
+                ("2025-12-30".into(), Some("2025-12-31".into())),
+                ("2025-12-31".into(), None),
+                ("2027-01-01".into(), None),
+            ]
+        );
+        // 跨越整年的特殊日期拆为前后两段, 导入的范围只保留该年份的部分
+        assert_eq!(
+            dates(&res.created),
+            [
+                ("2026-01-01".into(), None),
+                ("2027-01-01".into(), Some("2027-01-02".into())),
+            ]
+        );
+        assert!(res.skipped.is_empty());
+    }
+
+    #[tokio::test]
+    async fn existing_and_conflicting_days_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with(dir.path(), &[("a", "2026-10-01", Some("2026-10-03"))]).await;
+
+        let collection = store.special_date.read().await;
+
+        let res = plan(
+            &collection,
+            vec![
+                range("2026-09-30", "2026-10-05", SpecialDateType::Include),
+                range("2026-10-02", "2026-10-02", SpecialDateType::Exclude),
+                range("2026-10-05", "2026-10-06", SpecialDateType::Exclude),
+            ],
+            None,
+            &import_param(None, false),
+        );
+
+        assert_eq!(
+            dates(&res.created),
+            [
+                ("2026-09-30".into(), None),
+                ("2026-10-04".into(), Some("2026-10-05".into())),
+                ("2026-10-06".into(), None),
+            ]
+        );
+
+        let skipped: Vec<_> = res
+            .skipped
+            .iter()
+            .map(|p| {
+                (
+                    p.range.start_time.to_string(),
+                    p.range.end_time.to_string(),
+                    p.reason.as_str(),
+                )
+            })
+            .collect();
+
+        // 与文件中前面的范围冲突时同样跳过
+        assert_eq!(
+            skipped,
+            [
+                ("2026-10-01".into(), "2026-10-03".into(), "已存在"),
+                (
+                    "2026-10-02".into(),
+                    "2026-10-02".into(),
+                    "与已有特殊日期的类型冲突"
+                ),
+                (
+                    "2026-10-05".into(),
+                    "2026-10-05".into(),
+                    "与已有特殊日期的类型冲突"
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::Bound,
+};
+
+use chrono::NaiveDate;
+
+use super::db::Record;
+
+/// 二级索引的种类, 记录通过 [Record::index_keys] 提供各索引的值
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum IndexKey {
+    /// 人员id
+    EmployeeId,
+    /// 项目id
+    ProjectId,
+    /// 日期, 值为 [date_key] 的结果, 按字符串排序即按日期排序, 可以按范围查询; 覆盖日期范围的记录每一天一个值
+    Date,
+}
+
+impl IndexKey {
+    /// 索引名, 用作 sqlite 索引表中的 key 列
+    pub fn name(&self) -> &'static str {
+        match self {
+            IndexKey::EmployeeId => "employee_id",
+            IndexKey::ProjectId => "project_id",
+            IndexKey::Date => "date",
+        }
+    }
+}
+
+/// 日期索引的值
+pub fn date_key(date: NaiveDate) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+/// 日期范围覆盖的每一天的日期索引, 按范围查询时可以找到与查询范围有交集的记录, end 为空时只有 start 一天
+pub fn date_keys(start: NaiveDate, end: Option<NaiveDate>) -> Vec<(IndexKey, String)> {
+    let end = end.unwrap_or(start).max(start);
+
+    start
+        .iter_days()
+        .take_while(|d| *d <= end)
+        .map(|d| (IndexKey::Date, date_key(d)))
+        .collect()
+}
+
+/// 集合的索引, 随 [Collection](super::db::Collection) 的每次变更更新, 包括回收站中的记录
+#[derive(Clone, Default)]
+pub struct Index {
+    /// id 到记录位置的映射, 位置从末尾数起: 新记录插入到最前面时, 已有记录的位置不变
+    ids: HashMap<String, usize>,
+    /// 索引值到记录id的映射, 按值有序
+    secondary: HashMap<IndexKey, BTreeMap<String, BTreeSet<String>>>,
+}
+
+impl Index {
+    /// 为全部记录建立索引, rows 新记录在前
+    pub fn build<T: Record>(rows: &[T]) -> Self {
+        let mut index = Index::default();
+
+        for (ind, record) in rows.iter().enumerate() {
+            index
+                .ids
+                .insert(record.id().to_string(), rows.len() - 1 - ind);
+            index.add_keys(record);
+        }
+
+        index
+    }
+
+    /// 记录的下标, len 为集合当前的记录数
+    pub fn position(&self, id: &str, len: usize) -> Option<usize> {
+        self.ids.get(id).map(|rev| len - 1 - rev)
+    }
+
+    /// 记录插入到最前面之前调用, len 为插入前的记录数
+    pub fn insert<T: Record>(&mut self, record: &T, len: usize) {
+        self.ids.insert(record.id().to_string(), len);
+        self.add_keys(record);
+    }
+
+    /// 记录重新插入到 newer 之后时调用, 用于回滚删除; newer 为排在它前面的记录, 这些记录从末尾数起的位置加一
+    ///
+    /// rev 为插入后该记录从末尾数起的位置
+    pub fn insert_at<T: Record>(&mut self, record: &T, newer: &[T], rev: usize) {
+        for p in newer {
+            if let Some(cur) = self.ids.get_mut(p.id()) {
+                *cur += 1;
+            }
+        }
+
+        self.ids.insert(record.id().to_string(), rev);
+        self.add_keys(record);
+    }
+
+    /// 记录被原地替换时调用
+    pub fn update<T: Record>(&mut self, before: &T, after: &T) {
+        self.remove_keys(before);
+        self.add_keys(after);
+    }
+
+    /// 记录被移除后调用, newer 为排在它前面的记录, 这些记录从末尾数起的位置减一
+    pub fn remove<T: Record>(&mut self, removed: &T, newer: &[T]) {
+        self.ids.remove(removed.id());
+
+        for record in newer {
+            if let Some(rev) = self.ids.get_mut(record.id()) {
+                *rev -= 1;
+            }
+        }
+
+        self.remove_keys(removed);
+    }
+
+    /// 索引值等于 value 的记录id
+    pub fn lookup(&self, key: IndexKey, value: &str) -> Vec<&str> {
+        self.secondary
+            .get(&key)
+            .and_then(|values| values.get(value))
+            .map(|ids| ids.iter().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// 索引值在 from 和 to 之间 (包含两端) 的记录id, 为 None 时不限制
+    pub fn range(&self, key: IndexKey, from: Option<&str>, to: Option<&str>) -> Vec<&str> {
+        let Some(values) = self.secondary.get(&key) else {
+            return Vec::new();
+        };
+
+        if let (Some(from), Some(to)) = (from, to)
+            && from > to
+        {
+            return Vec::new();
+        }
+
+        let from = from.map_or(Bound::Unbounded, Bound::Included);
+        let to = to.map_or(Bound::Unbounded, Bound::Included);
+
+        values
+            .range::<str, _>((from, to))
+            .flat_map(|(_, ids)| ids.iter().map(String::as_str))
+            .collect()
+    }
+
+    fn add_keys<T: Record>(&mut self, record: &T) {
+        for (key, value) in record.index_keys() {
+            self.secondary
+                .entry(key)
+                .or_default()
+                .entry(value)
+                .or_default()
+                .insert(record.id().to_string());
+        }
+    }
+
+    fn remove_keys<T: Record>(&mut self, record: &T) {
+        for (key, value) in record.index_keys() {
+            let Some(values) = self.secondary.get_mut(&key) else {
+                continue;
+            };
+
+            if let Some(ids) = values.get_mut(&value) {
+                ids.remove(record.id());
+
+                if ids.is_empty() {
+                    values.remove(&value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{entity::employee_change::EntityEmployeeChange, repo::tests::date};
+
+    use super::*;
+
+    fn change(id: &str, employee_id: &str, in_time: &str) -> EntityEmployeeChange {
+        serde_json::from_value(json!({
+            "id": id,
+            "employee_id": employee_id,
+            "project_id": "p",
+            "in_time": in_time,
+        }))
+        .unwrap()
+    }
+
+    /// 各记录的下标, 与集合中的顺序一致
+    fn positions(index: &Index, rows: &[EntityEmployeeChange]) -> Vec<Option<usize>> {
+        rows.iter()
+            .map(|p| index.position(p.id(), rows.len()))
+            .collect()
+    }
+
+    #[test]
+    fn date_keys_cover_every_day() {
+        let keys = date_keys(date("2024-02-28"), Some(date("2024-03-01")));
+        let values: Vec<&str> = keys.iter().map(|(_, v)| v.as_str()).collect();
+        assert_eq!(values, ["2024-02-28", "2024-02-29", "2024-03-01"]);
+        assert!(keys.iter().all(|(k, _)| *k == IndexKey::Date));
+
+        assert_eq!(date_keys(date("2024-02-28"), None).len(), 1);
+        // 结束日期早于开始日期时只有开始日期
+        assert_eq!(
+            date_keys(date("2024-02-28"), Some(date("2024-02-01"))).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn insert_keeps_positions_of_existing_records() {
+        let mut rows = vec![
+            change("b", "e2", "2024-01-02"),
+            change("a", "e1", "2024-01-01"),
+        ];
+        let mut index = Index::build(&rows);
+        assert_eq!(positions(&index, &rows), [Some(0), Some(1)]);
+
+        // 新记录插入到最前面
+        let c = change("c", "e1", "2024-01-03");
+        index.insert(&c, rows.len());
+        rows.insert(0, c);
+
+        assert_eq!(positions(&index, &rows), [Some(0), Some(1), Some(2)]);
+        assert_eq!(index.position("x", rows.len()), None);
+        assert_eq!(index.lookup(IndexKey::EmployeeId, "e1"), ["a", "c"]);
+        assert_eq!(
+            index.range(IndexKey::Date, Some("2024-01-02"), None),
+            ["b", "c"]
+        );
+    }
+
+    #[test]
+    fn remove_shifts_newer_records_and_drops_keys() {
+        let mut rows = vec![
+            change("c", "e1", "2024-01-03"),
+            change("b", "e2", "2024-01-02"),
+            change("a", "e1", "2024-01-01"),
+        ];
+        let mut index = Index::build(&rows);
+
+        let removed = rows.remove(1);
+        index.remove(&removed, &rows[..1]);
+
+        assert_eq!(positions(&index, &rows), [Some(0), Some(1)]);
+        assert_eq!(index.position("b", rows.len()), None);
+        assert!(index.lookup(IndexKey::EmployeeId, "e2").is_empty());
+        assert!(index.lookup(IndexKey::Date, "2024-01-02").is_empty());
+
+        // 回滚删除, 重新插入到原位置
+        index.insert_at(&removed, &rows[..1], 1);
+        rows.insert(1, removed);
+
+        assert_eq!(positions(&index, &rows), [Some(0), Some(1), Some(2)]);
+        assert_eq!(index.lookup(IndexKey::EmployeeId, "e2"), ["b"]);
+    }
+
+    #[test]
+    fn update_moves_keys() {
+        let before = change("a", "e1", "2024-01-01");
+        let mut index = Index::build(std::slice::from_ref(&before));
+
+        index.update(&before, &change("a", "e2", "2024-02-01"));
+
+        assert!(index.lookup(IndexKey::EmployeeId, "e1").is_empty());
+        assert_eq!(index.lookup(IndexKey::EmployeeId, "e2"), ["a"]);
+        assert!(
+            index
+                .range(IndexKey::Date, None, Some("2024-01-31"))
+                .is_empty()
+        );
+        assert_eq!(index.range(IndexKey::Date, Some("2024-02-01"), None), ["a"]);
+        assert!(
+            index
+                .range(IndexKey::Date, Some("2024-03-01"), Some("2024-01-01"))
+                .is_empty()
+        );
+    }
+}
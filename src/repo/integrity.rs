@@ -0,0 +1,372 @@
+use anyhow::{Result, bail};
+
+use crate::{
+    config::{IntegrityConfig, OnDelete},
+    entity::{
+        attendance::EntityAttendance, employee::EntityEmployee,
+        employee_change::EntityEmployeeChange, leave_policy::EntityLeavePolicy,
+        project::EntityProject,
+    },
+};
+
+use super::{
+    db::{Collection, Record},
+    index::IndexKey,
+    transaction::{Staged, Transaction},
+};
+
+/// 校验引用的记录存在, name 为被引用集合的名称, 用于错误信息
+pub fn check_reference<T: Record + Clone>(
+    parent: &Collection<T>,
+    id: &str,
+    name: &str,
+) -> Result<()> {
+    if parent.get(id).is_none() {
+        bail!("{}不存在: {}", name, id);
+    }
+
+    Ok(())
+}
+
+/// 校验入项记录引用的人员和项目存在, 已被 nullify 清空的关联不校验
+pub fn check_employee_change(tx: &Transaction, record: &EntityEmployeeChange) -> Result<()> {
+    if let Some(id) = &record.employee_id {
+        check_reference(tx.employee.read()?, id, "人员")?;
+    }
+
+    if let Some(id) = &record.project_id {
+        check_reference(tx.project.read()?, id, "项目")?;
+    }
+
+    Ok(())
+}
+
+/// 校验考勤记录引用的人员存在, 已被 nullify 清空的关联不校验
+pub fn check_attendance(tx: &Transaction, record: &EntityAttendance) -> Result<()> {
+    if let Some(id) = &record.employee_id {
+        check_reference(tx.employee.read()?, id, "人员")?;
+    }
+
+    Ok(())
+}
+
+/// 校验年假政策适用的人员存在
+pub fn check_leave_policy(tx: &Transaction, record: &EntityLeavePolicy) -> Result<()> {
+    for id in &record.employee_ids {
+        check_reference(tx.employee.read()?, id, "人员")?;
+    }
+
+    Ok(())
+}
+
+/// 校验没有记录 (包括回收站中的记录) 引用指定项目
+pub fn check_project_unreferenced(tx: &Transaction, id: &str) -> Result<()> {
+    check_unreferenced(
+        tx.employee_change.read()?,
+        "入项记录",
+        IndexKey::ProjectId,
+        id,
+    )
+}
+
+/// 校验没有记录 (包括回收站中的记录) 引用指定人员
+pub fn check_employee_unreferenced(tx: &Transaction, id: &str) -> Result<()> {
+    check_unreferenced(
+        tx.employee_change.read()?,
+        "入项记录",
+        IndexKey::EmployeeId,
+        id,
+    )?;
+    check_unreferenced(tx.attendance.read()?, "考勤记录", IndexKey::EmployeeId, id)?;
+    check_unreferenced(
+        tx.leave_policy.read()?,
+        "年假政策",
+        IndexKey::EmployeeId,
+        id,
+    )
+}
+
+/// 通过索引 key 查找引用了 id 的记录, 回收站中的记录同样计入
+fn check_unreferenced<T: Record + Clone>(
+    children: &Collection<T>,
+    name: &str,
+    key: IndexKey,
+    id: &str,
+) -> Result<()> {
+    let refs = children.find_by(key, id);
+
+    if !refs.is_empty() {
+        let trashed = refs.iter().filter(|p| p.is_deleted()).count();
+
+        bail!(
+            "仍有 {} 条{}引用该记录 (其中 {} 条在回收站中)",
+            refs.len(),
+            name,
+            trashed
+        );
+    }
+
+    Ok(())
+}
+
+/// 将项目移入回收站, 并按配置处理引用它的入项记录, 项目不存在时返回 None
+pub fn delete_project(
+    tx: &mut Transaction,
+    rules: &IntegrityConfig,
+    id: &str,
+) -> Result<Option<EntityProject>> {
+    let Some(removed) = tx.project.soft_delete(id)?.cloned() else {
+        return Ok(None);
+    };
+
+    on_delete(
+        &mut tx.employee_change,
+        rules.employee_change_project,
+        "入项记录",
+        IndexKey::ProjectId,
+        id,
+        |p| p.project_id = None,
+    )?;
+
+    Ok(Some(removed))
+}
+
+/// 将人员移入回收站, 并按配置处理引用他的入项记录和考勤记录, 人员不存在时返回 None
+pub fn delete_employee(
+    tx: &mut Transaction,
+    rules: &IntegrityConfig,
+    id: &str,
+) -> Result<Option<EntityEmployee>> {
+    let Some(removed) = tx.employee.soft_delete(id)?.cloned() else {
+        return Ok(None);
+    };
+
+    on_delete(
+        &mut tx.employee_change,
+        rules.employee_change_employee,
+        "入项记录",
+        IndexKey::EmployeeId,
+        id,
+        |p| p.employee_id = None,
+    )?;
+
+    on_delete(
+        &mut tx.attendance,
+        rules.attendance_employee,
+        "考勤记录",
+        IndexKey::EmployeeId,
+        id,
+        |p| p.employee_id = None,
+    )?;
+
+    // 年假政策不随人员删除, 只从适用人员中移除
+    on_delete(
+        &mut tx.leave_policy,
+        OnDelete::Nullify,
+        "年假政策",
+        IndexKey::EmployeeId,
+        id,
+        |p| p.employee_ids.retain(|e| e != id),
+    )?;
+
+    Ok(Some(removed))
+}
+
+/// 按规则处理通过索引 key 引用了被删除记录 id 的记录, nullify 清空关联字段
+///
+/// 只处理未删除的记录, 级联删除同样是移入回收站; 在事务中调用, 拒绝删除时返回错误, 由事务回滚已做的修改
+fn on_delete<T: Record + Clone>(
+    children: &mut Staged<T>,
+    rule: OnDelete,
+    name: &str,
+    key: IndexKey,
+    id: &str,
+    nullify: impl Fn(&mut T),
+) -> Result<()> {
+    let refs: Vec<T> = children
+        .read()?
+        .list_by(key, id, |_| true)
+        .into_iter()
+        .cloned()
+        .collect();
+
+    if refs.is_empty() {
+        return Ok(());
+    }
+
+    match rule {
+        OnDelete::Restrict => bail!("仍有 {} 条{}引用该记录, 无法删除", refs.len(), name),
+        OnDelete::Cascade => {
+            for record in refs {
+                children.soft_delete(record.id())?;
+            }
+        }
+        OnDelete::Nullify => {
+            for mut record in refs {
+                nullify(&mut record);
+                children.put(record)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        config::AppConfig,
+        repo::{crypto::Keyring, db::DB, store::Store, transaction::Scope},
+    };
+
+    use super::*;
+
+    fn rules(employee_change_project: OnDelete) -> IntegrityConfig {
+        IntegrityConfig {
+            employee_change_project,
+            ..IntegrityConfig::default()
+        }
+    }
+
+    /// 项目 p, 人员 e, 以及 e 在 p 的入项记录 c
+    async fn store_with_change(dir: &std::path::Path) -> Store {
+        let store = Store::load(&AppConfig::with_data_dir(dir), &Keyring::default()).unwrap();
+
+        store
+            .transaction(Scope::ALL, |tx| {
+                tx.project.put(serde_json::from_value(json!({
+                    "id": "p",
+                    "name": "项目",
+                    "code": "P",
+                    "release_date": "2024-01-01",
+                    "plan_delivery_date": "2024-06-01",
+                    "tech_days": 10,
+                    "test_days": 5,
+                    "price": 1.0,
+                    "pm": "pm",
+                }))?)?;
+                tx.employee.put(serde_json::from_value(json!({
+                    "id": "e",
+                    "name": "人员",
+                    "status": "Working",
+                    "position": "dev",
+                }))?)?;
+                tx.employee_change.put(change(Some("p"), "2024-01-01"))?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        store
+    }
+
+    fn change(project_id: Option<&str>, in_time: &str) -> EntityEmployeeChange {
+        serde_json::from_value(json!({
+            "id": "c",
+            "employee_id": "e",
+            "project_id": project_id,
+            "in_time": in_time,
+        }))
+        .unwrap()
+    }
+
+    async fn delete(store: &Store, rules: &IntegrityConfig) -> Result<Option<EntityProject>> {
+        store
+            .transaction(EntityProject::scope(), |tx| delete_project(tx, rules, "p"))
+            .await
+    }
+
+    /// 与更新接口相同, 校验引用后保存
+    async fn update(store: &Store, record: EntityEmployeeChange) -> Result<()> {
+        store
+            .transaction(EntityEmployeeChange::scope(), |tx| {
+                EntityEmployeeChange::check_references(tx, &record)?;
+                tx.employee_change.put(record)?;
+                Ok(())
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn restrict_keeps_project_while_referenced() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with_change(dir.path()).await;
+
+        let err = delete(&store, &IntegrityConfig::default())
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("入项记录"));
+        assert!(store.project.read().await.get("p").is_some());
+
+        update(&store, change(Some("p"), "2024-02-01"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn nullified_change_can_be_updated_after_project_is_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with_change(dir.path()).await;
+
+        delete(&store, &rules(OnDelete::Nullify))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let cur = store
+            .employee_change
+            .read()
+            .await
+            .get("c")
+            .cloned()
+            .unwrap();
+        assert_eq!(cur.project_id, None);
+
+        update(&store, change(None, "2024-02-01")).await.unwrap();
+
+        // 重新关联到回收站中的项目同样被拒绝
+        let err = update(&store, change(Some("p"), "2024-02-01"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("项目不存在"));
+    }
+
+    #[tokio::test]
+    async fn cascaded_change_is_restored_and_updated_after_its_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with_change(dir.path()).await;
+
+        delete(&store, &rules(OnDelete::Cascade))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(store.employee_change.read().await.get("c").is_none());
+
+        let restore_change = || {
+            store.transaction(EntityEmployeeChange::scope(), |tx| {
+                let restored = tx.employee_change.restore("c")?.cloned().unwrap();
+                EntityEmployeeChange::check_references(tx, &restored)
+            })
+        };
+
+        // 项目仍在回收站中时不能单独恢复入项记录
+        assert!(restore_change().await.is_err());
+
+        store
+            .transaction(EntityProject::scope(), |tx| {
+                tx.project.restore("p")?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+        restore_change().await.unwrap();
+
+        update(&store, change(Some("p"), "2024-02-01"))
+            .await
+            .unwrap();
+    }
+}
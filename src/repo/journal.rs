@@ -0,0 +1,313 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    crypto::Keyring,
+    db::{Record, write_atomic},
+    recovery::with_suffix,
+};
+
+/// 集合的单条变更记录
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalEntry<T> {
+    /// 新增或整条替换
+    Upsert { record: T },
+    /// 按 id 删除
+    Delete { id: String },
+    /// 整体替换集合, 新记录在前
+    Replace { records: Vec<T> },
+}
+
+impl<T: Record> JournalEntry<T> {
+    /// 将变更应用到数据上, 返回被替换或删除的记录
+    pub fn apply_to(self, rows: &mut Vec<T>) -> Option<T> {
+        match self {
+            JournalEntry::Upsert { record } => {
+                match rows.iter().position(|p| p.id() == record.id()) {
+                    Some(ind) => Some(std::mem::replace(&mut rows[ind], record)),
+                    None => {
+                        rows.insert(0, record);
+                        None
+                    }
+                }
+            }
+            JournalEntry::Delete { id } => rows
+                .iter()
+                .position(|p| p.id() == id)
+                .map(|ind| rows.remove(ind)),
+            JournalEntry::Replace { records } => {
+                *rows = records;
+                None
+            }
+        }
+    }
+}
+
+/// 日志中无法解析的行
+#[derive(Debug)]
+pub struct BadLine {
+    /// 行号, 从 1 开始
+    pub line: usize,
+    pub content: String,
+    pub error: String,
+}
+
+/// 追加写入的变更日志, 每行一条 [JournalEntry]
+///
+/// 变更先写入日志并 fsync, 之后才写入数据文件, 启动时会将日志中尚未写入数据文件的变更重放
+pub struct Journal {
+    pub path: PathBuf,
+    keyring: Keyring,
+}
+
+impl Journal {
+    /// 数据文件对应的日志文件, 如 project.json -> project.json.journal
+    pub fn for_data_file(data_path: &Path, keyring: &Keyring) -> Self {
+        Journal {
+            path: with_suffix(data_path, ".journal"),
+            keyring: keyring.clone(),
+        }
+    }
+
+    /// 追加一批变更并同步到磁盘
+    pub fn append<T: Serialize>(&self, entries: &[JournalEntry<T>]) -> Result<()> {
+        let lines = self.encode(entries)?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("打开日志文件失败: {}", self.path.display()))?;
+
+        file.write_all(lines.as_bytes())?;
+        file.sync_all()?;
+
+        Ok(())
+    }
+
+    /// 以一批变更整体替换日志内容, 为空时清空日志
+    pub fn replace<T: Serialize>(&self, entries: &[JournalEntry<T>]) -> Result<()> {
+        if entries.is_empty() {
+            return self.clear();
+        }
+
+        write_atomic(&self.path, self.encode(entries)?.as_bytes())
+            .with_context(|| format!("写入日志文件失败: {}", self.path.display()))
+    }
+
+    /// 读取日志中的全部变更, 无法解析的行单独返回, 由调用方隔离
+    ///
+    /// 写入中途崩溃时最后一行可能不完整, 该行从未被确认过, 不会重放, 但与其他无法解析的行一样返回, 以便保留原始内容
+    pub fn read_all<T>(&self) -> Result<(Vec<JournalEntry<T>>, Vec<BadLine>)>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        if !self.path.exists() {
+            return Ok((Vec::new(), Vec::new()));
+        }
+
+        let file = File::open(&self.path)
+            .with_context(|| format!("打开日志文件失败: {}", self.path.display()))?;
+
+        let lines: Vec<String> = BufReader::new(file).lines().collect::<Result<_, _>>()?;
+        let mut entries = Vec::with_capacity(lines.len());
+        let mut bad_lines = Vec::new();
+
+        for (ind, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match self
+                .keyring
+                .open_line(line)
+                .and_then(|line| Ok(serde_json::from_str(&line)?))
+            {
+                Ok(entry) => entries.push(entry),
+                Err(err) => bad_lines.push(BadLine {
+                    line: ind + 1,
+                    content: line.clone(),
+                    error: err.to_string(),
+                }),
+            }
+        }
+
+        Ok((entries, bad_lines))
+    }
+
+    /// 数据文件已包含全部变更后清空日志
+    pub fn clear(&self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+
+        Ok(())
+    }
+
+    /// 将一批变更编码为日志中的行
+    fn encode<T: Serialize>(&self, entries: &[JournalEntry<T>]) -> Result<String> {
+        let mut lines = String::new();
+
+        for entry in entries {
+            lines.push_str(&self.keyring.seal_line(&serde_json::to_string(entry)?)?);
+            lines.push('\n');
+        }
+
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::entity::employee::EntityEmployee;
+
+    use super::*;
+
+    fn employee(id: &str) -> EntityEmployee {
+        serde_json::from_value(json!({
+            "id": id,
+            "name": id,
+            "status": "Working",
+            "position": "dev",
+        }))
+        .unwrap()
+    }
+
+    fn journal(dir: &Path) -> Journal {
+        Journal::for_data_file(&dir.join("employee.json"), &Keyring::default())
+    }
+
+    fn ids(rows: &[EntityEmployee]) -> Vec<&str> {
+        rows.iter().map(|p| p.id.as_str()).collect()
+    }
+
+    /// 按顺序应用日志中的全部变更
+    fn replay(journal: &Journal, mut rows: Vec<EntityEmployee>) -> Vec<EntityEmployee> {
+        let (entries, bad_lines) = journal.read_all().unwrap();
+        assert!(bad_lines.is_empty(), "{:?}", bad_lines);
+
+        for entry in entries {
+            entry.apply_to(&mut rows);
+        }
+
+        rows
+    }
+
+    #[test]
+    fn appended_entries_are_read_back_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = journal(dir.path());
+
+        journal
+            .append(&[
+                JournalEntry::Upsert {
+                    record: employee("a"),
+                },
+                JournalEntry::Upsert {
+                    record: employee("b"),
+                },
+            ])
+            .unwrap();
+        journal
+            .append(&[JournalEntry::<EntityEmployee>::Delete { id: "a".into() }])
+            .unwrap();
+
+        let (entries, bad_lines) = journal.read_all::<EntityEmployee>().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(bad_lines.is_empty());
+        assert!(matches!(&entries[2], JournalEntry::Delete { id } if id == "a"));
+
+        assert_eq!(ids(&replay(&journal, Vec::new())), ["b"]);
+    }
+
+    #[test]
+    fn replace_then_delete_is_applied_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = journal(dir.path());
+
+        journal
+            .append(&[JournalEntry::Replace {
+                records: vec![employee("b"), employee("a")],
+            }])
+            .unwrap();
+        journal
+            .append(&[JournalEntry::<EntityEmployee>::Delete { id: "b".into() }])
+            .unwrap();
+
+        assert_eq!(ids(&replay(&journal, vec![employee("c")])), ["a"]);
+    }
+
+    #[test]
+    fn unparsable_lines_including_truncated_last_line_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = journal(dir.path());
+
+        journal
+            .append(&[JournalEntry::Upsert {
+                record: employee("a"),
+            }])
+            .unwrap();
+
+        // 中间一行损坏, 末尾一行在写入中途被截断
+        let mut file = OpenOptions::new().append(true).open(&journal.path).unwrap();
+        file.write_all(b"oops\n").unwrap();
+        drop(file);
+
+        journal
+            .append(&[JournalEntry::Upsert {
+                record: employee("b"),
+            }])
+            .unwrap();
+
+        let mut file = OpenOptions::new().append(true).open(&journal.path).unwrap();
+        file.write_all(br#"{"op":"upsert","rec"#).unwrap();
+        drop(file);
+
+        let (entries, bad_lines) = journal.read_all::<EntityEmployee>().unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let lines: Vec<(usize, &str)> = bad_lines
+            .iter()
+            .map(|p| (p.line, p.content.as_str()))
+            .collect();
+        assert_eq!(lines, [(2, "oops"), (4, r#"{"op":"upsert","rec"#)]);
+    }
+
+    #[test]
+    fn clear_removes_the_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = journal(dir.path());
+
+        journal
+            .append(&[JournalEntry::Upsert {
+                record: employee("a"),
+            }])
+            .unwrap();
+        assert!(journal.path.exists());
+
+        journal.clear().unwrap();
+        assert!(!journal.path.exists());
+        assert!(replay(&journal, Vec::new()).is_empty());
+
+        // 不存在时清空不报错
+        journal.clear().unwrap();
+
+        // 替换为空时同样清空
+        journal
+            .append(&[JournalEntry::Upsert {
+                record: employee("a"),
+            }])
+            .unwrap();
+        journal.replace::<EntityEmployee>(&[]).unwrap();
+        assert!(!journal.path.exists());
+    }
+}
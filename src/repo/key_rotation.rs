@@ -0,0 +1,369 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+use anyhow::{Context, Result, bail};
+use log::{info, warn};
+
+use crate::{
+    config::{AppConfig, StorageKind},
+    entity::{
+        attendance::EntityAttendance, employee::EntityEmployee,
+        employee_change::EntityEmployeeChange, leave_policy::EntityLeavePolicy,
+        project::EntityProject, special_date::EntitySpecialDate,
+    },
+};
+
+use super::{
+    crypto::{Cipher, KEY_CHECK_FILE, Keyring, ROTATING_FILE},
+    db::{DB, write_atomic},
+    journal::Journal,
+    recovery::{backup_path, quarantine_path},
+    store::Store,
+};
+
+/// 执行 rotate-key 命令, 使用新密钥重新加密全部数据
+///
+/// 新密钥文件不存在时生成新密钥并写入该文件; 配置中未设置密钥时即为首次启用加密.
+/// 重新加密的范围包括各集合的数据文件及其 .bak, .bak.journal 和隔离文件, 审计日志, 以及全部备份.
+/// 执行期间数据目录下存在 encryption.rotating 标记, 中途失败时服务拒绝启动,
+/// 此时使用原密钥和同一个新密钥文件重新执行即可, 两个密钥加密的数据都能读取
+pub async fn run(config: &AppConfig, new_key_file: &Path) -> Result<()> {
+    if config.storage != StorageKind::Json {
+        bail!("加密仅支持 json 存储后端");
+    }
+
+    let old_key = config.encryption.load_key()?;
+    let new_key = load_or_generate(new_key_file)?;
+
+    let backups = rotate(config, old_key.as_deref(), &new_key).await?;
+
+    info!(
+        "已使用新密钥重新加密全部数据 (包括 {} 份备份), 请将配置中的密钥改为 {}",
+        backups,
+        new_key_file.display()
+    );
+
+    Ok(())
+}
+
+/// 将数据目录下的全部数据从 old_key 重新加密为 new_key, old_key 为 None 时即首次启用加密, 返回重新加密的备份数量
+///
+/// 读取时同样接受明文, 以便一并加密启用加密前写入的文件
+pub async fn rotate(config: &AppConfig, old_key: Option<&str>, new_key: &str) -> Result<usize> {
+    let old = || old_key.map(Cipher::from_base64).transpose();
+    let new = || Cipher::from_base64(new_key);
+
+    // 读取时两个密钥都可以使用, 以便继续上次未完成的更换
+    let keyring = Keyring::new(old()?, vec![new()?]).allowing_plain();
+
+    let check_path = config.data_dir.join(KEY_CHECK_FILE);
+    if check_path.exists() {
+        keyring.verify_key_check(&check_path)?;
+    }
+
+    let rotating = config.data_dir.join(ROTATING_FILE);
+    fs::write(&rotating, b"")?;
+
+    let store = Store::load(config, &keyring)?;
+
+    keyring.install(Some(new()?), old()?.into_iter().collect());
+
+    store.project.write().await.rewrite_all();
+    store.employee.write().await.rewrite_all();
+    store.employee_change.write().await.rewrite_all();
+    store.attendance.write().await.rewrite_all();
+    store.special_date.write().await.rewrite_all();
+    store.leave_policy.write().await.rewrite_all();
+
+    store.flush_async().await?;
+
+    // 写入数据文件时会将旧文件复制为 .bak, 因此在写入之后再重新加密 .bak 等文件
+    reencrypt_files::<EntityProject>(config, &keyring)?;
+    reencrypt_files::<EntityEmployee>(config, &keyring)?;
+    reencrypt_files::<EntityEmployeeChange>(config, &keyring)?;
+    reencrypt_files::<EntityAttendance>(config, &keyring)?;
+    reencrypt_files::<EntitySpecialDate>(config, &keyring)?;
+    reencrypt_files::<EntityLeavePolicy>(config, &keyring)?;
+
+    store.audit.rewrite()?;
+
+    let backups = reencrypt_backups(config, &keyring)?;
+
+    keyring.write_key_check(&check_path)?;
+    fs::remove_file(&rotating)?;
+
+    Ok(backups)
+}
+
+/// 读取密钥文件, 不存在时生成新密钥并写入, 文件仅所有者可读写
+fn load_or_generate(path: &Path) -> Result<String> {
+    if path.exists() {
+        return fs::read_to_string(path)
+            .with_context(|| format!("读取密钥文件失败: {}", path.display()));
+    }
+
+    let key = Cipher::generate_key();
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("创建密钥文件失败: {}", path.display()))?;
+    file.write_all(key.as_bytes())?;
+    file.sync_all()?;
+
+    info!("已生成新密钥: {}", path.display());
+
+    Ok(key)
+}
+
+/// 重新加密集合数据文件之外的 .bak, .bak.journal 及隔离文件
+fn reencrypt_files<D: DB>(config: &AppConfig, keyring: &Keyring) -> Result<()> {
+    let path = D::get_path(config);
+    let bak = backup_path(&path);
+
+    reencrypt_file(keyring, &bak)?;
+    reencrypt_lines(keyring, &Journal::for_data_file(&bak, keyring).path)?;
+
+    // 已有的隔离文件无法解析时会另起 .quarantine.<时间>.json
+    let quarantine = quarantine_path(&path);
+    let prefix = path
+        .file_name()
+        .map(|n| format!("{}.quarantine.", n.to_string_lossy()));
+
+    let Some((dir, prefix)) = path.parent().zip(prefix) else {
+        return Ok(());
+    };
+
+    for entry in fs::read_dir(dir)? {
+        let file = entry?.path();
+
+        let name = file.file_name().map(|n| n.to_string_lossy().into_owned());
+        if file == quarantine
+            || name.is_some_and(|n| n.starts_with(&prefix) && n.ends_with(".json"))
+        {
+            reencrypt_file(keyring, &file)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 重新加密全部备份中的集合文件, 返回备份数量
+fn reencrypt_backups(config: &AppConfig, keyring: &Keyring) -> Result<usize> {
+    let dir = config.backup_dir();
+
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let mut count = 0;
+
+    for entry in fs::read_dir(&dir)? {
+        let backup = entry?.path();
+
+        // 跳过未完成的临时目录
+        if !backup.is_dir()
+            || backup
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+
+        for collection in [
+            EntityProject::collection_name(),
+            EntityEmployee::collection_name(),
+            EntityEmployeeChange::collection_name(),
+            EntityAttendance::collection_name(),
+            EntitySpecialDate::collection_name(),
+            EntityLeavePolicy::collection_name(),
+        ] {
+            reencrypt_file(keyring, &backup.join(format!("{}.json", collection)))?;
+        }
+
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// 解密文件后使用当前密钥重新加密, 文件不存在时跳过
+fn reencrypt_file(keyring: &Keyring, path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let plain = keyring.read_file(path)?;
+
+    write_atomic(path, &keyring.seal(&plain)?)
+        .with_context(|| format!("重新加密文件失败: {}", path.display()))
+}
+
+/// 逐行解密按行追加的日志后使用当前密钥重新加密, 文件不存在时跳过
+///
+/// 末尾无法解析的一行视为写入中途崩溃留下的不完整记录, 原样保留, 读取日志时与其他无法解析的行一起隔离
+fn reencrypt_lines(keyring: &Keyring, path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("读取日志文件失败: {}", path.display()))?;
+    let lines: Vec<&str> = content.lines().filter(|p| !p.trim().is_empty()).collect();
+
+    let mut sealed = String::new();
+
+    for (ind, line) in lines.iter().enumerate() {
+        match keyring.open_line(line) {
+            Ok(plain) => {
+                sealed.push_str(&keyring.seal_line(&plain)?);
+                sealed.push('\n');
+            }
+            Err(_) if ind == lines.len() - 1 => {
+                warn!(
+                    "保留日志 {} 末尾不完整的记录, 读取日志时隔离",
+                    path.display()
+                );
+                sealed.push_str(line);
+                sealed.push('\n');
+            }
+            Err(err) => {
+                return Err(err.context(format!(
+                    "解密日志文件失败: {} 第 {} 行",
+                    path.display(),
+                    ind + 1
+                )));
+            }
+        }
+    }
+
+    write_atomic(path, sealed.as_bytes())
+        .with_context(|| format!("重新加密文件失败: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::repo::{
+        audit::AUDIT_FILE,
+        crypto::{self, ENCRYPTED_PREFIX},
+        transaction::Scope,
+    };
+
+    use super::*;
+
+    async fn put_employee(store: &Store, id: &str) {
+        store
+            .transaction(Scope::ALL, |tx| {
+                tx.employee.put(serde_json::from_value(json!({
+                    "id": id,
+                    "name": "人员",
+                    "status": "Working",
+                    "position": "dev",
+                }))?)?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+        store.flush_async().await.unwrap();
+    }
+
+    fn is_encrypted(path: &Path) -> bool {
+        fs::read(path)
+            .unwrap()
+            .starts_with(ENCRYPTED_PREFIX.as_bytes())
+    }
+
+    #[tokio::test]
+    async fn rotates_twice_and_recovers_from_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AppConfig::with_data_dir(dir.path());
+        let path = EntityEmployee::get_path(&config);
+
+        // 两次写入后 .bak 及 .bak.journal 都存在
+        let store = Store::load(&config, &Keyring::default()).unwrap();
+        put_employee(&store, "e1").await;
+        put_employee(&store, "e2").await;
+        drop(store);
+
+        let quarantine = quarantine_path(&path);
+        let quarantine_dated = dir.path().join(format!(
+            "{}.quarantine.20250101000000.json",
+            EntityEmployee::file_name(&config)
+        ));
+        fs::write(&quarantine, b"[]").unwrap();
+        fs::write(&quarantine_dated, b"[]").unwrap();
+
+        let (k1, k2) = (Cipher::generate_key(), Cipher::generate_key());
+
+        rotate(&config, None, &k1).await.unwrap();
+        rotate(&config, Some(&k1), &k2).await.unwrap();
+
+        assert!(!dir.path().join(ROTATING_FILE).exists());
+
+        let bak = backup_path(&path);
+        for file in [&path, &bak, &quarantine, &quarantine_dated] {
+            assert!(is_encrypted(file), "{}", file.display());
+        }
+
+        // 旧密钥已无法读取任何文件
+        let k2_only = Keyring::new(Some(Cipher::from_base64(&k2).unwrap()), Vec::new());
+        let k1_only = Keyring::new(Some(Cipher::from_base64(&k1).unwrap()), Vec::new());
+        for file in [&path, &bak, &quarantine, &quarantine_dated] {
+            assert!(k1_only.read_file(file).is_err(), "{}", file.display());
+            k2_only.read_file(file).unwrap();
+        }
+
+        // 数据文件损坏时由新密钥加密的 .bak 恢复全部记录
+        fs::write(&path, b"enc:v1:broken").unwrap();
+
+        let store = Store::load(&config, &k2_only).unwrap();
+        let employee = store.employee.read().await;
+        assert!(employee.get("e1").is_some());
+        assert!(employee.get("e2").is_some());
+    }
+
+    #[tokio::test]
+    async fn first_enable_encrypts_existing_files_and_rejects_plain_swaps() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = AppConfig::with_data_dir(dir.path());
+        let path = EntityEmployee::get_path(&config);
+        let bak = backup_path(&path);
+
+        let store = Store::load(&config, &Keyring::default()).unwrap();
+        put_employee(&store, "e1").await;
+        put_employee(&store, "e2").await;
+        drop(store);
+
+        config.encryption.key = Some(Cipher::generate_key());
+        let keyring = crypto::init(&config).await.unwrap();
+
+        let bak_journal = Journal::for_data_file(&bak, &keyring).path;
+        for file in [&path, &bak, &bak_journal] {
+            assert!(is_encrypted(file), "{}", file.display());
+        }
+
+        // 审计日志逐行加密
+        let audit = fs::read_to_string(dir.path().join(AUDIT_FILE)).unwrap();
+        assert!(!audit.is_empty());
+        assert!(audit.lines().all(|p| p.starts_with(ENCRYPTED_PREFIX)));
+
+        // 以明文替换数据文件时视为损坏, 由 .bak 恢复
+        let plain = String::from_utf8(keyring.read_file(&path).unwrap()).unwrap();
+        fs::write(&path, plain.replace("人员", "替换")).unwrap();
+
+        let store = Store::load(&config, &keyring).unwrap();
+        let employee = store.employee.read().await;
+        assert_eq!(employee.get("e1").unwrap().name, "人员");
+        assert_eq!(employee.get("e2").unwrap().name, "人员");
+    }
+}
@@ -0,0 +1,316 @@
+use std::{cmp::Ordering, collections::BTreeMap};
+
+use anyhow::{Result, bail};
+use chrono::{Datelike, NaiveDate};
+
+use crate::entity::{
+    attendance::{AttendanceType, EntityAttendance},
+    employee::EntityEmployee,
+    employee_change::EntityEmployeeChange,
+    leave_policy::{DTOLeaveBalance, EntityLeavePolicy},
+};
+
+use super::{
+    calendar::WorkCalendar, db::Collection, index::IndexKey, ledger::counted_days,
+    transaction::Transaction,
+};
+
+/// 人员适用的年假政策: 分配给该人员的政策优先, 其次是分配给其岗位的政策, 同类中新创建的优先
+pub fn policy_for<'a>(
+    policies: &'a Collection<EntityLeavePolicy>,
+    employee: &EntityEmployee,
+) -> Option<&'a EntityLeavePolicy> {
+    policies
+        .list_by(IndexKey::EmployeeId, &employee.id, |_| true)
+        .into_iter()
+        .next()
+        .or_else(|| {
+            policies
+                .list(|p| p.positions.contains(&employee.position))
+                .into_iter()
+                .next()
+        })
+}
+
+/// 校验年假政策的天数不为负数, 结转过期日期格式正确
+pub fn check_policy(policy: &EntityLeavePolicy) -> Result<()> {
+    if policy.annual_quota < 0.0 || policy.accrual_per_month < 0.0 || policy.carry_over_cap < 0.0 {
+        bail!("年假天数, 每月累积天数和结转上限不能为负数");
+    }
+
+    if let Some(expiry) = &policy.carry_over_expiry
+        && parse_expiry(expiry).is_none()
+    {
+        bail!("结转过期日期格式应为 月-日, 如 03-31: {}", expiry);
+    }
+
+    Ok(())
+}
+
+/// 解析 月-日 格式的过期日期
+fn parse_expiry(expiry: &str) -> Option<(u32, u32)> {
+    // 使用闰年解析, 以便接受 02-29
+    NaiveDate::parse_from_str(&format!("2000-{}", expiry), "%Y-%m-%d")
+        .ok()
+        .map(|d| (d.month(), d.day()))
+}
+
+/// 结转年假在 year 年的过期日期, 02-29 在平年为 02-28
+fn expiry_in(policy: &EntityLeavePolicy, year: i32) -> Option<NaiveDate> {
+    let (month, day) = parse_expiry(policy.carry_over_expiry.as_deref()?)?;
+
+    NaiveDate::from_ymd_opt(year, month, day)
+        .or_else(|| NaiveDate::from_ymd_opt(year, month, day - 1))
+}
+
+/// year 年截至 date 已累积的额度
+fn accrued(policy: &EntityLeavePolicy, year: i32, date: NaiveDate) -> f64 {
+    if policy.accrual_per_month <= 0.0 {
+        return policy.annual_quota;
+    }
+
+    let months = match date.year().cmp(&year) {
+        Ordering::Less => 0,
+        Ordering::Equal => date.month(),
+        Ordering::Greater => 12,
+    };
+
+    (policy.accrual_per_month * months as f64).min(policy.annual_quota)
+}
+
+/// 年假开始计算的日期: 人员最早的入项日期与政策生效日期中较晚的一个, 都未知时为 None
+pub fn effective_start(
+    changes: &Collection<EntityEmployeeChange>,
+    policy: &EntityLeavePolicy,
+    employee_id: &str,
+) -> Option<NaiveDate> {
+    let entry = changes
+        .list_by(IndexKey::EmployeeId, employee_id, |_| true)
+        .into_iter()
+        .map(|p| p.in_time)
+        .min();
+
+    entry.max(policy.start_date)
+}
+
+/// 计算人员在 date 所在年份截至 date 的年假余额, records 为该人员的考勤记录
+///
+/// 请假按工作日历折算, 优先使用上一年结转的天数; 结转天数在过期日期之后未用完的部分作废,
+/// 未设置过期日期时, 未用完的结转天数与当年剩余额度一起再次结转, 总数不超过结转上限
+///
+/// 从 start (参考 [effective_start]) 所在年份开始逐年结转, 没有请假的年份同样结转; start 为 None 时从有请假记录的第一年开始
+pub fn leave_balance(
+    calendar: &WorkCalendar,
+    policy: &EntityLeavePolicy,
+    employee_id: &str,
+    records: &[&EntityAttendance],
+    start: Option<NaiveDate>,
+    date: NaiveDate,
+) -> DTOLeaveBalance {
+    let year = date.year();
+
+    // 每年的请假天数, 及其中在结转过期日期 (包含当天) 之前的部分
+    let mut usage: BTreeMap<i32, (f64, f64)> = BTreeMap::new();
+
+    for p in records
+        .iter()
+        .filter(|p| p.date_type == AttendanceType::Leave)
+    {
+        for (d, days) in counted_days(calendar, p) {
+            let entry = usage.entry(d.year()).or_default();
+            entry.0 += days;
+
+            if expiry_in(policy, d.year()).is_none_or(|e| d <= e) {
+                entry.1 += days;
+            }
+        }
+    }
+
+    let usage_of = |y: i32| usage.get(&y).copied().unwrap_or_default();
+
+    let mut carry = 0.0;
+
+    if policy.carry_over_cap > 0.0 {
+        let first = match start {
+            Some(start) => start.year(),
+            None => usage.keys().next().copied().unwrap_or(year),
+        };
+
+        for y in first..year {
+            let (used, early) = usage_of(y);
+            let carry_used = f64::min(carry, early);
+
+            let carry_left = if policy.carry_over_expiry.is_some() {
+                0.0
+            } else {
+                carry - carry_used
+            };
+
+            let quota_left = accrued(policy, y, date) - (used - carry_used);
+
+            carry = (quota_left + carry_left).clamp(0.0, policy.carry_over_cap);
+        }
+    }
+
+    let (used, early) = usage_of(year);
+    let carry_used = f64::min(carry, early);
+
+    let expired = match expiry_in(policy, year) {
+        Some(expiry) if date > expiry => carry - carry_used,
+        _ => 0.0,
+    };
+
+    let accrued = accrued(policy, year, date);
+
+    DTOLeaveBalance {
+        employee_id: employee_id.to_string(),
+        policy_id: policy.id.clone(),
+        policy_name: policy.name.clone(),
+        year,
+        date,
+        annual_quota: policy.annual_quota,
+        accrued,
+        carried_over: carry,
+        carried_over_expired: expired,
+        used,
+        remaining: accrued + carry - expired - used,
+    }
+}
+
+/// 请假记录超出年假额度时的提示, 不阻止保存
+///
+/// 按记录涉及的每一年, 分别计算截至该年最后一个请假日的余额; 人员没有适用的年假政策时不提示
+pub fn quota_warnings(tx: &Transaction, record: &EntityAttendance) -> Result<Vec<String>> {
+    if record.date_type != AttendanceType::Leave {
+        return Ok(Vec::new());
+    }
+
+    let employees = tx.employee.read()?;
+
+    let Some(employee) = record
+        .employee_id
+        .as_deref()
+        .and_then(|id| employees.get(id))
+    else {
+        return Ok(Vec::new());
+    };
+
+    let Some(policy) = policy_for(tx.leave_policy.read()?, employee) else {
+        return Ok(Vec::new());
+    };
+
+    let calendar = WorkCalendar::new(tx.special_date.read()?);
+    let start = effective_start(tx.employee_change.read()?, policy, &employee.id);
+
+    let records = tx
+        .attendance
+        .read()?
+        .list_by(IndexKey::EmployeeId, &employee.id, |_| true);
+
+    // 每年最后一个请假日
+    let last: BTreeMap<i32, NaiveDate> = counted_days(&calendar, record)
+        .into_iter()
+        .map(|(d, _)| (d.year(), d))
+        .collect();
+
+    Ok(last
+        .into_iter()
+        .filter_map(|(year, date)| {
+            let balance = leave_balance(&calendar, policy, &employee.id, &records, start, date);
+
+            (balance.remaining < 0.0).then(|| {
+                format!(
+                    "{} 年的年假超出额度 {} 天 (年假政策: {})",
+                    year, -balance.remaining, policy.name
+                )
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        config::AppConfig,
+        repo::{crypto::Keyring, db::DB, store::Store, tests::date},
+    };
+
+    use super::*;
+
+    fn policy(carry_over_cap: f64, start_date: Option<&str>) -> EntityLeavePolicy {
+        serde_json::from_value(json!({
+            "id": "p",
+            "name": "默认",
+            "annual_quota": 10.0,
+            "carry_over_cap": carry_over_cap,
+            "start_date": start_date,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn carry_over_starts_without_prior_leave() {
+        let dir = tempfile::tempdir().unwrap();
+        let store =
+            Store::load(&AppConfig::with_data_dir(dir.path()), &Keyring::default()).unwrap();
+        let calendar = WorkCalendar::new(&*store.special_date.read().await);
+
+        let carried = |cap: f64, start: Option<&str>| {
+            leave_balance(
+                &calendar,
+                &policy(cap, None),
+                "e",
+                &[],
+                start.map(date),
+                date("2026-06-01"),
+            )
+            .carried_over
+        };
+
+        // 上一年没有请假时结转全年额度, 不超过结转上限
+        assert_eq!(carried(5.0, Some("2025-03-01")), 5.0);
+        assert_eq!(carried(20.0, Some("2025-03-01")), 10.0);
+
+        // 当年开始计算时没有可结转的年份
+        assert_eq!(carried(5.0, Some("2026-01-01")), 0.0);
+        assert_eq!(carried(5.0, None), 0.0);
+    }
+
+    #[tokio::test]
+    async fn effective_start_is_later_of_entry_and_policy_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let store =
+            Store::load(&AppConfig::with_data_dir(dir.path()), &Keyring::default()).unwrap();
+
+        store
+            .transaction(EntityEmployeeChange::scope(), |tx| {
+                for (id, in_time) in [("a", "2024-05-01"), ("b", "2023-02-01")] {
+                    tx.employee_change.put(
+                        serde_json::from_value(json!({
+                            "id": id,
+                            "employee_id": "e",
+                            "in_time": in_time,
+                        }))
+                        .unwrap(),
+                    )?;
+                }
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        let changes = store.employee_change.read().await;
+
+        let start = |start_date: Option<&str>, employee_id: &str| {
+            effective_start(&changes, &policy(5.0, start_date), employee_id)
+        };
+
+        assert_eq!(start(None, "e"), Some(date("2023-02-01")));
+        assert_eq!(start(Some("2022-01-01"), "e"), Some(date("2023-02-01")));
+        assert_eq!(start(Some("2025-01-01"), "e"), Some(date("2025-01-01")));
+        assert_eq!(start(Some("2025-01-01"), "other"), Some(date("2025-01-01")));
+        assert_eq!(start(None, "other"), None);
+    }
+}
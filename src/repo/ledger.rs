@@ -0,0 +1,285 @@
+use std::collections::BTreeSet;
+
+use anyhow::{Result, bail};
+use chrono::NaiveDate;
+
+use crate::entity::{
+    attendance::{AttendanceType, EntityAttendance},
+    ledger::{DTOAttendanceBalance, DTOLedgerEntry},
+};
+
+use super::{
+    calendar::{WorkCalendar, span_days},
+    index::{IndexKey, date_key},
+    transaction::Transaction,
+};
+
+/// 考勤记录覆盖的每一天及天数, 开始和结束日期标记为半天时计 0.5
+pub fn attendance_days(attendance: &EntityAttendance) -> Vec<(NaiveDate, f64)> {
+    span_days(
+        attendance.start_time,
+        attendance.start_half,
+        attendance.end_time.unwrap_or(attendance.start_time),
+        attendance.end_half,
+    )
+}
+
+/// 考勤记录实际计入的每一天及天数: 请假和调休只计工作日, 加班只计休息日
+pub fn counted_days(
+    calendar: &WorkCalendar,
+    attendance: &EntityAttendance,
+) -> Vec<(NaiveDate, f64)> {
+    let on_workday = attendance.date_type != AttendanceType::Overtime;
+
+    attendance_days(attendance)
+        .into_iter()
+        .filter(|(d, _)| calendar.is_workday(*d) == on_workday)
+        .collect()
+}
+
+/// 人员的考勤台账, 加班计入调休余额, 调休从余额中扣除, 请假只做统计
+pub fn balance(
+    calendar: &WorkCalendar,
+    employee_id: &str,
+    records: Vec<&EntityAttendance>,
+) -> DTOAttendanceBalance {
+    // 同一天开始的加班先于调休计入
+    let mut records = records;
+    records.sort_by_key(|p| (p.start_time, p.date_type != AttendanceType::Overtime));
+
+    let mut res = DTOAttendanceBalance {
+        employee_id: employee_id.to_string(),
+        overtime: 0.0,
+        compensatory_leave: 0.0,
+        leave: 0.0,
+        balance: 0.0,
+        entries: Vec::new(),
+    };
+
+    for p in records {
+        let days: f64 = counted_days(calendar, p).iter().map(|(_, d)| d).sum();
+
+        match p.date_type {
+            AttendanceType::Overtime => {
+                res.overtime += days;
+                res.balance += days;
+            }
+            AttendanceType::CompensatoryLeave => {
+                res.compensatory_leave += days;
+                res.balance -= days;
+            }
+            AttendanceType::Leave => res.leave += days,
+        }
+
+        res.entries.push(DTOLedgerEntry {
+            attendance_id: p.id.clone(),
+            date_type: p.date_type.clone(),
+            start_time: p.start_time,
+            end_time: p.end_time,
+            start_half: p.start_half,
+            end_half: p.end_half,
+            days,
+            balance: res.balance,
+        });
+    }
+
+    res
+}
+
+/// 事务提交前校验调休余额: 考勤记录或特殊日期有变化的人员, 按日期累计的余额在任何时候都不能为负
+///
+/// 删除加班, 修改加班的类型或日期, 从回收站恢复调休, 以及特殊日期改变计入的天数都会影响余额;
+/// 整体替换 (如从备份恢复) 时不校验
+pub fn check_transaction(tx: &Transaction) -> Result<()> {
+    let (Some(attendance), Some(special_dates)) =
+        (tx.attendance.changed()?, tx.special_date.changed()?)
+    else {
+        return Ok(());
+    };
+
+    if attendance.is_empty() && special_dates.is_empty() {
+        return Ok(());
+    }
+
+    let mut employee_ids: BTreeSet<&str> = attendance
+        .iter()
+        .filter_map(|p| p.employee_id.as_deref())
+        .collect();
+
+    // 特殊日期变化的日期上有考勤记录的人员
+    for p in special_dates {
+        let records = tx.attendance.read()?.list_between(
+            IndexKey::Date,
+            Some(&date_key(p.start_time)),
+            Some(&date_key(p.end_time.unwrap_or(p.start_time))),
+            |_| true,
+        );
+
+        employee_ids.extend(records.iter().filter_map(|p| p.employee_id.as_deref()));
+    }
+
+    let calendar = WorkCalendar::new(tx.special_date.read()?);
+
+    for employee_id in employee_ids {
+        let records = tx
+            .attendance
+            .read()?
+            .list_by(IndexKey::EmployeeId, employee_id, |_| true);
+
+        let res = balance(&calendar, employee_id, records);
+
+        if let Some(entry) = res.entries.iter().find(|p| p.balance < 0.0) {
+            bail!(
+                "调休余额不足: 人员 {} 在 {} 开始的调休后余额为 {} 天",
+                employee_id,
+                date_key(entry.start_time),
+                entry.balance
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        config::AppConfig,
+        entity::special_date::EntitySpecialDate,
+        repo::{crypto::Keyring, db::DB, store::Store},
+    };
+
+    use super::*;
+
+    fn attendance(id: &str, date: &str, date_type: &str) -> EntityAttendance {
+        serde_json::from_value(json!({
+            "id": id,
+            "start_time": date,
+            "employee_id": "e",
+            "date_type": date_type,
+            "start_half": false,
+            "end_half": false,
+        }))
+        .unwrap()
+    }
+
+    /// 2026-10-17 (周六) 加班一天, 2026-10-19 (周一) 调休一天
+    async fn store_with_balance(dir: &std::path::Path) -> Store {
+        let store = Store::load(&AppConfig::with_data_dir(dir), &Keyring::default()).unwrap();
+
+        store
+            .transaction(EntityAttendance::scope(), |tx| {
+                tx.attendance
+                    .put(attendance("overtime", "2026-10-17", "Overtime"))?;
+                tx.attendance
+                    .put(attendance("leave", "2026-10-19", "CompensatoryLeave"))?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        store
+    }
+
+    async fn update(store: &Store, record: EntityAttendance) -> Result<()> {
+        store
+            .transaction(EntityAttendance::scope(), |tx| {
+                tx.attendance.put(record)?;
+                Ok(())
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn overtime_changes_cannot_leave_balance_negative() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with_balance(dir.path()).await;
+
+        let res = store
+            .transaction(EntityAttendance::scope(), |tx| {
+                tx.attendance.soft_delete("overtime")?;
+                Ok(())
+            })
+            .await;
+        assert!(res.is_err());
+        assert!(store.attendance.read().await.get("overtime").is_some());
+
+        let res = update(&store, attendance("overtime", "2026-10-17", "Leave")).await;
+        assert!(res.is_err());
+
+        // 移到调休之后的休息日
+        let res = update(&store, attendance("overtime", "2026-10-24", "Overtime")).await;
+        assert!(res.unwrap_err().to_string().contains("2026-10-19"));
+
+        update(&store, attendance("overtime", "2026-10-18", "Overtime"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn running_balance_cannot_go_negative() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with_balance(dir.path()).await;
+
+        // 总余额为 0, 但 2026-10-12 的调休发生在加班之前
+        let res = store
+            .transaction(EntityAttendance::scope(), |tx| {
+                tx.attendance
+                    .put(attendance("early", "2026-10-12", "CompensatoryLeave"))?;
+                tx.attendance
+                    .put(attendance("later", "2026-10-24", "Overtime"))?;
+                Ok(())
+            })
+            .await;
+        assert!(res.unwrap_err().to_string().contains("2026-10-12"));
+        assert!(store.attendance.read().await.get("later").is_none());
+    }
+
+    #[tokio::test]
+    async fn restoring_compensatory_leave_checks_balance() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with_balance(dir.path()).await;
+
+        store
+            .transaction(EntityAttendance::scope(), |tx| {
+                tx.attendance.soft_delete("leave")?;
+                tx.attendance.soft_delete("overtime")?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        let res = store
+            .transaction(EntityAttendance::scope(), |tx| {
+                tx.attendance.restore("leave")?;
+                Ok(())
+            })
+            .await;
+        assert!(res.is_err());
+        assert!(store.attendance.read().await.get("leave").is_none());
+    }
+
+    #[tokio::test]
+    async fn special_date_on_overtime_checks_balance() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with_balance(dir.path()).await;
+
+        let workday: EntitySpecialDate = serde_json::from_value(json!({
+            "id": "s",
+            "start_time": "2026-10-17",
+            "date_type": "Exclude",
+        }))
+        .unwrap();
+
+        let res = store
+            .transaction(EntitySpecialDate::scope(), |tx| {
+                tx.special_date.put(workday)?;
+                Ok(())
+            })
+            .await;
+        assert!(res.is_err());
+        assert!(store.special_date.read().await.get("s").is_none());
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::BTreeSet;
+
+use anyhow::{Result, bail};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    config::{AppConfig, StorageKind},
+    entity::{
+        attendance::EntityAttendance, employee::EntityEmployee,
+        employee_change::EntityEmployeeChange, leave_policy::EntityLeavePolicy,
+        project::EntityProject, special_date::EntitySpecialDate,
+    },
+};
+
+use super::{crypto::Keyring, db::DB, recovery::read_versioned, storage::sqlite, store::Store};
+
+/// 集合数据结构的基础版本, 引入版本号之前的 json 文件 (直接是数组) 视为版本 0
+pub const BASE_VERSION: u32 = 1;
+
+/// json 数据文件的外层结构, 记录数据结构版本
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Envelope<T> {
+    pub version: u32,
+    pub records: T,
+}
+
+/// 单步迁移, 将指定集合的记录从 from 版本升级到 from + 1
+pub struct Migration {
+    /// 集合名, 参考 [DB::collection_name](super::db::DB::collection_name)
+    pub collection: &'static str,
+    pub from: u32,
+    /// 迁移说明, 会输出在迁移报告中
+    pub description: &'static str,
+    /// 对单条记录进行升级
+    pub up: fn(&mut Map<String, Value>) -> Result<()>,
+}
+
+/// 迁移注册表
+///
+/// 修改实体结构且旧数据无法直接反序列化时, 在此追加一步迁移, from 为当前最新版本
+pub fn registry() -> Vec<Migration> {
+    let mut migrations: Vec<Migration> = [
+        "project",
+        "employee",
+        "employee_change",
+        "attendance",
+        "special_date",
+        "leave_policy",
+    ]
+    .into_iter()
+    .map(|collection| Migration {
+        collection,
+        from: 1,
+        description: "为引入版本号之前的记录补充版本号 1",
+        up: add_version,
+    })
+    .collect();
+
+    migrations.extend(
+        ["employee_change", "attendance"].map(|collection| Migration {
+            collection,
+            from: 2,
+            description: "将 nullify 清空的关联字段由空字符串改为 null",
+            up: null_empty_references,
+        }),
+    );
+
+    migrations.push(Migration {
+        collection: "attendance",
+        from: 3,
+        description: "日期索引改为包含记录覆盖的每一天, 记录内容不变, sqlite 后端重建索引表",
+        up: keep_record,
+    });
+
+    migrations.push(Migration {
+        collection: "special_date",
+        from: 2,
+        description: "日期索引改为包含记录覆盖的每一天, 记录内容不变, sqlite 后端重建索引表",
+        up: keep_record,
+    });
+
+    migrations
+}
+
+/// 引入版本号之前的记录没有 version 字段, 按新增记录的规则设置为 1
+fn add_version(record: &mut Map<String, Value>) -> Result<()> {
+    let version = record.get("version").and_then(Value::as_u64).unwrap_or(0);
+
+    record.insert("version".to_string(), Value::from(version.max(1)));
+
+    Ok(())
+}
+
+/// 关联字段改为可空之前, nullify 将其设置为空字符串
+fn null_empty_references(record: &mut Map<String, Value>) -> Result<()> {
+    for field in ["employee_id", "project_id"] {
+        if record.get(field).and_then(Value::as_str) == Some("") {
+            record.insert(field.to_string(), Value::Null);
+        }
+    }
+
+    Ok(())
+}
+
+/// 只改变索引的迁移, 记录内容不变; 迁移后 sqlite 后端会根据记录重建索引表
+fn keep_record(_record: &mut Map<String, Value>) -> Result<()> {
+    Ok(())
+}
+
+/// 集合当前的最新版本
+pub fn latest_version(collection: &str) -> u32 {
+    BASE_VERSION
+        + registry()
+            .iter()
+            .filter(|m| m.collection == collection)
+            .count() as u32
+}
+
+/// 将一条记录从 from 版本逐步升级到最新版本
+pub fn migrate_record(collection: &str, from: u32, record: &mut Value) -> Result<()> {
+    let latest = latest_version(collection);
+
+    if from > latest {
+        bail!(
+            "集合 {} 的数据版本 {} 高于程序支持的版本 {}, 请升级程序",
+            collection,
+            from,
+            latest
+        );
+    }
+
+    let mut steps: Vec<Migration> = registry()
+        .into_iter()
+        .filter(|m| m.collection == collection && m.from >= from)
+        .collect();
+    steps.sort_by_key(|m| m.from);
+
+    for step in steps {
+        match record {
+            Value::Object(map) => (step.up)(map)?,
+            _ => bail!("记录不是对象, 无法迁移"),
+        }
+    }
+
+    Ok(())
+}
+
+/// 执行 migrate 命令
+///
+/// dry_run 时只读取数据并输出每个集合的迁移报告, 否则加载全部集合, 加载过程中会完成迁移并写回
+pub fn run(config: &AppConfig, keyring: &Keyring, dry_run: bool) -> Result<()> {
+    if !dry_run {
+        Store::load(config, keyring)?;
+
+        info!("迁移完成");
+        return Ok(());
+    }
+
+    let reports = [
+        plan::<EntityProject>(config, keyring)?,
+        plan::<EntityEmployee>(config, keyring)?,
+        plan::<EntityEmployeeChange>(config, keyring)?,
+        plan::<EntityAttendance>(config, keyring)?,
+        plan::<EntitySpecialDate>(config, keyring)?,
+        plan::<EntityLeavePolicy>(config, keyring)?,
+    ];
+
+    for report in reports.iter().flatten() {
+        report.print();
+    }
+
+    Ok(())
+}
+
+/// 生成单个集合的迁移报告, 数据尚不存在时返回 None
+fn plan<D: DB>(config: &AppConfig, keyring: &Keyring) -> Result<Option<MigrationReport>> {
+    let collection = D::collection_name();
+
+    let (source, versioned) = match config.storage {
+        StorageKind::Json => {
+            let path = D::get_path(config);
+            let versioned = if path.exists() {
+                Some(read_versioned(&path, keyring)?)
+            } else {
+                None
+            };
+            (path.display().to_string(), versioned)
+        }
+        StorageKind::Sqlite => (
+            format!("{} 表 {}", config.sqlite_path().display(), collection),
+            sqlite::read_versioned(&config.sqlite_path(), collection)?,
+        ),
+    };
+
+    Ok(versioned.map(|(from, records)| MigrationReport::plan(collection, source, from, records)))
+}
+
+/// 单个集合的迁移计划
+#[derive(Debug)]
+pub struct MigrationReport {
+    pub collection: String,
+    /// 数据来源, 文件路径或数据库表
+    pub source: String,
+    pub from: u32,
+    pub to: u32,
+    /// 将要执行的迁移说明
+    pub steps: Vec<String>,
+    /// 会发生变化的记录及其变化的字段
+    pub changes: Vec<(String, Vec<String>)>,
+    /// 迁移失败的记录及错误
+    pub errors: Vec<(String, String)>,
+}
+
+impl MigrationReport {
+    /// 模拟迁移给定记录, 不做任何写入
+    pub fn plan(collection: &str, source: String, from: u32, records: Vec<Value>) -> Self {
+        let to = latest_version(collection);
+
+        let steps = registry()
+            .into_iter()
+            .filter(|m| m.collection == collection && m.from >= from)
+            .map(|m| format!("v{} -> v{}: {}", m.from, m.from + 1, m.description))
+            .collect();
+
+        let mut report = MigrationReport {
+            collection: collection.to_string(),
+            source,
+            from,
+            to,
+            steps,
+            changes: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        for before in records {
+            let id = before
+                .get("id")
+                .and_then(Value::as_str)
+                .unwrap_or("<无 id>")
+                .to_string();
+
+            let mut after = before.clone();
+
+            match migrate_record(collection, from, &mut after) {
+                Ok(()) => {
+                    let fields = changed_fields(&before, &after);
+                    if !fields.is_empty() {
+                        report.changes.push((id, fields));
+                    }
+                }
+                Err(err) => report.errors.push((id, format!("{:#}", err))),
+            }
+        }
+
+        report
+    }
+
+    pub fn print(&self) {
+        if self.from == self.to {
+            println!(
+                "[{}] {} 已是最新版本 v{}",
+                self.collection, self.source, self.to
+            );
+            return;
+        }
+
+        println!(
+            "[{}] {} v{} -> v{}",
+            self.collection, self.source, self.from, self.to
+        );
+
+        if self.from < BASE_VERSION {
+            println!("  - 添加版本信息");
+        }
+
+        for step in &self.steps {
+            println!("  - {}", step);
+        }
+
+        println!("  {} 条记录将发生变化", self.changes.len());
+
+        for (id, fields) in &self.changes {
+            println!("    {}: {}", id, fields.join(", "));
+        }
+
+        for (id, err) in &self.errors {
+            println!("    {} 迁移失败, 将被隔离: {}", id, err);
+        }
+    }
+}
+
+/// 比较两条记录, 返回新增, 删除或值发生变化的字段
+fn changed_fields(before: &Value, after: &Value) -> Vec<String> {
+    let (Some(before), Some(after)) = (before.as_object(), after.as_object()) else {
+        return if before == after {
+            Vec::new()
+        } else {
+            vec!["<整条记录>".to_string()]
+        };
+    };
+
+    let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+
+    keys.into_iter()
+        .filter(|k| before.get(*k) != after.get(*k))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// 引入版本号之前的项目数据文件, 直接是数组
+    fn write_v0(config: &AppConfig) -> Vec<u8> {
+        let content = serde_json::to_vec_pretty(&json!([{
+            "id": "p1",
+            "name": "项目",
+            "code": "P1",
+            "release_date": "2024-01-01",
+            "plan_delivery_date": "2024-06-30",
+            "tech_days": 10,
+            "test_days": 5,
+            "price": 1.0,
+            "pm": "pm",
+        }]))
+        .unwrap();
+
+        std::fs::write(EntityProject::get_path(config), &content).unwrap();
+
+        content
+    }
+
+    #[test]
+    fn dry_run_reports_without_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AppConfig::with_data_dir(dir.path());
+        let content = write_v0(&config);
+
+        let report = plan::<EntityProject>(&config, &Keyring::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!((report.from, report.to), (0, 2));
+        assert_eq!(report.steps.len(), 1);
+        assert_eq!(
+            report.changes,
+            [("p1".to_string(), vec!["version".to_string()])]
+        );
+        assert!(report.errors.is_empty());
+
+        assert!(
+            plan::<EntityEmployee>(&config, &Keyring::default())
+                .unwrap()
+                .is_none()
+        );
+
+        run(&config, &Keyring::default(), true).unwrap();
+
+        let after = std::fs::read(EntityProject::get_path(&config)).unwrap();
+        assert_eq!(after, content);
+    }
+
+    #[test]
+    fn migrates_v0_file_to_latest() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AppConfig::with_data_dir(dir.path());
+        write_v0(&config);
+
+        run(&config, &Keyring::default(), false).unwrap();
+
+        let (version, records) =
+            read_versioned(&EntityProject::get_path(&config), &Keyring::default()).unwrap();
+        assert_eq!(version, latest_version("project"));
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["version"], 1);
+
+        let report = plan::<EntityProject>(&config, &Keyring::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(report.from, report.to);
+        assert!(report.changes.is_empty());
+    }
+
+    #[test]
+    fn empty_references_become_null() {
+        let mut record = json!({
+            "id": "c1",
+            "employee_id": "",
+            "project_id": "p1",
+            "in_time": "2024-01-01",
+            "version": 3,
+        });
+
+        migrate_record("employee_change", 2, &mut record).unwrap();
+
+        assert!(record["employee_id"].is_null());
+        assert_eq!(record["project_id"], "p1");
+        assert_eq!(record["version"], 3);
+        assert_eq!(latest_version("employee_change"), 3);
+        assert_eq!(latest_version("project"), 2);
+    }
+}
@@ -1,50 +1,445 @@
-use std::path::Path;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use db::{Collection, DB, Record};
+use index::{IndexKey, date_key, date_keys};
+use store::StoreRead;
+use transaction::{CollectionId, Scope, Staged, Transaction};
 
-use db::DB;
-
-use crate::entity::{
-    attendance::EntityAttendance, employee::EntityEmployee, employee_change::EntityEmployeeChange,
-    project::EntityProject, special_date::EntitySpecialDate,
+use crate::{
+    config::AppConfig,
+    entity::{
+        attendance::EntityAttendance, employee::EntityEmployee,
+        employee_change::EntityEmployeeChange, leave_policy::EntityLeavePolicy,
+        project::EntityProject, special_date::EntitySpecialDate,
+    },
 };
 
+pub mod audit;
+pub mod backup;
+pub mod calendar;
+pub mod crypto;
 pub mod db;
+pub mod effort;
+pub mod filter;
+pub mod holiday;
+pub mod index;
+pub mod integrity;
+pub mod journal;
+pub mod key_rotation;
+pub mod leave;
+pub mod ledger;
+pub mod migration;
+pub mod persist;
+pub mod query;
+pub mod recovery;
+pub mod reload;
+pub mod storage;
+pub mod store;
+pub mod transaction;
+pub mod trash;
+pub mod undo;
+
+impl Record for EntityProject {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u64) {
+        self.version = version;
+    }
+
+    fn deleted_at(&self) -> Option<DateTime<Local>> {
+        self.deleted_at
+    }
+
+    fn set_deleted_at(&mut self, deleted_at: Option<DateTime<Local>>) {
+        self.deleted_at = deleted_at;
+    }
+}
 
 impl DB for EntityProject {
     type Entity = EntityProject;
 
-    fn get_path() -> impl AsRef<Path> {
-        "/Users/lixianjie/project/proj/po_manager/db/project.json"
+    const ID: CollectionId = CollectionId::Project;
+
+    fn collection_name() -> &'static str {
+        "project"
+    }
+
+    /// 删除时按规则处理引用它的入项记录
+    fn scope() -> Scope {
+        Scope::new()
+            .write(CollectionId::Project)
+            .write(CollectionId::EmployeeChange)
+    }
+
+    fn file_name(config: &AppConfig) -> &str {
+        &config.collections.project
+    }
+
+    fn staged<'a, 'b>(tx: &'b mut Transaction<'a>) -> &'b mut Staged<'a, Self::Entity> {
+        &mut tx.project
+    }
+
+    fn read<'a, 'b>(all: &'b StoreRead<'a>) -> &'b Collection<Self::Entity> {
+        &all.project
+    }
+
+    fn check_unreferenced(tx: &Transaction, id: &str) -> Result<()> {
+        integrity::check_project_unreferenced(tx, id)
+    }
+}
+
+impl Record for EntityEmployee {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u64) {
+        self.version = version;
+    }
+
+    fn deleted_at(&self) -> Option<DateTime<Local>> {
+        self.deleted_at
+    }
+
+    fn set_deleted_at(&mut self, deleted_at: Option<DateTime<Local>>) {
+        self.deleted_at = deleted_at;
     }
 }
 
 impl DB for EntityEmployee {
     type Entity = EntityEmployee;
 
-    fn get_path() -> impl AsRef<Path> {
-        "/Users/lixianjie/project/proj/po_manager/db/employee.json"
+    const ID: CollectionId = CollectionId::Employee;
+
+    fn collection_name() -> &'static str {
+        "employee"
+    }
+
+    /// 删除时按规则处理引用他的入项记录, 考勤记录和年假政策, 考勤记录变化时按工作日历校验调休余额
+    fn scope() -> Scope {
+        Scope::new()
+            .write(CollectionId::Employee)
+            .write(CollectionId::EmployeeChange)
+            .write(CollectionId::Attendance)
+            .read(CollectionId::SpecialDate)
+            .write(CollectionId::LeavePolicy)
+    }
+
+    fn file_name(config: &AppConfig) -> &str {
+        &config.collections.employee
+    }
+
+    fn staged<'a, 'b>(tx: &'b mut Transaction<'a>) -> &'b mut Staged<'a, Self::Entity> {
+        &mut tx.employee
+    }
+
+    fn read<'a, 'b>(all: &'b StoreRead<'a>) -> &'b Collection<Self::Entity> {
+        &all.employee
+    }
+
+    fn check_unreferenced(tx: &Transaction, id: &str) -> Result<()> {
+        integrity::check_employee_unreferenced(tx, id)
+    }
+}
+
+impl Record for EntityEmployeeChange {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u64) {
+        self.version = version;
+    }
+
+    fn deleted_at(&self) -> Option<DateTime<Local>> {
+        self.deleted_at
+    }
+
+    fn set_deleted_at(&mut self, deleted_at: Option<DateTime<Local>>) {
+        self.deleted_at = deleted_at;
+    }
+
+    fn index_keys(&self) -> Vec<(IndexKey, String)> {
+        let mut keys = vec![(IndexKey::Date, date_key(self.in_time))];
+
+        if let Some(employee_id) = &self.employee_id {
+            keys.push((IndexKey::EmployeeId, employee_id.clone()));
+        }
+
+        if let Some(project_id) = &self.project_id {
+            keys.push((IndexKey::ProjectId, project_id.clone()));
+        }
+
+        keys
     }
 }
 
 impl DB for EntityEmployeeChange {
     type Entity = EntityEmployeeChange;
 
-    fn get_path() -> impl AsRef<Path> {
-        "/Users/lixianjie/project/proj/po_manager/db/employee_change.json"
+    const ID: CollectionId = CollectionId::EmployeeChange;
+
+    fn collection_name() -> &'static str {
+        "employee_change"
+    }
+
+    /// 校验引用的人员和项目
+    fn scope() -> Scope {
+        Scope::new()
+            .read(CollectionId::Project)
+            .read(CollectionId::Employee)
+            .write(CollectionId::EmployeeChange)
+    }
+
+    fn file_name(config: &AppConfig) -> &str {
+        &config.collections.employee_change
+    }
+
+    fn staged<'a, 'b>(tx: &'b mut Transaction<'a>) -> &'b mut Staged<'a, Self::Entity> {
+        &mut tx.employee_change
+    }
+
+    fn read<'a, 'b>(all: &'b StoreRead<'a>) -> &'b Collection<Self::Entity> {
+        &all.employee_change
+    }
+
+    fn check_references(tx: &Transaction, record: &Self::Entity) -> Result<()> {
+        integrity::check_employee_change(tx, record)
+    }
+}
+
+impl Record for EntityAttendance {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u64) {
+        self.version = version;
+    }
+
+    fn deleted_at(&self) -> Option<DateTime<Local>> {
+        self.deleted_at
+    }
+
+    fn set_deleted_at(&mut self, deleted_at: Option<DateTime<Local>>) {
+        self.deleted_at = deleted_at;
+    }
+
+    fn index_keys(&self) -> Vec<(IndexKey, String)> {
+        let mut keys = date_keys(self.start_time, self.end_time);
+
+        if let Some(employee_id) = &self.employee_id {
+            keys.push((IndexKey::EmployeeId, employee_id.clone()));
+        }
+
+        keys
     }
 }
 
 impl DB for EntityAttendance {
     type Entity = EntityAttendance;
 
-    fn get_path() -> impl AsRef<Path> {
-        "/Users/lixianjie/project/proj/po_manager/db/attendance.json"
+    const ID: CollectionId = CollectionId::Attendance;
+
+    fn collection_name() -> &'static str {
+        "attendance"
+    }
+
+    /// 校验引用的人员, 按工作日历计算调休余额 (提交前校验, 参考 [ledger::check_transaction]) 及年假额度,
+    /// 年假从人员入项的年份开始结转
+    fn scope() -> Scope {
+        Scope::new()
+            .read(CollectionId::Employee)
+            .read(CollectionId::EmployeeChange)
+            .write(CollectionId::Attendance)
+            .read(CollectionId::SpecialDate)
+            .read(CollectionId::LeavePolicy)
+    }
+
+    fn file_name(config: &AppConfig) -> &str {
+        &config.collections.attendance
+    }
+
+    fn staged<'a, 'b>(tx: &'b mut Transaction<'a>) -> &'b mut Staged<'a, Self::Entity> {
+        &mut tx.attendance
+    }
+
+    fn read<'a, 'b>(all: &'b StoreRead<'a>) -> &'b Collection<Self::Entity> {
+        &all.attendance
+    }
+
+    fn check_references(tx: &Transaction, record: &Self::Entity) -> Result<()> {
+        integrity::check_attendance(tx, record)
+    }
+
+    fn validate(_tx: &Transaction, record: &Self::Entity) -> Result<()> {
+        calendar::check_span(record.start_time, record.end_time)
+    }
+}
+
+impl Record for EntitySpecialDate {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u64) {
+        self.version = version;
+    }
+
+    fn deleted_at(&self) -> Option<DateTime<Local>> {
+        self.deleted_at
+    }
+
+    fn set_deleted_at(&mut self, deleted_at: Option<DateTime<Local>>) {
+        self.deleted_at = deleted_at;
+    }
+
+    fn index_keys(&self) -> Vec<(IndexKey, String)> {
+        date_keys(self.start_time, self.end_time)
     }
 }
 
 impl DB for EntitySpecialDate {
     type Entity = EntitySpecialDate;
 
-    fn get_path() -> impl AsRef<Path> {
-        "/Users/lixianjie/project/proj/po_manager/db/special_date.json"
+    const ID: CollectionId = CollectionId::SpecialDate;
+
+    fn collection_name() -> &'static str {
+        "special_date"
+    }
+
+    /// 特殊日期改变考勤记录计入的天数, 提交前校验受影响人员的调休余额
+    fn scope() -> Scope {
+        Scope::new()
+            .read(CollectionId::Attendance)
+            .write(CollectionId::SpecialDate)
+    }
+
+    fn file_name(config: &AppConfig) -> &str {
+        &config.collections.special_date
+    }
+
+    fn staged<'a, 'b>(tx: &'b mut Transaction<'a>) -> &'b mut Staged<'a, Self::Entity> {
+        &mut tx.special_date
+    }
+
+    fn read<'a, 'b>(all: &'b StoreRead<'a>) -> &'b Collection<Self::Entity> {
+        &all.special_date
+    }
+
+    fn validate(_tx: &Transaction, record: &Self::Entity) -> Result<()> {
+        calendar::check_span(record.start_time, record.end_time)
+    }
+}
+
+impl Record for EntityLeavePolicy {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u64) {
+        self.version = version;
+    }
+
+    fn deleted_at(&self) -> Option<DateTime<Local>> {
+        self.deleted_at
+    }
+
+    fn set_deleted_at(&mut self, deleted_at: Option<DateTime<Local>>) {
+        self.deleted_at = deleted_at;
+    }
+
+    fn index_keys(&self) -> Vec<(IndexKey, String)> {
+        self.employee_ids
+            .iter()
+            .map(|p| (IndexKey::EmployeeId, p.clone()))
+            .collect()
+    }
+}
+
+impl DB for EntityLeavePolicy {
+    type Entity = EntityLeavePolicy;
+
+    const ID: CollectionId = CollectionId::LeavePolicy;
+
+    fn collection_name() -> &'static str {
+        "leave_policy"
+    }
+
+    /// 校验适用的人员
+    fn scope() -> Scope {
+        Scope::new()
+            .read(CollectionId::Employee)
+            .write(CollectionId::LeavePolicy)
+    }
+
+    fn file_name(config: &AppConfig) -> &str {
+        &config.collections.leave_policy
+    }
+
+    fn staged<'a, 'b>(tx: &'b mut Transaction<'a>) -> &'b mut Staged<'a, Self::Entity> {
+        &mut tx.leave_policy
+    }
+
+    fn read<'a, 'b>(all: &'b StoreRead<'a>) -> &'b Collection<Self::Entity> {
+        &all.leave_policy
+    }
+
+    fn check_references(tx: &Transaction, record: &Self::Entity) -> Result<()> {
+        integrity::check_leave_policy(tx, record)
+    }
+
+    fn validate(_tx: &Transaction, record: &Self::Entity) -> Result<()> {
+        leave::check_policy(record)
+    }
+}
+
+/// 各模块测试共用的构造函数
+#[cfg(test)]
+pub mod tests {
+    use chrono::NaiveDate;
+    use serde_json::json;
+
+    use crate::entity::special_date::EntitySpecialDate;
+
+    /// 解析 年-月-日 格式的日期
+    pub fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    /// 单日的计入假日的特殊日期
+    pub fn special_date(id: &str, date: &str) -> EntitySpecialDate {
+        serde_json::from_value(json!({
+            "id": id,
+            "start_time": date,
+            "date_type": "Include",
+        }))
+        .unwrap()
     }
 }
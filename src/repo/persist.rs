@@ -0,0 +1,363 @@
+use std::{
+    cell::Cell,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{Method, header::CONTENT_LENGTH},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body_util::BodyExt;
+use log::warn;
+use serde_json::{Value, json};
+use tokio::sync::{Notify, watch};
+
+use crate::{
+    config::PersistenceConfig,
+    result::{response::AppResponse, response_code::AppResponseCode},
+};
+
+use super::store::Store;
+
+/// 请求头, 值为 true 时等待本次变更写入磁盘后再响应, 为 false 时不等待, 未设置时按配置
+pub const DURABLE_HEADER: &str = "x-durable";
+
+/// 写入失败后重试的间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 已写入磁盘的进度
+#[derive(Debug, Clone, Default)]
+pub struct FlushState {
+    /// 已写入磁盘的变更序号
+    pub seq: u64,
+    /// 最近一次写入失败的错误, 写入成功后清空
+    pub error: Option<String>,
+    /// 最近一次失败的写入所覆盖的变更序号, 只有序号不大于它的变更受该错误影响
+    pub failed: u64,
+}
+
+impl FlushState {
+    /// 序号为 seq 的变更的写入失败错误, 之后的变更不受此前失败的影响
+    fn error_for(&self, seq: u64) -> Option<&str> {
+        self.error.as_deref().filter(|_| self.failed >= seq)
+    }
+}
+
+/// 后台持久化的状态, 由全部集合共享
+///
+/// 集合的变更只修改内存并标记为脏, 由 [spawn_persister] 启动的后台任务合并一段时间内的变更后批量写入
+pub struct Persister {
+    /// 变更序号, 每次变更加一
+    seq: AtomicU64,
+    /// 有新的变更需要写入
+    dirty: Notify,
+    /// 有请求在等待写入, 不再等待 debounce
+    urgent: Notify,
+    flushed: watch::Sender<FlushState>,
+    /// 合并变更的等待时间
+    debounce: Duration,
+    /// 是否所有写请求都等待写入磁盘后再响应
+    wait_for_flush: bool,
+}
+
+impl Persister {
+    pub fn new(config: &PersistenceConfig) -> Self {
+        Persister {
+            seq: AtomicU64::new(0),
+            dirty: Notify::new(),
+            urgent: Notify::new(),
+            flushed: watch::Sender::new(FlushState::default()),
+            debounce: Duration::from_millis(config.debounce_ms),
+            wait_for_flush: config.wait_for_flush,
+        }
+    }
+
+    /// 标记有新的变更, 返回该变更的序号; 处理请求期间同时记为该请求的变更, 参考 [durability_ack]
+    pub fn mark_dirty(&self) -> u64 {
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst) + 1;
+        let _ = REQUEST_SEQ.try_with(|p| p.latest.set(seq));
+        self.dirty.notify_one();
+        seq
+    }
+
+    /// 当前最新的变更序号
+    pub fn current_seq(&self) -> u64 {
+        self.seq.load(Ordering::SeqCst)
+    }
+
+    /// 记录一次写入的结果, target 为写入前读取的变更序号
+    pub fn report(&self, target: u64, res: &Result<()>) {
+        self.flushed.send_modify(|state| match res {
+            Ok(()) => {
+                state.seq = state.seq.max(target);
+                state.error = None;
+            }
+            Err(err) => {
+                state.error = Some(format!("{:#}", err));
+                state.failed = state.failed.max(target);
+            }
+        });
+    }
+
+    /// 等待序号不大于 seq 的变更全部写入磁盘, 包含该变更的写入失败时返回错误
+    ///
+    /// 等待期间后台任务不再等待 debounce, 立即写入
+    pub async fn wait_flushed(&self, seq: u64) -> Result<()> {
+        let mut rx = self.flushed.subscribe();
+
+        if rx.borrow().seq < seq {
+            self.urgent.notify_one();
+        }
+
+        let state = rx
+            .wait_for(|state| state.seq >= seq || state.error_for(seq).is_some())
+            .await?;
+
+        match state.error_for(seq) {
+            Some(err) if state.seq < seq => Err(anyhow!("数据写入失败: {}", err)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 启动后台持久化任务
+///
+/// 收到变更通知后等待 debounce, 将这段时间内的变更合并后一次写入, 有请求在等待写入时提前写入;
+/// 写入失败时保留变更并稍后重试
+pub fn spawn_persister(store: Store) {
+    tokio::spawn(async move {
+        let persister = store.persister.clone();
+
+        loop {
+            persister.dirty.notified().await;
+
+            tokio::select! {
+                _ = tokio::time::sleep(persister.debounce) => {}
+                _ = persister.urgent.notified() => {}
+            }
+
+            if let Err(err) = store.flush_async().await {
+                warn!("后台写入失败, 稍后重试: {:#}", err);
+                tokio::time::sleep(RETRY_INTERVAL).await;
+                persister.dirty.notify_one();
+            }
+        }
+    });
+}
+
+/// 请求产生的变更序号
+#[derive(Default)]
+struct RequestSeq {
+    /// 最近一次变更的序号, 所在事务可能尚未提交
+    latest: Cell<u64>,
+    /// 已提交的事务中最后一次变更的序号
+    committed: Cell<u64>,
+}
+
+tokio::task_local! {
+    /// 当前请求产生的变更序号
+    static REQUEST_SEQ: RequestSeq;
+}
+
+/// 事务结束时调用, 提交时其中的变更计入当前请求, 回滚时丢弃
+pub fn end_transaction(committed: bool) {
+    let _ = REQUEST_SEQ.try_with(|p| {
+        if committed {
+            p.committed.set(p.latest.get());
+        } else {
+            p.latest.set(p.committed.get());
+        }
+    });
+}
+
+/// 写请求完成后, 按需等待本次请求提交的变更写入磁盘再响应
+///
+/// 仅对非 GET 请求, 按请求头 x-durable 决定是否等待, 未设置时按配置的 wait_for_flush; 请求失败或没有提交变更时不等待.
+/// 写入失败时变更已在内存中生效并会继续重试, 响应码改为 [AppResponseCode::NotDurable], 保留原响应的数据
+pub async fn durability_ack(
+    State(persister): State<Arc<Persister>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let header = request.headers().get(DURABLE_HEADER).map(|v| v.as_bytes());
+
+    let durable = request.method() != Method::GET
+        && match header {
+            Some(v) if v.eq_ignore_ascii_case(b"true") => true,
+            Some(v) if v.eq_ignore_ascii_case(b"false") => false,
+            _ => persister.wait_for_flush,
+        };
+
+    if !durable {
+        return next.run(request).await;
+    }
+
+    let (response, seq) = REQUEST_SEQ
+        .scope(RequestSeq::default(), async {
+            let response = next.run(request).await;
+            (response, REQUEST_SEQ.with(|p| p.committed.get()))
+        })
+        .await;
+
+    if seq == 0 || !response.status().is_success() {
+        return response;
+    }
+
+    match persister.wait_flushed(seq).await {
+        Ok(()) => response,
+        Err(err) => not_durable(response, &format!("{:#}", err)).await,
+    }
+}
+
+/// 将响应码改为 [AppResponseCode::NotDurable], 保留原响应的数据和响应头
+async fn not_durable(response: Response, err: &str) -> Response {
+    let msg = format!(
+        "变更已生效, 但尚未写入磁盘, 将自动重试写入, 请勿重复提交: {}",
+        err
+    );
+
+    let (mut parts, body) = response.into_parts();
+
+    let mut body = match body.collect().await {
+        Ok(collected) => serde_json::from_slice(&collected.to_bytes()).unwrap_or(Value::Null),
+        Err(_) => Value::Null,
+    };
+
+    let Some(fields) = body.as_object_mut() else {
+        return AppResponse::<()>::new()
+            .code(AppResponseCode::NotDurable)
+            .msg(msg)
+            .into_response();
+    };
+
+    fields.insert("code".to_string(), json!(AppResponseCode::NotDurable));
+    fields.insert("msg".to_string(), json!(msg));
+
+    parts.headers.remove(CONTENT_LENGTH);
+
+    Response::from_parts(parts, Body::from(body.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{entity::employee::EntityEmployee, repo::db::DB, tests::TestApp};
+
+    use super::*;
+
+    fn persister() -> Persister {
+        Persister::new(&PersistenceConfig::default())
+    }
+
+    #[tokio::test]
+    async fn earlier_failure_does_not_fail_later_changes() {
+        let persister = persister();
+
+        let first = persister.mark_dirty();
+        persister.report(first, &Err(anyhow!("磁盘已满")));
+        assert!(persister.wait_flushed(first).await.is_err());
+
+        // 失败之后的变更只受覆盖它的写入结果影响
+        let second = persister.mark_dirty();
+        let waiter = persister.wait_flushed(second);
+
+        persister.report(second, &Ok(()));
+        assert!(waiter.await.is_ok());
+        assert!(persister.wait_flushed(first).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn failure_covering_change_fails_waiter() {
+        let persister = persister();
+
+        let first = persister.mark_dirty();
+        persister.report(first, &Err(anyhow!("磁盘已满")));
+
+        let second = persister.mark_dirty();
+        persister.report(second, &Err(anyhow!("磁盘已满")));
+
+        let err = persister.wait_flushed(second).await.unwrap_err();
+        assert!(err.to_string().contains("磁盘已满"));
+    }
+
+    async fn update(app: &TestApp, id: &str, name: &str, durable: &str) -> Value {
+        let (_, res) = app
+            .send(
+                "POST",
+                &format!("/employee/update/{}", id),
+                &[(DURABLE_HEADER, durable)],
+                Some(json!({ "name": name })),
+            )
+            .await;
+        res
+    }
+
+    #[tokio::test]
+    async fn failed_flush_only_affects_requests_that_changed_data() {
+        let app = TestApp::new().await;
+
+        let (_, res) = app
+            .send(
+                "POST",
+                "/employee/create",
+                &[],
+                Some(json!({ "name": "人员", "position": "dev" })),
+            )
+            .await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+        let id = res["data"]["id"].as_str().unwrap().to_string();
+
+        // 之后人员集合的写入一直失败
+        let path = EntityEmployee::get_path(&app.config);
+        fs::remove_file(&path).unwrap();
+        fs::create_dir(&path).unwrap();
+
+        let res = update(&app, &id, "a", "false").await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+
+        // 没有变更的请求保留原响应, 不受其他变更写入失败的影响
+        let (_, res) = app
+            .send(
+                "POST",
+                "/employee/update/missing",
+                &[],
+                Some(json!({ "name": "b" })),
+            )
+            .await;
+        assert_eq!(res["code"], "Err", "{}", res);
+        assert_eq!(res["msg"], "记录不存在");
+
+        // 提交前校验失败而回滚的事务同样不等待写入: 没有加班时不能调休
+        let (_, res) = app
+            .send(
+                "POST",
+                "/attendance/create",
+                &[],
+                Some(json!({
+                    "start_time": "2025-01-06",
+                    "employee_id": id,
+                    "date_type": "CompensatoryLeave",
+                    "start_half": false,
+                    "end_half": false,
+                })),
+            )
+            .await;
+        assert_eq!(res["code"], "Err", "{}", res);
+        assert!(!res["msg"].as_str().unwrap().contains("写入"), "{}", res);
+
+        // 本次变更写入失败时说明变更已生效, 保留原响应的数据
+        let res = update(&app, &id, "c", "true").await;
+        assert_eq!(res["code"], "NotDurable", "{}", res);
+        assert_eq!(res["data"]["name"], "c");
+        assert_eq!(app.store.employee.read().await.get(&id).unwrap().name, "c");
+    }
+}
@@ -0,0 +1,54 @@
+use super::{db::Record, filter::Filter, index::IndexKey};
+
+/// 通过索引缩小范围的条件, 参考 [IndexKey]
+#[derive(Clone, Debug)]
+pub enum KeyQuery {
+    /// 索引值等于给定值
+    Eq(IndexKey, String),
+    /// 索引值在两者之间 (包含两端), 为 None 时不限制
+    Range(IndexKey, Option<String>, Option<String>),
+}
+
+/// 集合查询条件, 由 [Collection::query](super::db::Collection::query) 执行
+///
+/// 存储后端支持时直接在后端查询, 否则在内存中通过索引查询, 两者结果相同, 新记录在前
+#[derive(Clone, Debug, Default)]
+pub struct Query {
+    /// 索引条件
+    pub key: Option<KeyQuery>,
+    /// 为 true 时只查询回收站中的记录, 否则只查询未删除的记录
+    pub deleted: bool,
+    /// 过滤条件, 按记录序列化后的字段求值
+    pub filter: Option<Filter>,
+}
+
+impl Query {
+    /// 未删除的记录
+    pub fn active(key: Option<KeyQuery>) -> Self {
+        Query {
+            key,
+            deleted: false,
+            filter: None,
+        }
+    }
+
+    /// 回收站中的记录
+    pub fn trash() -> Self {
+        Query {
+            key: None,
+            deleted: true,
+            filter: None,
+        }
+    }
+
+    /// 加上过滤条件
+    pub fn filter(self, filter: Option<Filter>) -> Self {
+        Query { filter, ..self }
+    }
+
+    /// 记录是否满足除索引条件之外的条件
+    pub fn matches<T: Record>(&self, record: &T) -> bool {
+        record.is_deleted() == self.deleted
+            && self.filter.as_ref().is_none_or(|f| f.matches(record))
+    }
+}
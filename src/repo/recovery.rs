@@ -0,0 +1,468 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::Local;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::{Value, value::RawValue};
+
+use super::{
+    crypto::Keyring,
+    db::{Record, write_atomic},
+    journal::{BadLine, Journal},
+    migration::{Envelope, latest_version, migrate_record},
+};
+
+/// 数据文件加载结果
+#[derive(Debug)]
+pub struct Loaded<T> {
+    pub rows: Vec<T>,
+    /// 加载过程中是否进行过修复, 修复后需要重新写入数据文件
+    pub repaired: bool,
+}
+
+/// 被隔离的无法解析的记录
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuarantineItem {
+    /// 记录来源文件
+    pub source: String,
+    /// 记录在来源文件中的行号, 来自 sqlite 表时为 0
+    pub line: usize,
+    /// 解析错误
+    pub error: String,
+    /// 原始内容, 不是合法 json 时保存为字符串
+    pub record: Value,
+    /// 隔离时间
+    pub time: String,
+}
+
+/// 在文件名后追加后缀, 如 project.json -> project.json.bak
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(suffix);
+    PathBuf::from(p)
+}
+
+/// 数据文件最近一次写入前的备份, 备份之后写入的变更保存在 .bak.journal 中, 参考 [Journal::for_data_file]
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+/// 隔离文件, 保存无法解析的记录
+pub fn quarantine_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".quarantine.json")
+}
+
+/// 读取数据文件, 遇到损坏时尽可能恢复而不是直接失败
+///
+/// - 文件整体无法解析: 报告出错的行列, 从 .bak 备份恢复并重放 .bak.journal 中备份之后写入的变更,
+///   然后将原文件重命名为 .corrupt.<时间> 保留; 备份也不可用时返回错误, allow_empty 为 true 时以空集合启动
+/// - 个别记录无法解析: 报告记录所在行, 将其移入 .quarantine.json, 其余记录正常加载
+///
+/// 数据版本低于最新版本时会逐条执行迁移, 迁移失败的记录同样会被隔离
+pub fn load_rows<T>(
+    path: &Path,
+    collection: &str,
+    allow_empty: bool,
+    keyring: &Keyring,
+) -> Result<Loaded<T>>
+where
+    T: Record + DeserializeOwned,
+{
+    if !path.exists() {
+        return Ok(Loaded {
+            rows: Vec::new(),
+            repaired: true,
+        });
+    }
+
+    let diagnostic = match parse_rows(path, collection, keyring)? {
+        Ok(loaded) => return Ok(loaded),
+        Err(diagnostic) => diagnostic,
+    };
+
+    error!("数据文件损坏: {}", diagnostic);
+
+    let rows = match load_backup(path, collection, keyring)? {
+        Some(rows) => rows,
+        None if allow_empty => {
+            error!(
+                "没有可用的备份, 按 recovery.allow_empty 配置以空数据启动: {}",
+                path.display()
+            );
+            Vec::new()
+        }
+        None => bail!(
+            "数据文件损坏且没有可用的备份: {}; 确认要以空数据启动时将 recovery.allow_empty 设为 true",
+            path.display()
+        ),
+    };
+
+    let corrupt = with_suffix(
+        path,
+        &format!(".corrupt.{}", Local::now().format("%Y%m%d%H%M%S")),
+    );
+    fs::rename(path, &corrupt).with_context(|| format!("保留损坏文件失败: {}", path.display()))?;
+    warn!("损坏的文件已保留为: {}", corrupt.display());
+
+    Ok(Loaded {
+        rows,
+        repaired: true,
+    })
+}
+
+/// 读取数据文件的 .bak 备份, 并重放 .bak.journal 中备份之后写入数据文件的变更, 备份不可用时返回 None
+fn load_backup<T>(path: &Path, collection: &str, keyring: &Keyring) -> Result<Option<Vec<T>>>
+where
+    T: Record + DeserializeOwned,
+{
+    let bak = backup_path(path);
+
+    if !bak.exists() {
+        return Ok(None);
+    }
+
+    let mut rows = match parse_rows(&bak, collection, keyring)? {
+        Ok(loaded) => loaded.rows,
+        Err(diagnostic) => {
+            error!("备份同样不可用: {}", diagnostic);
+            return Ok(None);
+        }
+    };
+
+    let journal = Journal::for_data_file(&bak, keyring);
+    let (entries, bad_lines) = journal.read_all()?;
+    let replayed = entries.len();
+
+    if !bad_lines.is_empty() {
+        quarantine_journal_lines(path, &journal.path, bad_lines, keyring)?;
+    }
+
+    for entry in entries {
+        entry.apply_to(&mut rows);
+    }
+
+    warn!(
+        "已从备份恢复 {} 条记录, 重放 {} 条变更: {}",
+        rows.len(),
+        replayed,
+        bak.display()
+    );
+
+    Ok(Some(rows))
+}
+
+/// 解析数据文件, 外层 Err 表示 IO 错误, 内层 Err 表示文件整体损坏及其诊断信息
+fn parse_rows<T>(
+    path: &Path,
+    collection: &str,
+    keyring: &Keyring,
+) -> Result<Result<Loaded<T>, String>>
+where
+    T: DeserializeOwned,
+{
+    let bytes = fs::read(path).with_context(|| format!("读取数据文件失败: {}", path.display()))?;
+
+    // 密钥已在启动时校验, 此时无法解密说明文件已损坏
+    let bytes = match keyring.open(&bytes) {
+        Ok(bytes) => bytes,
+        Err(err) => return Ok(Err(format!("{}: {:#}", path.display(), err))),
+    };
+
+    let content = match String::from_utf8(bytes) {
+        Ok(content) => content,
+        Err(err) => {
+            return Ok(Err(format!(
+                "{}: 不是合法的 utf-8 文本: {}",
+                path.display(),
+                err
+            )));
+        }
+    };
+
+    let (version, raws) = match parse_envelope(&content) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            return Ok(Err(format!(
+                "{} 第 {} 行第 {} 列: {}",
+                path.display(),
+                err.line(),
+                err.column(),
+                err
+            )));
+        }
+    };
+
+    let latest = latest_version(collection);
+
+    if version > latest {
+        bail!(
+            "{} 的数据版本 v{} 高于程序支持的版本 v{}, 请升级程序",
+            path.display(),
+            version,
+            latest
+        );
+    }
+
+    if version < latest {
+        info!(
+            "迁移数据文件: {} v{} -> v{}",
+            path.display(),
+            version,
+            latest
+        );
+    }
+
+    let mut rows = Vec::with_capacity(raws.len());
+    let mut bad = Vec::new();
+
+    for raw in raws {
+        let parsed = if version == latest {
+            serde_json::from_str::<T>(raw.get()).map_err(anyhow::Error::from)
+        } else {
+            upgrade_record(collection, version, raw.get())
+        };
+
+        match parsed {
+            Ok(row) => rows.push(row),
+            Err(err) => {
+                let line = line_of(&content, raw.get());
+
+                warn!(
+                    "记录无法解析, 已隔离: {} 第 {} 行: {}",
+                    path.display(),
+                    line,
+                    err
+                );
+
+                bad.push(QuarantineItem {
+                    source: path.display().to_string(),
+                    line,
+                    error: format!("{:#}", err),
+                    record: serde_json::from_str(raw.get()).unwrap_or(Value::Null),
+                    time: Local::now().to_rfc3339(),
+                });
+            }
+        }
+    }
+
+    let repaired = !bad.is_empty() || version != latest;
+
+    if !bad.is_empty() {
+        quarantine(path, bad, keyring)?;
+    }
+
+    Ok(Ok(Loaded { rows, repaired }))
+}
+
+/// 严格解析数据文件内容, 任何记录无法解析都返回错误, 不做修复, 用于校验外部修改后的数据文件
+pub fn parse_strict<T>(content: &str, path: &Path, collection: &str) -> Result<Vec<T>>
+where
+    T: DeserializeOwned,
+{
+    let (version, raws) = parse_envelope(content).map_err(|err| {
+        anyhow!(
+            "{} 第 {} 行第 {} 列: {}",
+            path.display(),
+            err.line(),
+            err.column(),
+            err
+        )
+    })?;
+
+    let latest = latest_version(collection);
+
+    if version > latest {
+        bail!(
+            "{} 的数据版本 v{} 高于程序支持的版本 v{}",
+            path.display(),
+            version,
+            latest
+        );
+    }
+
+    raws.into_iter()
+        .map(|raw| {
+            let parsed = if version == latest {
+                serde_json::from_str::<T>(raw.get()).map_err(anyhow::Error::from)
+            } else {
+                upgrade_record(collection, version, raw.get())
+            };
+
+            parsed.with_context(|| {
+                format!(
+                    "{} 第 {} 行的记录无法解析",
+                    path.display(),
+                    line_of(content, raw.get())
+                )
+            })
+        })
+        .collect()
+}
+
+/// 将旧版本的记录迁移到最新版本后反序列化
+fn upgrade_record<T: DeserializeOwned>(collection: &str, version: u32, raw: &str) -> Result<T> {
+    let mut value: Value = serde_json::from_str(raw)?;
+
+    migrate_record(collection, version, &mut value)?;
+
+    Ok(serde_json::from_value(value)?)
+}
+
+/// 解析数据文件外层结构, 返回数据版本及各条记录的原始内容
+///
+/// 直接是数组的旧格式视为版本 0
+fn parse_envelope(content: &str) -> serde_json::Result<(u32, Vec<&RawValue>)> {
+    if content.trim_start().starts_with('[') {
+        return Ok((0, serde_json::from_str(content)?));
+    }
+
+    let envelope: Envelope<Vec<&RawValue>> = serde_json::from_str(content)?;
+
+    Ok((envelope.version, envelope.records))
+}
+
+/// 读取数据文件的版本及全部记录, 不做任何修复, 用于生成迁移报告
+pub fn read_versioned(path: &Path, keyring: &Keyring) -> Result<(u32, Vec<Value>)> {
+    let content = String::from_utf8(keyring.read_file(path)?)
+        .with_context(|| format!("读取数据文件失败: {}", path.display()))?;
+
+    let (version, raws) = parse_envelope(&content)
+        .with_context(|| format!("解析数据文件失败: {}", path.display()))?;
+
+    let records = raws
+        .into_iter()
+        .map(|raw| serde_json::from_str(raw.get()))
+        .collect::<Result<_, _>>()?;
+
+    Ok((version, records))
+}
+
+/// 隔离日志中无法解析的行
+pub fn quarantine_journal_lines(
+    data_path: &Path,
+    journal_path: &Path,
+    lines: Vec<BadLine>,
+    keyring: &Keyring,
+) -> Result<()> {
+    let items = lines
+        .into_iter()
+        .map(|bad| {
+            warn!(
+                "日志记录无法解析, 已隔离: {} 第 {} 行: {}",
+                journal_path.display(),
+                bad.line,
+                bad.error
+            );
+
+            QuarantineItem {
+                source: journal_path.display().to_string(),
+                line: bad.line,
+                error: bad.error,
+                record: serde_json::from_str(&bad.content).unwrap_or(Value::String(bad.content)),
+                time: Local::now().to_rfc3339(),
+            }
+        })
+        .collect();
+
+    quarantine(data_path, items, keyring)
+}
+
+/// 将记录追加到数据文件对应的隔离文件中
+pub fn quarantine(data_path: &Path, items: Vec<QuarantineItem>, keyring: &Keyring) -> Result<()> {
+    let mut path = quarantine_path(data_path);
+
+    let mut all: Vec<QuarantineItem> = Vec::new();
+
+    if path.exists() {
+        let content = keyring.read_file(&path)?;
+
+        match serde_json::from_slice(&content) {
+            Ok(existing) => all = existing,
+            // 已有的隔离文件本身无法解析时不覆盖它, 另起一个文件
+            Err(_) => {
+                path = with_suffix(
+                    data_path,
+                    &format!(".quarantine.{}.json", Local::now().format("%Y%m%d%H%M%S")),
+                )
+            }
+        }
+    }
+
+    all.extend(items);
+
+    write_atomic(
+        &path,
+        &keyring.seal(serde_json::to_string_pretty(&all)?.as_bytes())?,
+    )
+    .with_context(|| format!("写入隔离文件失败: {}", path.display()))
+}
+
+/// 计算 part 在 content 中所处的行号, part 必须是 content 的子切片
+fn line_of(content: &str, part: &str) -> usize {
+    let offset = part.as_ptr() as usize - content.as_ptr() as usize;
+
+    content[..offset].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entity::special_date::EntitySpecialDate;
+
+    use super::*;
+
+    #[test]
+    fn quarantines_bad_records_and_keeps_the_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("special_date.json");
+        fs::write(
+            &path,
+            r#"{
+  "version": 1,
+  "records": [
+    {"id": "a", "start_time": "2024-01-01", "date_type": "Include"},
+    {"id": "b", "start_time": "not a date", "date_type": "Include"}
+  ]
+}"#,
+        )
+        .unwrap();
+
+        let loaded =
+            load_rows::<EntitySpecialDate>(&path, "special_date", false, &Keyring::default())
+                .unwrap();
+
+        assert!(loaded.repaired);
+        assert_eq!(loaded.rows.len(), 1);
+        assert_eq!(loaded.rows[0].id, "a");
+
+        let items: Vec<QuarantineItem> =
+            serde_json::from_slice(&fs::read(quarantine_path(&path)).unwrap()).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].line, 5);
+        assert_eq!(items[0].record["id"], "b");
+    }
+
+    #[test]
+    fn unusable_backup_is_not_recovered_from() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("special_date.json");
+        fs::write(&path, "[").unwrap();
+        fs::write(backup_path(&path), "{").unwrap();
+
+        let err = load_rows::<EntitySpecialDate>(&path, "special_date", false, &Keyring::default())
+            .unwrap_err();
+
+        assert!(err.to_string().contains("没有可用的备份"));
+        assert!(path.exists());
+
+        let loaded =
+            load_rows::<EntitySpecialDate>(&path, "special_date", true, &Keyring::default())
+                .unwrap();
+
+        assert!(loaded.rows.is_empty());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+use log::error;
+
+use super::store::Store;
+
+/// 启动外部修改检测任务, 每隔 interval 检查一次数据文件, 参考 [Store::reload_external]
+///
+/// 无法解析的修改只报告一次, 文件被修正后重新加载
+pub fn spawn_hot_reload(store: Store, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+
+            let store = store.clone();
+            let res = tokio::task::spawn_blocking(move || store.reload_external()).await;
+
+            match res {
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => error!("重新加载数据文件失败: {:#}", err),
+                Err(err) => error!("重新加载数据文件失败: {}", err),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use anyhow::Result;
+    use serde_json::json;
+
+    use crate::{
+        config::AppConfig,
+        entity::employee::EntityEmployee,
+        repo::{crypto::Keyring, db::DB, transaction::Scope},
+    };
+
+    use super::*;
+
+    /// 写入一名人员并落盘, 返回数据文件路径
+    async fn store_with_employee(config: &AppConfig) -> (Store, PathBuf) {
+        let store = Store::load(config, &Keyring::default()).unwrap();
+
+        store
+            .transaction(Scope::ALL, |tx| {
+                tx.employee.put(serde_json::from_value(json!({
+                    "id": "e",
+                    "name": "人员",
+                    "status": "Working",
+                    "position": "dev",
+                }))?)?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+        store.flush_async().await.unwrap();
+
+        (
+            store,
+            config.data_dir.join(EntityEmployee::file_name(config)),
+        )
+    }
+
+    async fn reload(store: &Store) -> Result<Vec<&'static str>> {
+        let store = store.clone();
+        tokio::task::spawn_blocking(move || store.reload_external()).await?
+    }
+
+    async fn name(store: &Store) -> String {
+        store.employee.read().await.get("e").unwrap().name.clone()
+    }
+
+    fn edit(path: &PathBuf, from: &str, to: &str) {
+        let content = fs::read_to_string(path).unwrap();
+        assert!(content.contains(from));
+        fs::write(path, content.replace(from, to)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn external_edit_is_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AppConfig::with_data_dir(dir.path());
+        let (store, path) = store_with_employee(&config).await;
+
+        edit(&path, "人员", "外部修改");
+
+        assert_eq!(reload(&store).await.unwrap(), ["employee"]);
+        assert_eq!(name(&store).await, "外部修改");
+
+        // 重新加载的记录版本号加一, 之前取得的版本号失效
+        let version = store.employee.read().await.get("e").unwrap().version;
+        assert_eq!(version, 2);
+
+        assert!(reload(&store).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn invalid_edit_is_rejected_and_memory_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AppConfig::with_data_dir(dir.path());
+        let (store, path) = store_with_employee(&config).await;
+
+        let valid = fs::read_to_string(&path).unwrap();
+        fs::write(&path, valid.replace("人员", "外部修改").replace('}', "")).unwrap();
+
+        assert!(reload(&store).await.is_err());
+        assert_eq!(name(&store).await, "人员");
+
+        // 同一份无法解析的内容只报告一次
+        assert!(reload(&store).await.unwrap().is_empty());
+
+        // 修正后重新加载
+        fs::write(&path, valid.replace("人员", "修正后")).unwrap();
+        assert_eq!(reload(&store).await.unwrap(), ["employee"]);
+        assert_eq!(name(&store).await, "修正后");
+    }
+
+    #[tokio::test]
+    async fn own_writes_are_not_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AppConfig::with_data_dir(dir.path());
+        let (store, _) = store_with_employee(&config).await;
+
+        assert!(reload(&store).await.unwrap().is_empty());
+
+        store
+            .transaction(EntityEmployee::scope(), |tx| {
+                tx.employee.soft_delete("e")?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+        store.flush_async().await.unwrap();
+
+        assert!(reload(&store).await.unwrap().is_empty());
+        assert!(store.employee.read().await.get_deleted("e").is_some());
+    }
+}
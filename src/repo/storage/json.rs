@@ -0,0 +1,384 @@
+use std::{
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::ErrorKind,
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result, bail};
+use log::info;
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::repo::{
+    crypto::Keyring,
+    db::{Record, write_atomic},
+    journal::{Journal, JournalEntry},
+    migration::{Envelope, latest_version},
+    recovery::{backup_path, load_rows, parse_strict, quarantine_journal_lines},
+};
+
+use super::Storage;
+
+/// json 文件存储, 每个集合一个文件
+///
+/// 变更先追加到日志, 内存数据更新后再将整个集合原子写入数据文件, 旧文件保留为 .bak,
+/// 这批变更转存到 .bak.journal, 数据文件损坏时从 .bak 恢复不会丢失最后一批变更
+///
+/// 记录最后一次读写时数据文件内容的摘要, 据此发现在程序之外对数据文件的修改, 修改被重新加载之前拒绝覆盖
+pub struct JsonStorage {
+    /// 集合名
+    pub collection: &'static str,
+    /// 数据文件路径
+    pub path: PathBuf,
+    /// 变更日志
+    pub journal: Journal,
+    /// .bak 备份之后写入数据文件的变更
+    pub bak_journal: Journal,
+    /// 数据文件及其备份都无法读取时是否以空集合启动, 参考 [load_rows]
+    allow_empty: bool,
+    /// 数据文件及日志读写使用的密钥
+    keyring: Keyring,
+    /// 最后一次读写时数据文件内容的摘要, 文件不存在时为 None
+    stamp: Option<u64>,
+    /// 已报告过无法解析的外部修改的摘要, 避免重复报告
+    rejected: Option<u64>,
+}
+
+impl JsonStorage {
+    pub fn new(path: PathBuf, collection: &'static str, keyring: &Keyring) -> Self {
+        JsonStorage {
+            collection,
+            journal: Journal::for_data_file(&path, keyring),
+            bak_journal: Journal::for_data_file(&backup_path(&path), keyring),
+            path,
+            allow_empty: false,
+            keyring: keyring.clone(),
+            stamp: None,
+            rejected: None,
+        }
+    }
+
+    /// 数据文件及其备份都无法读取时以空集合启动, 默认返回错误
+    pub fn allow_empty(mut self, allow_empty: bool) -> Self {
+        self.allow_empty = allow_empty;
+        self
+    }
+
+    /// 读取数据文件当前的内容及摘要, 文件不存在时返回 None
+    fn read_current(&self) -> Result<Option<(Vec<u8>, u64)>> {
+        match fs::read(&self.path) {
+            Ok(content) => {
+                let stamp = digest(&content);
+                Ok(Some((content, stamp)))
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => {
+                Err(err).with_context(|| format!("读取数据文件失败: {}", self.path.display()))
+            }
+        }
+    }
+
+    /// 数据文件在最后一次读写之后被外部修改时返回错误, 文件被删除时视为未修改
+    fn ensure_unmodified(&self) -> Result<()> {
+        if let Some((_, stamp)) = self.read_current()?
+            && Some(stamp) != self.stamp
+        {
+            bail!(
+                "数据文件已在外部被修改, 重新加载之前不会覆盖 (可以通过 POST /admin/reload 立即重新加载): {}",
+                self.path.display()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// 将当前数据以最新版本完整写入数据文件, 写入前将旧文件复制为 .bak 备份, entries 为旧文件之后的变更,
+    /// 一并保存到 .bak.journal; 数据文件不存在时 (如已从 .bak 恢复) 保留原有备份, 将 entries 追加到 .bak.journal
+    pub fn checkpoint<T: Serialize>(
+        &mut self,
+        rows: &[T],
+        entries: &[JournalEntry<T>],
+    ) -> Result<()> {
+        let content = serde_json::to_string_pretty(&Envelope {
+            version: latest_version(self.collection),
+            records: rows,
+        })?;
+
+        if self.path.exists() {
+            fs::copy(&self.path, backup_path(&self.path))
+                .with_context(|| format!("备份数据文件失败: {}", self.path.display()))?;
+            self.bak_journal.replace(entries)?;
+        } else if backup_path(&self.path).exists() {
+            self.bak_journal.append(entries)?;
+        }
+
+        let content = self.keyring.seal(content.as_bytes())?;
+
+        write_atomic(&self.path, &content)?;
+
+        self.stamp = Some(digest(&content));
+
+        Ok(())
+    }
+}
+
+impl<T> Storage<T> for JsonStorage
+where
+    T: Record + Clone + Serialize + DeserializeOwned,
+{
+    /// 读取时会尽可能修复损坏的数据 (参考 [load_rows]), 然后重放日志中未写入数据文件的变更
+    fn load(&mut self) -> Result<Vec<T>> {
+        let loaded = load_rows(&self.path, self.collection, self.allow_empty, &self.keyring)?;
+        let mut rows = loaded.rows;
+
+        let (entries, bad_lines) = self.journal.read_all()?;
+        let replayed = entries.len();
+
+        if !bad_lines.is_empty() {
+            quarantine_journal_lines(&self.path, &self.journal.path, bad_lines, &self.keyring)?;
+        }
+
+        for entry in entries.iter().cloned() {
+            entry.apply_to(&mut rows);
+        }
+
+        if loaded.repaired || replayed > 0 || self.journal.path.exists() {
+            self.checkpoint(&rows, &entries)?;
+            self.journal.clear()?;
+        }
+
+        if replayed > 0 {
+            info!("已从日志恢复 {} 条变更: {}", replayed, self.path.display());
+        }
+
+        self.stamp = self.read_current()?.map(|(_, stamp)| stamp);
+
+        Ok(rows)
+    }
+
+    /// 整批变更写入日志后再写入数据文件, 数据文件写入失败时保留日志, 在下次启动时重放
+    ///
+    /// 数据文件被外部修改且尚未重新加载时拒绝写入, 变更保留在内存中, 重新加载时合并
+    fn write(&mut self, entries: &[JournalEntry<T>], rows: &[T]) -> Result<()> {
+        self.ensure_unmodified()?;
+
+        self.journal.append(entries)?;
+
+        self.checkpoint(rows, entries)
+            .context("写入数据文件失败, 变更保留在日志中")?;
+
+        self.journal.clear()
+    }
+
+    /// 数据文件内容与最后一次读写时不同时严格解析, 解析失败时报告一次, 之后保持拒绝写入直到文件被修正
+    fn reload_external(&mut self) -> Result<Option<Vec<T>>> {
+        let Some((content, stamp)) = self.read_current()? else {
+            return Ok(None);
+        };
+
+        if Some(stamp) == self.stamp || Some(stamp) == self.rejected {
+            return Ok(None);
+        }
+
+        let parsed = self
+            .keyring
+            .open(&content)
+            .and_then(|content| Ok(String::from_utf8(content)?))
+            .and_then(|content| parse_strict(&content, &self.path, self.collection));
+
+        match parsed {
+            Ok(rows) => {
+                self.stamp = Some(stamp);
+                self.rejected = None;
+                Ok(Some(rows))
+            }
+            Err(err) => {
+                self.rejected = Some(stamp);
+                Err(err.context("数据文件在外部被修改但无法解析, 修正之前暂停写入"))
+            }
+        }
+    }
+}
+
+/// 文件内容摘要, 用于判断文件是否被修改
+fn digest(content: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, OpenOptions},
+        io::Write,
+        path::Path,
+    };
+
+    use crate::{
+        entity::special_date::EntitySpecialDate,
+        repo::{
+            recovery::{QuarantineItem, quarantine_path},
+            tests::special_date,
+        },
+    };
+
+    use super::*;
+
+    fn upsert(id: &str, date: &str) -> JournalEntry<EntitySpecialDate> {
+        JournalEntry::Upsert {
+            record: special_date(id, date),
+        }
+    }
+
+    fn open(dir: &Path) -> JsonStorage {
+        JsonStorage::new(
+            dir.join("special_date.json"),
+            "special_date",
+            &Keyring::default(),
+        )
+    }
+
+    /// 加载全部记录, 返回 id 及开始日期, 新记录在前
+    fn load(storage: &mut JsonStorage) -> Vec<(String, String)> {
+        let rows: Vec<EntitySpecialDate> = storage.load().unwrap();
+        rows.into_iter()
+            .map(|p| (p.id, p.start_time.to_string()))
+            .collect()
+    }
+
+    /// 写入一批变更, 同时在内存中维护完整数据
+    fn write(
+        storage: &mut JsonStorage,
+        rows: &mut Vec<EntitySpecialDate>,
+        entries: Vec<JournalEntry<EntitySpecialDate>>,
+    ) {
+        for entry in entries.iter().cloned() {
+            entry.apply_to(rows);
+        }
+        storage.write(&entries, rows).unwrap();
+    }
+
+    fn pairs(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter()
+            .map(|(id, date)| (id.to_string(), date.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn replays_journal_written_before_a_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = open(dir.path());
+        let mut rows = storage.load().unwrap();
+
+        write(&mut storage, &mut rows, vec![upsert("a", "2024-01-01")]);
+
+        // 日志已写入, 写入数据文件前崩溃
+        storage
+            .journal
+            .append(&[upsert("b", "2024-01-02"), upsert("a", "2024-02-01")])
+            .unwrap();
+
+        // 崩溃时正在追加的下一批变更只写入了一部分
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&storage.journal.path)
+            .unwrap();
+        file.write_all(br#"{"op":"upsert","record":{"id":"c""#)
+            .unwrap();
+
+        let mut storage = open(dir.path());
+
+        assert_eq!(
+            load(&mut storage),
+            pairs(&[("b", "2024-01-02"), ("a", "2024-02-01")])
+        );
+        assert!(!storage.journal.path.exists());
+
+        // 不完整的一行不重放, 原样移入隔离文件
+        let quarantined: Vec<QuarantineItem> =
+            serde_json::from_slice(&fs::read(quarantine_path(&storage.path)).unwrap()).unwrap();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].line, 3);
+        assert_eq!(
+            quarantined[0].record,
+            r#"{"op":"upsert","record":{"id":"c""#
+        );
+
+        // 重放的变更已写入数据文件
+        assert_eq!(
+            load(&mut open(dir.path())),
+            pairs(&[("b", "2024-01-02"), ("a", "2024-02-01")])
+        );
+    }
+
+    #[test]
+    fn recovers_corrupt_data_from_backup_without_losing_the_last_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = open(dir.path());
+        let mut rows = storage.load().unwrap();
+
+        write(&mut storage, &mut rows, vec![upsert("a", "2024-01-01")]);
+        write(&mut storage, &mut rows, vec![upsert("b", "2024-01-02")]);
+        write(
+            &mut storage,
+            &mut rows,
+            vec![
+                upsert("a", "2024-02-01"),
+                JournalEntry::Delete { id: "b".into() },
+            ],
+        );
+
+        fs::write(&storage.path, "{\"version\": 1, \"records\": [").unwrap();
+
+        let mut storage = open(dir.path());
+
+        assert_eq!(load(&mut storage), pairs(&[("a", "2024-02-01")]));
+        assert_eq!(
+            fs::read_dir(dir.path())
+                .unwrap()
+                .filter(|p| {
+                    p.as_ref()
+                        .unwrap()
+                        .file_name()
+                        .to_string_lossy()
+                        .starts_with("special_date.json.corrupt.")
+                })
+                .count(),
+            1
+        );
+
+        // 恢复后的数据写回数据文件, 之后的写入照常进行
+        let mut rows = storage.load().unwrap();
+        write(&mut storage, &mut rows, vec![upsert("c", "2024-03-01")]);
+
+        assert_eq!(
+            load(&mut open(dir.path())),
+            pairs(&[("c", "2024-03-01"), ("a", "2024-02-01")])
+        );
+    }
+
+    #[test]
+    fn corrupt_data_without_backup_requires_opt_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("special_date.json");
+        fs::write(&path, "not json").unwrap();
+
+        let err = Storage::<EntitySpecialDate>::load(&mut open(dir.path())).unwrap_err();
+
+        assert!(format!("{:#}", err).contains("recovery.allow_empty"));
+        // 拒绝启动时不动损坏的文件, 修正后可以直接重新启动
+        assert_eq!(fs::read_to_string(&path).unwrap(), "not json");
+
+        let mut storage = open(dir.path()).allow_empty(true);
+
+        assert!(load(&mut storage).is_empty());
+        assert!(path.exists());
+        assert!(fs::read_dir(dir.path()).unwrap().any(|p| {
+            p.unwrap()
+                .file_name()
+                .to_string_lossy()
+                .contains(".corrupt.")
+        }));
+    }
+}
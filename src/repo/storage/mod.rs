@@ -0,0 +1,29 @@
+use anyhow::Result;
+
+use super::{journal::JournalEntry, query::Query};
+
+pub mod json;
+pub mod sqlite;
+
+/// 集合的存储后端
+///
+/// 修改由内存中的 [Collection](super::db::Collection) 完成, 后端负责持久化,
+/// 由后台持久化任务 (参考 [persist](super::persist)) 批量调用;
+/// 支持索引的后端还可以直接执行查询, 参考 [Collection::query](super::db::Collection::query)
+pub trait Storage<T>: Send {
+    /// 读取全部记录, 新记录在前
+    fn load(&mut self) -> Result<Vec<T>>;
+
+    /// 持久化一批变更, rows 为这批变更应用后的完整数据, 供需要整体写入的后端使用
+    fn write(&mut self, entries: &[JournalEntry<T>], rows: &[T]) -> Result<()>;
+
+    /// 检查数据是否在程序之外被修改, 修改且校验通过时返回修改后的全部记录, 未修改或不支持时返回 None
+    fn reload_external(&mut self) -> Result<Option<Vec<T>>> {
+        Ok(None)
+    }
+
+    /// 在后端执行查询, 新记录在前, 不支持时返回 None, 由内存中的索引完成查询
+    fn query(&mut self, _query: &Query) -> Result<Option<Vec<T>>> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,1017 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use chrono::Local;
+use log::{info, warn};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, ToSql, Transaction, params};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use crate::repo::{
+    crypto::Keyring,
+    db::Record,
+    filter::{Filter, Literal, Op},
+    journal::JournalEntry,
+    migration::{BASE_VERSION, latest_version, migrate_record},
+    query::{KeyQuery, Query},
+    recovery::{self, QuarantineItem},
+};
+
+use super::{Storage, json::JsonStorage};
+
+/// sqlite 存储, 所有集合存放在同一个数据库文件中, 每个集合一张表
+///
+/// 记录以 json 形式存放在 data 列中, 变更只写入单行, 不需要整体重写集合
+///
+/// 是否删除存放在单独的 deleted 列中, 二级索引 (参考 [Record::index_keys]) 存放在 <表名>_keys 表中,
+/// 两者都建有索引, 查询直接在数据库中完成; 过滤条件通过 json_extract 在数据库中求值, 参考 [filter_sql]
+pub struct SqliteStorage {
+    conn: Connection,
+    /// 表名, 即集合名
+    table: &'static str,
+    /// 同一集合的 json 数据文件, 表为空时会从该文件导入数据
+    json_path: PathBuf,
+    /// deleted 列及索引表是否需要根据 data 列重建, 新增索引表或迁移数据后为 true
+    stale: bool,
+}
+
+impl SqliteStorage {
+    pub fn open(db_path: &Path, table: &'static str, json_path: PathBuf) -> Result<Self> {
+        let conn = Connection::open(db_path)
+            .with_context(|| format!("打开数据库失败: {}", db_path.display()))?;
+
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "FULL")?;
+
+        let existed = table_exists(&conn, table)?;
+
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS _meta (
+                collection TEXT PRIMARY KEY,
+                version INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS \"{table}\" (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT NOT NULL UNIQUE,
+                data TEXT NOT NULL,
+                deleted INTEGER NOT NULL DEFAULT 0
+            )"
+        ))?;
+
+        // 早期版本的表没有 deleted 列及索引表, 补上后需要重建
+        let mut stale = false;
+
+        if !column_exists(&conn, table, "deleted")? {
+            conn.execute(
+                &format!("ALTER TABLE \"{table}\" ADD COLUMN deleted INTEGER NOT NULL DEFAULT 0"),
+                [],
+            )?;
+            stale = true;
+        }
+
+        if !table_exists(&conn, &format!("{table}_keys"))? {
+            stale = true;
+        }
+
+        conn.execute_batch(&format!(
+            "CREATE INDEX IF NOT EXISTS \"{table}_deleted\" ON \"{table}\" (deleted, seq);
+            CREATE TABLE IF NOT EXISTS \"{table}_keys\" (
+                id TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS \"{table}_keys_value\" ON \"{table}_keys\" (key, value);
+            CREATE INDEX IF NOT EXISTS \"{table}_keys_id\" ON \"{table}_keys\" (id)"
+        ))?;
+
+        // 新建的表直接是最新版本, 引入版本号之前已存在的表视为基础版本
+        let initial = if existed {
+            BASE_VERSION
+        } else {
+            latest_version(table)
+        };
+        conn.execute(
+            "INSERT OR IGNORE INTO _meta (collection, version) VALUES (?1, ?2)",
+            params![table, initial],
+        )?;
+
+        Ok(SqliteStorage {
+            conn,
+            table,
+            json_path,
+            stale,
+        })
+    }
+
+    /// 表中数据的版本
+    pub fn version(&self) -> Result<u32> {
+        Ok(self.conn.query_row(
+            "SELECT version FROM _meta WHERE collection = ?1",
+            params![self.table],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// 读取表中全部记录的原始 json, 新记录在前
+    pub fn raw_rows(&self) -> Result<Vec<(String, String)>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, data FROM \"{}\" ORDER BY seq DESC",
+            self.table
+        ))?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(rows)
+    }
+
+    /// 将表中数据逐步迁移到最新版本, 在同一个事务中完成
+    ///
+    /// 迁移失败的记录保持原样并输出错误, 不影响其他记录
+    fn migrate(&mut self) -> Result<()> {
+        let from = self.version()?;
+        let latest = latest_version(self.table);
+
+        if from > latest {
+            bail!(
+                "sqlite 表 {} 的数据版本 v{} 高于程序支持的版本 v{}, 请升级程序",
+                self.table,
+                from,
+                latest
+            );
+        }
+
+        if from == latest {
+            return Ok(());
+        }
+
+        info!("迁移 sqlite 表: {} v{} -> v{}", self.table, from, latest);
+
+        let raws = self.raw_rows()?;
+
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(&format!(
+                "UPDATE \"{}\" SET data = ?2 WHERE id = ?1",
+                self.table
+            ))?;
+
+            for (id, data) in raws {
+                let mut value: Value = serde_json::from_str(&data)?;
+
+                match migrate_record(self.table, from, &mut value) {
+                    Ok(()) => {
+                        stmt.execute(params![id, serde_json::to_string(&value)?])?;
+                    }
+                    Err(err) => warn!(
+                        "记录迁移失败, 保持原样: 表 {} id {}: {:#}",
+                        self.table, id, err
+                    ),
+                }
+            }
+
+            tx.execute(
+                "UPDATE _meta SET version = ?2 WHERE collection = ?1",
+                params![self.table, latest],
+            )?;
+        }
+        tx.commit()?;
+
+        self.stale = true;
+
+        Ok(())
+    }
+
+    /// 根据 data 列重建 deleted 列及索引表, 无法解析的记录不建索引
+    ///
+    /// data 列同时改写为记录当前的序列化结果, 迁移或旧版本写入的数据中缺少的字段补上默认值, 使数据库中的过滤与内存中一致
+    fn reindex<T>(&mut self) -> Result<()>
+    where
+        T: Record + DeserializeOwned,
+    {
+        let raws = self.raw_rows()?;
+        let table = self.table;
+
+        let tx = self.conn.transaction()?;
+        tx.execute(&format!("DELETE FROM \"{table}_keys\""), [])?;
+
+        for (id, data) in raws {
+            if let Ok(record) = serde_json::from_str::<T>(&data) {
+                tx.execute(
+                    &format!("UPDATE \"{table}\" SET data = ?2, deleted = ?3 WHERE id = ?1"),
+                    params![id, serde_json::to_string(&record)?, record.is_deleted()],
+                )?;
+                insert_keys(&tx, table, &record)?;
+            }
+        }
+        tx.commit()?;
+
+        self.stale = false;
+
+        Ok(())
+    }
+
+    /// 表为空且存在同名 json 数据文件时导入, 便于从 json 后端切换过来
+    fn import_json<T>(&mut self) -> Result<()>
+    where
+        T: Record + Clone + Serialize + DeserializeOwned,
+    {
+        let count: i64 = self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM \"{}\"", self.table),
+            [],
+            |row| row.get(0),
+        )?;
+
+        if count > 0 || !self.json_path.exists() {
+            return Ok(());
+        }
+
+        // sqlite 后端不支持加密, json 数据文件为明文
+        let rows: Vec<T> =
+            JsonStorage::new(self.json_path.clone(), self.table, &Keyring::default()).load()?;
+
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let tx = self.conn.transaction()?;
+
+        // rows 中新记录在前, 倒序插入使 seq 与新旧顺序一致
+        for row in rows.iter().rev() {
+            upsert(&tx, self.table, row)?;
+        }
+
+        tx.commit()?;
+
+        info!(
+            "已从 {} 导入 {} 条记录到 sqlite 表 {}",
+            self.json_path.display(),
+            rows.len(),
+            self.table
+        );
+
+        Ok(())
+    }
+
+    /// 将无法解析的记录 (id, data, 错误) 移到同一集合 json 数据文件对应的隔离文件中, 并从表及索引表中删除
+    ///
+    /// 留在表中的记录对程序不可见, 却会在下次整体替换 (如恢复备份) 时被静默删除; 先写隔离文件再删除, 中途失败时记录不会丢失
+    fn quarantine(&mut self, bad: Vec<(String, String, String)>) -> Result<()> {
+        if bad.is_empty() {
+            return Ok(());
+        }
+
+        let table = self.table;
+
+        let items = bad
+            .iter()
+            .map(|(id, data, error)| {
+                warn!("记录无法解析, 已隔离: 表 {} id {}: {}", table, id, error);
+
+                QuarantineItem {
+                    source: format!("sqlite 表 {table} id {id}"),
+                    line: 0,
+                    error: error.clone(),
+                    record: serde_json::from_str(data).unwrap_or(Value::String(data.clone())),
+                    time: Local::now().to_rfc3339(),
+                }
+            })
+            .collect();
+
+        // sqlite 后端不支持加密, 隔离文件为明文
+        recovery::quarantine(&self.json_path, items, &Keyring::default())?;
+
+        let tx = self.conn.transaction()?;
+
+        for (id, _, _) in &bad {
+            tx.execute(
+                &format!("DELETE FROM \"{table}\" WHERE id = ?1"),
+                params![id],
+            )?;
+            tx.execute(
+                &format!("DELETE FROM \"{table}_keys\" WHERE id = ?1"),
+                params![id],
+            )?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+}
+
+impl<T> Storage<T> for SqliteStorage
+where
+    T: Record + Clone + Serialize + DeserializeOwned,
+{
+    fn load(&mut self) -> Result<Vec<T>> {
+        self.migrate()?;
+        self.import_json::<T>()?;
+
+        if self.stale {
+            self.reindex::<T>()?;
+        }
+
+        let raws = self.raw_rows()?;
+
+        let mut rows = Vec::with_capacity(raws.len());
+        let mut bad = Vec::new();
+
+        for (id, data) in raws {
+            match serde_json::from_str(&data) {
+                Ok(row) => rows.push(row),
+                Err(err) => bad.push((id, data, err.to_string())),
+            }
+        }
+
+        self.quarantine(bad)?;
+
+        Ok(rows)
+    }
+
+    /// 整批变更在同一个事务中完成, 更新已有记录时保持其原有顺序
+    fn write(&mut self, entries: &[JournalEntry<T>], _rows: &[T]) -> Result<()> {
+        let table = self.table;
+
+        let tx = self.conn.transaction()?;
+
+        for entry in entries {
+            match entry {
+                JournalEntry::Upsert { record } => upsert(&tx, table, record)?,
+                JournalEntry::Delete { id } => {
+                    tx.execute(
+                        &format!("DELETE FROM \"{table}\" WHERE id = ?1"),
+                        params![id],
+                    )?;
+                    tx.execute(
+                        &format!("DELETE FROM \"{table}_keys\" WHERE id = ?1"),
+                        params![id],
+                    )?;
+                }
+                JournalEntry::Replace { records } => {
+                    tx.execute(&format!("DELETE FROM \"{table}\""), [])?;
+                    tx.execute(&format!("DELETE FROM \"{table}_keys\""), [])?;
+
+                    for record in records.iter().rev() {
+                        upsert(&tx, table, record)?;
+                    }
+                }
+            }
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// 通过 deleted 列及索引表查询, 按 seq 倒序即新记录在前
+    fn query(&mut self, query: &Query) -> Result<Option<Vec<T>>> {
+        let table = self.table;
+
+        let mut sql = format!("SELECT id, data FROM \"{table}\" WHERE deleted = ?");
+        let mut args: Vec<Box<dyn ToSql>> = vec![Box::new(query.deleted)];
+
+        match &query.key {
+            None => {}
+            Some(KeyQuery::Eq(key, value)) => {
+                sql += &format!(
+                    " AND id IN (SELECT id FROM \"{table}_keys\" WHERE key = ? AND value = ?)"
+                );
+                args.push(Box::new(key.name()));
+                args.push(Box::new(value.clone()));
+            }
+            Some(KeyQuery::Range(key, from, to)) => {
+                sql += &format!(" AND id IN (SELECT id FROM \"{table}_keys\" WHERE key = ?");
+                args.push(Box::new(key.name()));
+
+                if let Some(from) = from {
+                    sql += " AND value >= ?";
+                    args.push(Box::new(from.clone()));
+                }
+
+                if let Some(to) = to {
+                    sql += " AND value <= ?";
+                    args.push(Box::new(to.clone()));
+                }
+
+                sql += ")";
+            }
+        }
+
+        // 无法在数据库中求值的过滤条件在读出记录后求值
+        let mut post_filter = None;
+
+        if let Some(filter) = &query.filter {
+            let mut filter_args = Vec::new();
+
+            match filter_sql(filter, &mut filter_args) {
+                Some(cond) => {
+                    sql += &format!(" AND {cond}");
+                    args.extend(filter_args);
+                }
+                None => post_filter = Some(filter),
+            }
+        }
+
+        sql += " ORDER BY seq DESC";
+
+        let raws = self
+            .conn
+            .prepare(&sql)?
+            .query_map(rusqlite::params_from_iter(args.iter()), |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut rows = Vec::with_capacity(raws.len());
+        let mut bad = Vec::new();
+
+        for (id, data) in raws {
+            match serde_json::from_str::<T>(&data) {
+                Ok(row) => {
+                    if post_filter.is_none_or(|f| f.matches(&row)) {
+                        rows.push(row);
+                    }
+                }
+                // 与加载时一致, 隔离无法解析的记录
+                Err(err) => bad.push((id, data, err.to_string())),
+            }
+        }
+
+        self.quarantine(bad)?;
+
+        Ok(Some(rows))
+    }
+}
+
+/// 新增或更新记录, 同时更新 deleted 列及索引表, 更新已有记录时保持其原有顺序
+fn upsert<T: Record>(tx: &Transaction, table: &str, record: &T) -> Result<()> {
+    tx.execute(
+        &format!(
+            "INSERT INTO \"{table}\" (id, data, deleted) VALUES (?1, ?2, ?3)
+            ON CONFLICT (id) DO UPDATE SET data = excluded.data, deleted = excluded.deleted"
+        ),
+        params![
+            record.id(),
+            serde_json::to_string(record)?,
+            record.is_deleted()
+        ],
+    )?;
+
+    tx.execute(
+        &format!("DELETE FROM \"{table}_keys\" WHERE id = ?1"),
+        params![record.id()],
+    )?;
+
+    insert_keys(tx, table, record)
+}
+
+/// 将记录的二级索引写入索引表
+fn insert_keys<T: Record>(tx: &Transaction, table: &str, record: &T) -> Result<()> {
+    let mut stmt = tx.prepare_cached(&format!(
+        "INSERT INTO \"{table}_keys\" (id, key, value) VALUES (?1, ?2, ?3)"
+    ))?;
+
+    for (key, value) in record.index_keys() {
+        stmt.execute(params![record.id(), key.name(), value])?;
+    }
+
+    Ok(())
+}
+
+/// 过滤条件中最多在数据库中求值的比较数量, in 列表中的每个值计一次
+///
+/// 每个比较转换为多个参数, 并以 OR 连接, 比较过多时会超出 sqlite 表达式深度 (1000) 及参数数量的限制, 此时在内存中求值
+const MAX_SQL_COMPARISONS: usize = 100;
+
+/// 将过滤条件转换为 SQL 条件, 求值结果与 [Filter::matches] 相同, 参数依次追加到 args;
+/// 无法转换或比较数量超过 [MAX_SQL_COMPARISONS] 时返回 None
+///
+/// 字段值通过 json_extract 从 data 列读取, 并按 json_type 区分类型: 与内存中一样, 数字按数值比较, 字符串按字节比较,
+/// 布尔值按 false < true 比较, 类型不匹配时不相等也不可比较; 转换出的条件总是 0 或 1, 不会是 NULL
+fn filter_sql(filter: &Filter, args: &mut Vec<Box<dyn ToSql>>) -> Option<String> {
+    if comparisons(filter) > MAX_SQL_COMPARISONS {
+        return None;
+    }
+
+    condition_sql(filter, args)
+}
+
+/// 过滤条件中的比较数量
+fn comparisons(filter: &Filter) -> usize {
+    match filter {
+        Filter::And(items) | Filter::Or(items) => items.iter().map(comparisons).sum(),
+        Filter::Not(item) => comparisons(item),
+        Filter::Compare { .. } => 1,
+        Filter::In { values, .. } => values.len(),
+        Filter::Between { .. } => 2,
+    }
+}
+
+/// 参考 [filter_sql]
+fn condition_sql(filter: &Filter, args: &mut Vec<Box<dyn ToSql>>) -> Option<String> {
+    let join = |items: &[Filter], sep: &str, args: &mut Vec<Box<dyn ToSql>>| {
+        let parts = items
+            .iter()
+            .map(|p| condition_sql(p, args))
+            .collect::<Option<Vec<_>>>()?;
+        Some(format!("({})", parts.join(sep)))
+    };
+
+    match filter {
+        Filter::And(items) => join(items, " AND ", args),
+        Filter::Or(items) => join(items, " OR ", args),
+        Filter::Not(item) => Some(format!("(NOT {})", condition_sql(item, args)?)),
+        Filter::Compare { field, op, value } => compare_sql(field, *op, value, args),
+        Filter::In { field, values } => {
+            let parts = values
+                .iter()
+                .map(|p| compare_sql(field, Op::Eq, p, args))
+                .collect::<Option<Vec<_>>>()?;
+            Some(format!("({})", parts.join(" OR ")))
+        }
+        Filter::Between { field, from, to } => Some(format!(
+            "({} AND {})",
+            compare_sql(field, Op::Ge, from, args)?,
+            compare_sql(field, Op::Le, to, args)?
+        )),
+    }
+}
+
+/// 单个字段比较的 SQL 条件, 参考 [filter_sql]; 字段名中含有引号或反斜杠时返回 None
+fn compare_sql(
+    field: &str,
+    op: Op,
+    value: &Literal,
+    args: &mut Vec<Box<dyn ToSql>>,
+) -> Option<String> {
+    if field.contains(['"', '\\']) {
+        return None;
+    }
+
+    let path = format!("$.\"{field}\"");
+
+    let text = match value {
+        Literal::Null => {
+            let is_null = "(json_type(data, ?) IS NULL OR json_type(data, ?) = 'null')";
+
+            return Some(match op {
+                Op::Gt | Op::Lt | Op::Contains => "0".to_string(),
+                Op::Eq | Op::Ne | Op::Ge | Op::Le => {
+                    args.push(Box::new(path.clone()));
+                    args.push(Box::new(path));
+
+                    if op == Op::Ne {
+                        format!("(NOT {is_null})")
+                    } else {
+                        is_null.to_string()
+                    }
+                }
+            });
+        }
+        Literal::Text(text) => text,
+    };
+
+    if op == Op::Contains {
+        args.push(Box::new(path.clone()));
+        args.push(Box::new(path));
+        args.push(Box::new(text.clone()));
+        return Some(
+            "COALESCE(json_type(data, ?) = 'text' AND instr(json_extract(data, ?), ?) > 0, 0)"
+                .to_string(),
+        );
+    }
+
+    let symbol = match op {
+        Op::Eq | Op::Ne => "=",
+        Op::Gt => ">",
+        Op::Ge => ">=",
+        Op::Lt => "<",
+        Op::Le => "<=",
+        Op::Contains => unreachable!(),
+    };
+
+    // 按字段的 json 类型分别比较, 表达式中的值无法转换为该类型时不比较
+    let mut branches = Vec::new();
+
+    if let Ok(number) = text.parse::<f64>() {
+        branches.push(format!(
+            "(json_type(data, ?) IN ('integer', 'real') AND json_extract(data, ?) {symbol} ?)"
+        ));
+        args.push(Box::new(path.clone()));
+        args.push(Box::new(path.clone()));
+        args.push(Box::new(number));
+    }
+
+    branches.push(format!(
+        "(json_type(data, ?) = 'text' AND json_extract(data, ?) {symbol} ?)"
+    ));
+    args.push(Box::new(path.clone()));
+    args.push(Box::new(path.clone()));
+    args.push(Box::new(text.clone()));
+
+    if let Ok(flag) = text.parse::<bool>() {
+        branches.push(format!(
+            "(json_type(data, ?) IN ('true', 'false') AND json_extract(data, ?) {symbol} ?)"
+        ));
+        args.push(Box::new(path.clone()));
+        args.push(Box::new(path));
+        args.push(Box::new(flag));
+    }
+
+    let cond = format!("COALESCE({}, 0)", branches.join(" OR "));
+
+    Some(if op == Op::Ne {
+        format!("(NOT {cond})")
+    } else {
+        cond
+    })
+}
+
+/// 以只读方式读取表的版本及全部记录, 数据库或表不存在时返回 None, 用于生成迁移报告
+pub fn read_versioned(db_path: &Path, table: &str) -> Result<Option<(u32, Vec<Value>)>> {
+    if !db_path.exists() {
+        return Ok(None);
+    }
+
+    let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("打开数据库失败: {}", db_path.display()))?;
+
+    if !table_exists(&conn, table)? {
+        return Ok(None);
+    }
+
+    let version = if table_exists(&conn, "_meta")? {
+        conn.query_row(
+            "SELECT version FROM _meta WHERE collection = ?1",
+            params![table],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or(BASE_VERSION)
+    } else {
+        BASE_VERSION
+    };
+
+    let mut stmt = conn.prepare(&format!("SELECT data FROM \"{}\" ORDER BY seq DESC", table))?;
+
+    let records = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .map(|data| Ok(serde_json::from_str(&data?)?))
+        .collect::<Result<Vec<Value>>>()?;
+
+    Ok(Some((version, records)))
+}
+
+fn table_exists(conn: &Connection, table: &str) -> Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        params![table],
+        |row| row.get(0),
+    )?;
+
+    Ok(count > 0)
+}
+
+fn column_exists(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let count: i64 = conn.query_row(
+        &format!("SELECT COUNT(*) FROM pragma_table_info('{table}') WHERE name = ?1"),
+        params![column],
+        |row| row.get(0),
+    )?;
+
+    Ok(count > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        entity::{attendance::EntityAttendance, employee_change::EntityEmployeeChange},
+        repo::{filter::Filter, index::IndexKey},
+    };
+
+    use super::*;
+
+    fn change(id: &str, project_id: &str, deleted: bool) -> EntityEmployeeChange {
+        let mut value = json!({
+            "id": id,
+            "employee_id": "e1",
+            "project_id": project_id,
+            "in_time": "2024-01-01",
+        });
+
+        if deleted {
+            value["deleted_at"] = json!("2024-02-01T00:00:00+08:00");
+        }
+
+        serde_json::from_value(value).unwrap()
+    }
+
+    /// 在数据库中查询, 返回记录id
+    fn ids(storage: &mut SqliteStorage, query: &Query) -> Vec<String> {
+        let rows: Vec<EntityEmployeeChange> = storage.query(query).unwrap().unwrap();
+        rows.into_iter().map(|p| p.id).collect()
+    }
+
+    fn by_project(project_id: &str) -> Query {
+        Query::active(Some(KeyQuery::Eq(IndexKey::ProjectId, project_id.into())))
+    }
+
+    fn open(dir: &Path) -> SqliteStorage {
+        SqliteStorage::open(
+            &dir.join("data.db"),
+            "employee_change",
+            dir.join("employee_change.json"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn query_uses_key_table_and_deleted_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = open(dir.path());
+        Storage::<EntityEmployeeChange>::load(&mut storage).unwrap();
+
+        let entries = vec![
+            JournalEntry::Upsert {
+                record: change("a", "p1", false),
+            },
+            JournalEntry::Upsert {
+                record: change("b", "p2", false),
+            },
+            JournalEntry::Upsert {
+                record: change("c", "p1", false),
+            },
+            JournalEntry::Upsert {
+                record: change("d", "p1", true),
+            },
+        ];
+        storage.write(&entries, &[]).unwrap();
+
+        assert_eq!(ids(&mut storage, &by_project("p1")), ["c", "a"]);
+        assert_eq!(ids(&mut storage, &Query::trash()), ["d"]);
+
+        // 更新后索引随之变化, 顺序保持不变
+        let entries = vec![
+            JournalEntry::Upsert {
+                record: change("a", "p2", false),
+            },
+            JournalEntry::Delete { id: "c".into() },
+        ];
+        storage.write(&entries, &[]).unwrap();
+
+        assert!(ids(&mut storage, &by_project("p1")).is_empty());
+        assert_eq!(ids(&mut storage, &by_project("p2")), ["b", "a"]);
+    }
+
+    #[test]
+    fn legacy_table_is_reindexed_on_load() {
+        let dir = tempfile::tempdir().unwrap();
+
+        {
+            let conn = Connection::open(dir.path().join("data.db")).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE employee_change (
+                    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                    id TEXT NOT NULL UNIQUE,
+                    data TEXT NOT NULL
+                )",
+            )
+            .unwrap();
+
+            for p in [change("a", "p1", false), change("b", "p1", true)] {
+                conn.execute(
+                    "INSERT INTO employee_change (id, data) VALUES (?1, ?2)",
+                    params![p.id, serde_json::to_string(&p).unwrap()],
+                )
+                .unwrap();
+            }
+        }
+
+        let mut storage = open(dir.path());
+        let rows: Vec<EntityEmployeeChange> = storage.load().unwrap();
+        assert_eq!(rows.len(), 2);
+
+        assert_eq!(ids(&mut storage, &by_project("p1")), ["a"]);
+        assert_eq!(ids(&mut storage, &Query::trash()), ["b"]);
+    }
+
+    #[test]
+    fn unparsable_rows_are_quarantined_and_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = open(dir.path());
+        Storage::<EntityEmployeeChange>::load(&mut storage).unwrap();
+
+        let entries = ["a", "b", "c"]
+            .map(|p| JournalEntry::Upsert {
+                record: change(p, "p1", false),
+            })
+            .to_vec();
+        storage.write(&entries, &[]).unwrap();
+
+        let corrupt = |storage: &SqliteStorage, id: &str| {
+            storage
+                .conn
+                .execute(
+                    "UPDATE employee_change SET data = 'oops' WHERE id = ?1",
+                    params![id],
+                )
+                .unwrap();
+        };
+        let count = |storage: &SqliteStorage, id: &str| -> (i64, i64) {
+            let count = |table: &str| {
+                storage
+                    .conn
+                    .query_row(
+                        &format!("SELECT COUNT(*) FROM {table} WHERE id = ?1"),
+                        params![id],
+                        |row| row.get(0),
+                    )
+                    .unwrap()
+            };
+            (count("employee_change"), count("employee_change_keys"))
+        };
+        let quarantined = || -> Vec<QuarantineItem> {
+            let path = recovery::quarantine_path(&dir.path().join("employee_change.json"));
+            serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+        };
+
+        // 加载时遇到
+        corrupt(&storage, "b");
+        let mut storage = open(dir.path());
+        let rows: Vec<EntityEmployeeChange> = storage.load().unwrap();
+        assert_eq!(
+            rows.iter().map(|p| p.id.as_str()).collect::<Vec<_>>(),
+            ["c", "a"]
+        );
+        assert_eq!(count(&storage, "b"), (0, 0));
+
+        let items = quarantined();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].source, "sqlite 表 employee_change id b");
+        assert_eq!(items[0].record, json!("oops"));
+
+        // 查询时遇到
+        corrupt(&storage, "a");
+        assert_eq!(ids(&mut storage, &by_project("p1")), ["c"]);
+        assert_eq!(count(&storage, "a"), (0, 0));
+        assert_eq!(quarantined().len(), 2);
+
+        // 其他记录及其索引不受影响
+        assert_eq!(count(&storage, "c"), (1, 3));
+    }
+
+    #[test]
+    fn date_range_finds_overlapping_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = SqliteStorage::open(
+            &dir.path().join("data.db"),
+            "attendance",
+            dir.path().join("attendance.json"),
+        )
+        .unwrap();
+        Storage::<EntityAttendance>::load(&mut storage).unwrap();
+
+        let leave = |id: &str, start: &str, end: Option<&str>| JournalEntry::Upsert {
+            record: serde_json::from_value::<EntityAttendance>(json!({
+                "id": id,
+                "start_time": start,
+                "end_time": end,
+                "employee_id": "e1",
+                "date_type": "Leave",
+                "start_half": false,
+                "end_half": false,
+            }))
+            .unwrap(),
+        };
+
+        let entries = vec![
+            leave("a", "2024-01-01", Some("2024-01-10")),
+            leave("b", "2024-01-05", None),
+            leave("c", "2024-01-20", None),
+        ];
+        storage.write(&entries, &[]).unwrap();
+
+        let mut between = |from: &str, to: &str| {
+            let query = Query::active(Some(KeyQuery::Range(
+                IndexKey::Date,
+                Some(from.into()),
+                Some(to.into()),
+            )));
+            let rows: Vec<EntityAttendance> = storage.query(&query).unwrap().unwrap();
+            rows.into_iter().map(|p| p.id).collect::<Vec<_>>()
+        };
+
+        // 开始日期早于查询范围的记录同样可以找到, 覆盖多天的记录只出现一次
+        assert_eq!(between("2024-01-03", "2024-01-06"), ["b", "a"]);
+        assert_eq!(between("2024-01-08", "2024-01-31"), ["c", "a"]);
+        assert!(between("2024-01-11", "2024-01-19").is_empty());
+    }
+
+    #[test]
+    fn filter_in_database_matches_filter_in_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = SqliteStorage::open(
+            &dir.path().join("data.db"),
+            "attendance",
+            dir.path().join("attendance.json"),
+        )
+        .unwrap();
+        Storage::<EntityAttendance>::load(&mut storage).unwrap();
+
+        let records: Vec<EntityAttendance> = [
+            json!({"id": "a", "start_time": "2024-01-01", "end_time": "2024-01-03", "employee_id": "e1", "date_type": "Leave", "start_half": true, "end_half": false, "version": 1}),
+            json!({"id": "b", "start_time": "2024-02-01", "employee_id": "e2", "date_type": "Overtime", "start_half": false, "end_half": false, "version": 10}),
+            json!({"id": "c", "start_time": "2024-03-01", "employee_id": null, "date_type": "CompensatoryLeave", "start_half": false, "end_half": true, "version": 2}),
+        ]
+        .into_iter()
+        .map(|p| serde_json::from_value(p).unwrap())
+        .collect();
+
+        let entries: Vec<_> = records
+            .iter()
+            .map(|p| JournalEntry::Upsert { record: p.clone() })
+            .collect();
+        storage.write(&entries, &[]).unwrap();
+
+        for expr in [
+            "employee_id = e1",
+            "employee_id != e1",
+            "employee_id = null",
+            "employee_id != null",
+            "end_time = null or end_time >= 2024-01-02",
+            "end_time < 2024-01-05",
+            "start_time between 2024-01-15, 2024-03-01",
+            "date_type in (Leave, Overtime)",
+            "date_type not in (Leave)",
+            "employee_id ~ e",
+            "employee_id ~ ''",
+            "version > 2",
+            "version >= 2.0 and version < 10",
+            "version = abc",
+            "version != abc",
+            "version = 10 or start_half = true",
+            "start_half > false",
+            "end_half != true",
+            "not (start_half = true or end_half = true)",
+            "missing = null",
+            "missing != 1",
+            "id > a and id < c",
+            "version ~ 1",
+        ] {
+            let filter = Filter::parse(expr).unwrap();
+
+            let mut args = Vec::new();
+            assert!(filter_sql(&filter, &mut args).is_some(), "{expr}");
+
+            let expected: Vec<&str> = records
+                .iter()
+                .rev()
+                .filter(|p| filter.matches(*p))
+                .map(|p| p.id.as_str())
+                .collect();
+
+            let rows: Vec<EntityAttendance> = storage
+                .query(&Query::active(None).filter(Some(filter)))
+                .unwrap()
+                .unwrap();
+            let actual: Vec<&str> = rows.iter().map(|p| p.id.as_str()).collect();
+
+            assert_eq!(actual, expected, "{expr}");
+        }
+    }
+
+    #[test]
+    fn large_in_list_is_evaluated_in_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = open(dir.path());
+        Storage::<EntityEmployeeChange>::load(&mut storage).unwrap();
+
+        let entries: Vec<_> = (0..20)
+            .map(|p| JournalEntry::Upsert {
+                record: change(&format!("c{p}"), &format!("p{p}"), false),
+            })
+            .collect();
+        storage.write(&entries, &[]).unwrap();
+
+        for count in [MAX_SQL_COMPARISONS, 2000] {
+            let values: Vec<String> = (0..count).map(|p| format!("p{}", p * 2)).collect();
+            let filter = Filter::parse(&format!("project_id in ({})", values.join(", "))).unwrap();
+
+            let mut args = Vec::new();
+            assert_eq!(
+                filter_sql(&filter, &mut args).is_some(),
+                count <= MAX_SQL_COMPARISONS
+            );
+
+            let query = Query::active(None).filter(Some(filter));
+            let expected: Vec<String> = (0..10).rev().map(|p| format!("c{}", p * 2)).collect();
+            assert_eq!(ids(&mut storage, &query), expected, "{count}");
+        }
+    }
+}
@@ -0,0 +1,447 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use log::info;
+use tokio::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{
+    config::{AppConfig, IntegrityConfig},
+    entity::{
+        attendance::EntityAttendance, employee::EntityEmployee,
+        employee_change::EntityEmployeeChange, leave_policy::EntityLeavePolicy,
+        project::EntityProject, special_date::EntitySpecialDate,
+    },
+};
+
+use super::{
+    audit::{AUDIT_FILE, AuditLog},
+    crypto::Keyring,
+    db::{Batch, Collection, DB, DBType, Record},
+    ledger,
+    persist::{self, Persister},
+    transaction::{COMMIT_LOG_FILE, CommitLog, CommitRecord, Scope, Transaction, commit_values},
+    undo::UndoHistory,
+};
+
+/// 全部集合的句柄, 用于需要同时访问多个集合的场景
+///
+/// 同时持有多个集合的锁时, 必须按以下顺序加锁, 不需要的集合可以跳过, 但不能颠倒顺序, 以免死锁:
+///
+/// project -> employee -> employee_change -> attendance -> special_date -> leave_policy
+///
+/// 需要全部集合时使用 [Store::read_all], 事务通过 [Store::transaction] 按声明的集合加锁, 它们都按此顺序加锁
+#[derive(Clone)]
+pub struct Store {
+    pub project: DBType<EntityProject>,
+    pub employee: DBType<EntityEmployee>,
+    pub employee_change: DBType<EntityEmployeeChange>,
+    pub attendance: DBType<EntityAttendance>,
+    pub special_date: DBType<EntitySpecialDate>,
+    pub leave_policy: DBType<EntityLeavePolicy>,
+    /// 后台持久化状态
+    pub persister: Arc<Persister>,
+    /// 删除被引用记录时的处理方式
+    pub integrity: IntegrityConfig,
+    /// 审计日志
+    pub audit: Arc<AuditLog>,
+    /// 各操作人的撤销历史
+    pub undo: Arc<UndoHistory>,
+    /// 数据文件及日志读写使用的密钥
+    pub keyring: Keyring,
+    /// 事务日志, 同时用作写入锁, 保证同一时刻只有一个写入方, 各批变更按顺序写入
+    commit_log: Arc<Mutex<CommitLog>>,
+}
+
+impl Store {
+    /// 按配置加载全部集合, 并重放事务日志中尚未完整写入的提交, 数据文件使用 keyring 加解密
+    pub fn load(config: &AppConfig, keyring: &Keyring) -> Result<Self> {
+        let persister = Arc::new(Persister::new(&config.persistence));
+        let audit = Arc::new(AuditLog::load(
+            config.data_dir.join(AUDIT_FILE),
+            keyring.clone(),
+        )?);
+
+        let commit_log = CommitLog::new(config.data_dir.join(COMMIT_LOG_FILE), keyring.clone());
+        let commits = commit_log.read_all()?;
+
+        let store = Store {
+            project: EntityProject::new(
+                config,
+                persister.clone(),
+                audit.clone(),
+                keyring,
+                &commits,
+            )
+            .context("加载项目数据失败")?,
+            employee: EntityEmployee::new(
+                config,
+                persister.clone(),
+                audit.clone(),
+                keyring,
+                &commits,
+            )
+            .context("加载员工数据失败")?,
+            employee_change: EntityEmployeeChange::new(
+                config,
+                persister.clone(),
+                audit.clone(),
+                keyring,
+                &commits,
+            )
+            .context("加载入项记录数据失败")?,
+            attendance: EntityAttendance::new(
+                config,
+                persister.clone(),
+                audit.clone(),
+                keyring,
+                &commits,
+            )
+            .context("加载考勤数据失败")?,
+            special_date: EntitySpecialDate::new(
+                config,
+                persister.clone(),
+                audit.clone(),
+                keyring,
+                &commits,
+            )
+            .context("加载特殊日期数据失败")?,
+            leave_policy: EntityLeavePolicy::new(
+                config,
+                persister.clone(),
+                audit.clone(),
+                keyring,
+                &commits,
+            )
+            .context("加载年假政策数据失败")?,
+            persister,
+            integrity: config.integrity.clone(),
+            audit,
+            undo: Arc::new(UndoHistory::default()),
+            keyring: keyring.clone(),
+            commit_log: Arc::new(Mutex::new(commit_log)),
+        };
+
+        // 全部集合都已写入日志中的提交
+        store.commit_log.lock().unwrap().clear()?;
+
+        Ok(store)
+    }
+
+    /// 在事务中修改多个集合, 事务期间持有 scope 中声明的集合的锁, 其余集合不受影响
+    ///
+    /// f 返回错误时全部修改回滚, 成功时全部修改作为一个整体写入磁盘, 参考 [Transaction]
+    ///
+    /// 提交前校验涉及多条记录的业务规则 (调休余额, 参考 [ledger::check_transaction]), 不满足时同样回滚
+    pub async fn transaction<R>(
+        &self,
+        scope: Scope,
+        f: impl FnOnce(&mut Transaction) -> Result<R>,
+    ) -> Result<R> {
+        let mut tx = Transaction::lock(self, scope).await;
+
+        let res = f(&mut tx).and_then(|res| {
+            ledger::check_transaction(&tx)?;
+            Ok(res)
+        });
+
+        persist::end_transaction(res.is_ok());
+
+        if res.is_ok() {
+            tx.commit();
+        }
+
+        res
+    }
+
+    /// 按约定顺序获取全部集合的读锁
+    pub async fn read_all(&self) -> StoreRead<'_> {
+        StoreRead {
+            project: self.project.read().await,
+            employee: self.employee.read().await,
+            employee_change: self.employee_change.read().await,
+            attendance: self.attendance.read().await,
+            special_date: self.special_date.read().await,
+            leave_policy: self.leave_policy.read().await,
+        }
+    }
+
+    /// 在阻塞线程中执行 [Store::flush]
+    pub async fn flush_async(&self) -> Result<()> {
+        let store = self.clone();
+
+        tokio::task::spawn_blocking(move || store.flush()).await?
+    }
+
+    /// 将全部集合待写入的变更写入后端, 会阻塞当前线程, 异步环境中使用 [Store::flush_async]
+    ///
+    /// 在全部集合的写锁下同时取出变更, 保证事务中的变更在同一批写入; 涉及多个集合时先写入事务日志,
+    /// 之后写入对应的审计记录. 某个集合写入失败时继续写入其余集合, 失败的变更放回集合中稍后重试, 返回第一个错误
+    pub fn flush(&self) -> Result<()> {
+        let mut commit_log = self.commit_log.lock().unwrap();
+
+        let target = self.persister.current_seq();
+
+        // 持有全部写锁时没有进行中的事务, 此时的审计记录都已确定, 不会再被回滚
+        let (audit_end, project, employee, employee_change, attendance, special_date, leave_policy) = {
+            let mut all = self.blocking_write_all();
+            (
+                self.audit.next_seq(),
+                all.project.take_batch(),
+                all.employee.take_batch(),
+                all.employee_change.take_batch(),
+                all.attendance.take_batch(),
+                all.special_date.take_batch(),
+                all.leave_policy.take_batch(),
+            )
+        };
+
+        let mut record = CommitRecord::new();
+
+        let logged = add_to_commit::<EntityProject>(&mut record, &project)
+            .and_then(|_| add_to_commit::<EntityEmployee>(&mut record, &employee))
+            .and_then(|_| add_to_commit::<EntityEmployeeChange>(&mut record, &employee_change))
+            .and_then(|_| add_to_commit::<EntityAttendance>(&mut record, &attendance))
+            .and_then(|_| add_to_commit::<EntitySpecialDate>(&mut record, &special_date))
+            .and_then(|_| add_to_commit::<EntityLeavePolicy>(&mut record, &leave_policy))
+            .and_then(|_| {
+                if commit_log.required(record.len()) {
+                    commit_log.append(&record)
+                } else {
+                    Ok(())
+                }
+            });
+
+        let res = match logged {
+            Ok(()) => {
+                let results = [
+                    self.audit.flush(audit_end).context("写入审计日志失败"),
+                    write_batch(&self.project, project).context("写入项目数据失败"),
+                    write_batch(&self.employee, employee).context("写入员工数据失败"),
+                    write_batch(&self.employee_change, employee_change)
+                        .context("写入入项记录数据失败"),
+                    write_batch(&self.attendance, attendance).context("写入考勤数据失败"),
+                    write_batch(&self.special_date, special_date).context("写入特殊日期数据失败"),
+                    write_batch(&self.leave_policy, leave_policy).context("写入年假政策数据失败"),
+                ];
+
+                results
+                    .into_iter()
+                    .collect::<Result<Vec<()>>>()
+                    .and_then(|_| commit_log.clear())
+            }
+            Err(err) => {
+                requeue(&self.project, project);
+                requeue(&self.employee, employee);
+                requeue(&self.employee_change, employee_change);
+                requeue(&self.attendance, attendance);
+                requeue(&self.special_date, special_date);
+                requeue(&self.leave_policy, leave_policy);
+
+                Err(err.context("写入事务日志失败"))
+            }
+        };
+
+        self.persister.report(target, &res);
+
+        res
+    }
+
+    /// 检查各集合的数据文件是否在外部被修改, 修改时校验并重新加载, 返回重新加载的集合名, 会阻塞当前线程
+    ///
+    /// 持有事务日志锁, 期间不会有写入进行; 某个集合失败时继续检查其余集合, 返回第一个错误
+    pub fn reload_external(&self) -> Result<Vec<&'static str>> {
+        let _writer = self.commit_log.lock().unwrap();
+
+        let results = [
+            reload_collection(&self.project),
+            reload_collection(&self.employee),
+            reload_collection(&self.employee_change),
+            reload_collection(&self.attendance),
+            reload_collection(&self.special_date),
+            reload_collection(&self.leave_policy),
+        ];
+
+        let reloaded = results.into_iter().collect::<Result<Vec<Option<&str>>>>()?;
+
+        Ok(reloaded.into_iter().flatten().collect())
+    }
+
+    /// 按约定顺序阻塞地获取全部集合的写锁
+    fn blocking_write_all(&self) -> StoreWrite<'_> {
+        StoreWrite {
+            project: self.project.blocking_write(),
+            employee: self.employee.blocking_write(),
+            employee_change: self.employee_change.blocking_write(),
+            attendance: self.attendance.blocking_write(),
+            special_date: self.special_date.blocking_write(),
+            leave_policy: self.leave_policy.blocking_write(),
+        }
+    }
+}
+
+/// 将集合的一批变更加入事务日志的提交中
+fn add_to_commit<D: DB>(record: &mut CommitRecord, batch: &Option<Batch<D::Entity>>) -> Result<()> {
+    if let Some(batch) = batch {
+        record.insert(
+            D::collection_name().to_string(),
+            commit_values(&batch.entries)?,
+        );
+    }
+
+    Ok(())
+}
+
+/// 写入一个集合的变更, 失败时放回集合
+fn write_batch<T: Record + Clone>(db: &DBType<T>, batch: Option<Batch<T>>) -> Result<()> {
+    let Some(batch) = batch else {
+        return Ok(());
+    };
+
+    let res = batch.write();
+
+    if res.is_err() {
+        db.blocking_write().requeue(batch);
+    }
+
+    res
+}
+
+/// 重新加载一个集合在外部被修改的数据, 重新加载时返回集合名
+fn reload_collection<T: Record + Clone>(db: &DBType<T>) -> Result<Option<&'static str>> {
+    let mut collection = db.blocking_write();
+
+    if !collection.reload_external()? {
+        return Ok(None);
+    }
+
+    info!(
+        "数据文件在外部被修改, 已重新加载: {} ({} 条记录)",
+        collection.name(),
+        collection.len()
+    );
+
+    Ok(Some(collection.name()))
+}
+
+/// 将没有写入的一批变更放回集合, 下次写入时重试, 用于事务日志写入失败时
+fn requeue<T: Record + Clone>(db: &DBType<T>, batch: Option<Batch<T>>) {
+    if let Some(batch) = batch {
+        db.blocking_write().requeue(batch);
+    }
+}
+
+/// 全部集合的读锁
+pub struct StoreRead<'a> {
+    pub project: RwLockReadGuard<'a, Collection<EntityProject>>,
+    pub employee: RwLockReadGuard<'a, Collection<EntityEmployee>>,
+    pub employee_change: RwLockReadGuard<'a, Collection<EntityEmployeeChange>>,
+    pub attendance: RwLockReadGuard<'a, Collection<EntityAttendance>>,
+    pub special_date: RwLockReadGuard<'a, Collection<EntitySpecialDate>>,
+    pub leave_policy: RwLockReadGuard<'a, Collection<EntityLeavePolicy>>,
+}
+
+/// 全部集合的写锁
+pub struct StoreWrite<'a> {
+    pub project: RwLockWriteGuard<'a, Collection<EntityProject>>,
+    pub employee: RwLockWriteGuard<'a, Collection<EntityEmployee>>,
+    pub employee_change: RwLockWriteGuard<'a, Collection<EntityEmployeeChange>>,
+    pub attendance: RwLockWriteGuard<'a, Collection<EntityAttendance>>,
+    pub special_date: RwLockWriteGuard<'a, Collection<EntitySpecialDate>>,
+    pub leave_policy: RwLockWriteGuard<'a, Collection<EntityLeavePolicy>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+    use tokio::time::timeout;
+
+    use crate::repo::transaction::CollectionId;
+
+    use super::*;
+
+    fn store(dir: &std::path::Path) -> Store {
+        Store::load(&AppConfig::with_data_dir(dir), &Keyring::default()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn read_all_can_be_held_concurrently() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store(dir.path());
+
+        let first = store.read_all().await;
+        let second = timeout(Duration::from_secs(5), store.read_all())
+            .await
+            .expect("第二个 read_all 被阻塞");
+
+        assert_eq!(first.project.len(), second.project.len());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn overlapping_scopes_do_not_deadlock() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store(dir.path());
+
+        // 两类事务共享考勤集合, 各自还锁定一个排在它前面的集合
+        let employee_scope = Scope::new()
+            .write(CollectionId::Employee)
+            .write(CollectionId::Attendance);
+        let project_scope = Scope::new()
+            .write(CollectionId::Project)
+            .write(CollectionId::Attendance);
+
+        let mut tasks = Vec::new();
+
+        for ind in 0..50 {
+            let employees = store.clone();
+
+            tasks.push(tokio::spawn(async move {
+                employees
+                    .transaction(employee_scope, |tx| {
+                        tx.employee.put(serde_json::from_value(json!({
+                            "id": format!("e{ind}"),
+                            "name": "人员",
+                            "status": "Working",
+                            "position": "dev",
+                        }))?)?;
+                        Ok(())
+                    })
+                    .await
+            }));
+
+            let projects = store.clone();
+
+            tasks.push(tokio::spawn(async move {
+                projects
+                    .transaction(project_scope, |tx| {
+                        tx.project.put(serde_json::from_value(json!({
+                            "id": format!("p{ind}"),
+                            "name": "项目",
+                            "code": "P",
+                            "release_date": "2024-01-01",
+                            "plan_delivery_date": "2024-06-01",
+                            "tech_days": 1,
+                            "test_days": 1,
+                            "price": 1.0,
+                            "pm": "pm",
+                        }))?)?;
+                        Ok(())
+                    })
+                    .await
+            }));
+        }
+
+        timeout(Duration::from_secs(10), async {
+            for task in tasks {
+                task.await.unwrap().unwrap();
+            }
+        })
+        .await
+        .expect("事务死锁");
+
+        let all = store.read_all().await;
+        assert_eq!(all.employee.len(), 50);
+        assert_eq!(all.project.len(), 50);
+    }
+}
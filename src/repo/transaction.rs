@@ -0,0 +1,517 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result, bail};
+use log::warn;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::entity::{
+    attendance::EntityAttendance, employee::EntityEmployee, employee_change::EntityEmployeeChange,
+    leave_policy::EntityLeavePolicy, project::EntityProject, special_date::EntitySpecialDate,
+};
+
+use super::{
+    crypto::Keyring,
+    db::{Collection, Record},
+    journal::{BadLine, JournalEntry},
+    recovery::quarantine_journal_lines,
+    store::Store,
+};
+
+/// 事务日志文件名, 位于数据目录下
+pub const COMMIT_LOG_FILE: &str = "transaction.journal";
+
+/// 事务日志中的一次提交, 集合名 -> 该集合的变更
+pub type CommitRecord = BTreeMap<String, Vec<Value>>;
+
+/// 事务日志, 保证一次写入涉及的多个集合要么全部写入, 要么全部不写入
+///
+/// 一次写入涉及多个集合时, 先将全部变更作为一行追加到日志并 fsync, 之后再分别写入各集合的后端, 全部成功后清空日志;
+/// 启动时重放日志中的全部提交. 末尾不完整的一行视为未提交, 直接忽略
+pub struct CommitLog {
+    pub path: PathBuf,
+    keyring: Keyring,
+    /// 日志中存在尚未全部写入后端的提交, 此时后续的写入都需要先写入日志, 以保证重放顺序
+    pending: bool,
+}
+
+impl CommitLog {
+    pub fn new(path: PathBuf, keyring: Keyring) -> Self {
+        CommitLog {
+            pending: path.exists(),
+            path,
+            keyring,
+        }
+    }
+
+    /// 本次写入是否需要先写入日志
+    pub fn required(&self, collections: usize) -> bool {
+        collections > 1 || self.pending
+    }
+
+    /// 追加一次提交并同步到磁盘
+    pub fn append(&mut self, record: &CommitRecord) -> Result<()> {
+        let mut line = self.keyring.seal_line(&serde_json::to_string(record)?)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("打开事务日志失败: {}", self.path.display()))?;
+
+        file.write_all(line.as_bytes())?;
+        file.sync_all()?;
+
+        self.pending = true;
+
+        Ok(())
+    }
+
+    /// 读取全部提交, 无法解析的行会被隔离
+    pub fn read_all(&self) -> Result<Vec<CommitRecord>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let file = File::open(&self.path)
+            .with_context(|| format!("打开事务日志失败: {}", self.path.display()))?;
+
+        let lines: Vec<String> = BufReader::new(file).lines().collect::<Result<_, _>>()?;
+        let mut records = Vec::with_capacity(lines.len());
+        let mut bad_lines = Vec::new();
+
+        for (ind, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match self
+                .keyring
+                .open_line(line)
+                .and_then(|line| Ok(serde_json::from_str(&line)?))
+            {
+                Ok(record) => records.push(record),
+                Err(_) if ind == lines.len() - 1 => {
+                    warn!(
+                        "忽略事务日志 {} 末尾不完整的提交 (第 {} 行)",
+                        self.path.display(),
+                        ind + 1
+                    );
+                }
+                Err(err) => bad_lines.push(BadLine {
+                    line: ind + 1,
+                    content: line.clone(),
+                    error: err.to_string(),
+                }),
+            }
+        }
+
+        if !bad_lines.is_empty() {
+            quarantine_journal_lines(&self.path, &self.path, bad_lines, &self.keyring)?;
+        }
+
+        Ok(records)
+    }
+
+    /// 全部提交都已写入后端后清空日志
+    pub fn clear(&mut self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+
+        self.pending = false;
+
+        Ok(())
+    }
+}
+
+/// 将一批变更转换为事务日志中的格式
+pub fn commit_values<T: Serialize>(entries: &[JournalEntry<T>]) -> Result<Vec<Value>> {
+    entries
+        .iter()
+        .map(|entry| Ok(serde_json::to_value(entry)?))
+        .collect()
+}
+
+/// 取出全部提交中属于指定集合的变更, 按提交顺序排列
+pub fn commit_entries<T: DeserializeOwned>(
+    commits: &[CommitRecord],
+    collection: &str,
+) -> Result<Vec<JournalEntry<T>>> {
+    commits
+        .iter()
+        .filter_map(|commit| commit.get(collection))
+        .flatten()
+        .map(|value| {
+            serde_json::from_value(value.clone())
+                .with_context(|| format!("事务日志中的变更无法解析: {}", collection))
+        })
+        .collect()
+}
+
+/// 集合, 按约定的加锁顺序排列, 参考 [Store]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CollectionId {
+    Project,
+    Employee,
+    EmployeeChange,
+    Attendance,
+    SpecialDate,
+    LeavePolicy,
+}
+
+/// 事务需要访问的集合, 写入的集合加写锁, 只读取的集合加读锁, 其余集合不加锁
+///
+/// 访问未声明的集合或写入只声明读取的集合时返回错误, 事务随之回滚
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Scope {
+    write: u8,
+    read: u8,
+}
+
+/// 事务对单个集合的访问方式
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Access {
+    Write,
+    Read,
+    None,
+}
+
+impl Scope {
+    /// 写入全部集合
+    pub const ALL: Scope = Scope {
+        write: 0b11_1111,
+        read: 0,
+    };
+
+    pub const fn new() -> Self {
+        Scope { write: 0, read: 0 }
+    }
+
+    /// 写入 id 对应的集合
+    pub const fn write(mut self, id: CollectionId) -> Self {
+        self.write |= 1 << id as u8;
+        self
+    }
+
+    /// 读取 id 对应的集合
+    pub const fn read(mut self, id: CollectionId) -> Self {
+        self.read |= 1 << id as u8;
+        self
+    }
+
+    fn access(&self, id: CollectionId) -> Access {
+        let bit = 1 << id as u8;
+
+        if self.write & bit != 0 {
+            Access::Write
+        } else if self.read & bit != 0 {
+            Access::Read
+        } else {
+            Access::None
+        }
+    }
+}
+
+/// 事务对单个集合持有的锁
+enum Guard<'a, T> {
+    Write(RwLockWriteGuard<'a, Collection<T>>),
+    Read(RwLockReadGuard<'a, Collection<T>>),
+    /// 未声明, 保存集合名用于错误信息
+    Unlocked(&'static str),
+}
+
+/// 事务中的单个集合, 第一次修改前开始记录撤销日志, 事务失败时撤销
+pub struct Staged<'a, T> {
+    guard: Guard<'a, T>,
+    /// 是否已开始记录撤销日志
+    touched: bool,
+}
+
+impl<'a, T: Record + Clone> Staged<'a, T> {
+    async fn lock(db: &'a RwLock<Collection<T>>, access: Access, name: &'static str) -> Self {
+        let guard = match access {
+            Access::Write => Guard::Write(db.write().await),
+            Access::Read => Guard::Read(db.read().await),
+            Access::None => Guard::Unlocked(name),
+        };
+
+        Staged {
+            guard,
+            touched: false,
+        }
+    }
+
+    /// 读取集合, 事务没有声明访问该集合时返回错误
+    pub fn read(&self) -> Result<&Collection<T>> {
+        match &self.guard {
+            Guard::Write(collection) => Ok(collection),
+            Guard::Read(collection) => Ok(collection),
+            Guard::Unlocked(name) => bail!("事务没有声明访问集合 {}", name),
+        }
+    }
+
+    /// 参考 [Collection::put]
+    pub fn put(&mut self, record: T) -> Result<&T> {
+        Ok(self.touch()?.put(record))
+    }
+
+    /// 参考 [Collection::soft_delete]
+    pub fn soft_delete(&mut self, id: &str) -> Result<Option<&T>> {
+        Ok(self.touch()?.soft_delete(id))
+    }
+
+    /// 参考 [Collection::restore]
+    pub fn restore(&mut self, id: &str) -> Result<Option<&T>> {
+        Ok(self.touch()?.restore(id))
+    }
+
+    /// 参考 [Collection::remove_by_id]
+    pub fn remove_by_id(&mut self, id: &str) -> Result<Option<T>> {
+        Ok(self.touch()?.remove_by_id(id))
+    }
+
+    /// 参考 [Collection::replace_all]
+    pub fn replace_all(&mut self, rows: Vec<T>) -> Result<()> {
+        self.touch()?.replace_all(rows);
+        Ok(())
+    }
+
+    /// 事务中被修改过的记录, 参考 [Collection::changed_records], 未修改时为空
+    pub fn changed(&self) -> Result<Option<Vec<&T>>> {
+        if !self.touched {
+            return Ok(Some(Vec::new()));
+        }
+
+        Ok(self.read()?.changed_records())
+    }
+
+    /// 取得集合的写入权限, 事务没有声明写入该集合时返回错误
+    fn touch(&mut self) -> Result<&mut Collection<T>> {
+        let collection = match &mut self.guard {
+            Guard::Write(collection) => collection,
+            Guard::Read(collection) => bail!("事务没有声明写入集合 {}", collection.name()),
+            Guard::Unlocked(name) => bail!("事务没有声明访问集合 {}", name),
+        };
+
+        if !self.touched {
+            collection.begin();
+            self.touched = true;
+        }
+
+        Ok(collection)
+    }
+
+    /// 事务结束, committed 为 false 时撤销全部修改
+    fn finish(&mut self, committed: bool) {
+        if let Guard::Write(collection) = &mut self.guard
+            && self.touched
+        {
+            if committed {
+                collection.release();
+            } else {
+                collection.rollback();
+            }
+        }
+
+        self.touched = false;
+    }
+}
+
+/// 跨集合的事务, 通过 [Store::transaction] 创建
+///
+/// 事务期间按声明的 [Scope] 持有各集合的锁, 修改直接作用于内存; 事务返回错误 (或 panic) 时撤销全部修改,
+/// 成功时全部变更进入同一批写入, 由 [CommitLog] 保证要么全部写入磁盘, 要么全部不写入
+pub struct Transaction<'a> {
+    pub project: Staged<'a, EntityProject>,
+    pub employee: Staged<'a, EntityEmployee>,
+    pub employee_change: Staged<'a, EntityEmployeeChange>,
+    pub attendance: Staged<'a, EntityAttendance>,
+    pub special_date: Staged<'a, EntitySpecialDate>,
+    pub leave_policy: Staged<'a, EntityLeavePolicy>,
+    committed: bool,
+}
+
+impl<'a> Transaction<'a> {
+    /// 按约定顺序获取 scope 声明的集合的锁
+    pub async fn lock(store: &'a Store, scope: Scope) -> Self {
+        use CollectionId::*;
+
+        Transaction {
+            project: Staged::lock(&store.project, scope.access(Project), "project").await,
+            employee: Staged::lock(&store.employee, scope.access(Employee), "employee").await,
+            employee_change: Staged::lock(
+                &store.employee_change,
+                scope.access(EmployeeChange),
+                "employee_change",
+            )
+            .await,
+            attendance: Staged::lock(&store.attendance, scope.access(Attendance), "attendance")
+                .await,
+            special_date: Staged::lock(
+                &store.special_date,
+                scope.access(SpecialDate),
+                "special_date",
+            )
+            .await,
+            leave_policy: Staged::lock(
+                &store.leave_policy,
+                scope.access(LeavePolicy),
+                "leave_policy",
+            )
+            .await,
+            committed: false,
+        }
+    }
+
+    /// 确认事务, 之后不再回滚
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        let committed = self.committed;
+
+        self.project.finish(committed);
+        self.employee.finish(committed);
+        self.employee_change.finish(committed);
+        self.attendance.finish(committed);
+        self.special_date.finish(committed);
+        self.leave_policy.finish(committed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::bail;
+
+    use crate::{
+        config::AppConfig,
+        repo::{db::DB, index::IndexKey, tests::special_date},
+    };
+
+    use super::*;
+
+    /// 记录id, 新记录在前
+    fn ids(collection: &Collection<EntitySpecialDate>) -> Vec<&str> {
+        collection.iter().map(|p| p.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn rollback_restores_rows_index_and_audit() {
+        let dir = tempfile::tempdir().unwrap();
+        let store =
+            Store::load(&AppConfig::with_data_dir(dir.path()), &Keyring::default()).unwrap();
+
+        store
+            .transaction(EntitySpecialDate::scope(), |tx| {
+                for (id, date) in [
+                    ("a", "2024-01-01"),
+                    ("b", "2024-01-02"),
+                    ("c", "2024-01-03"),
+                ] {
+                    tx.special_date.put(special_date(id, date))?;
+                }
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        let audit = store.audit.next_seq();
+
+        let res: Result<()> = store
+            .transaction(EntitySpecialDate::scope(), |tx| {
+                tx.special_date.put(special_date("d", "2024-01-04"))?;
+                tx.special_date.put(special_date("a", "2024-02-01"))?;
+                tx.special_date.remove_by_id("b")?;
+                tx.special_date.soft_delete("c")?;
+                tx.special_date.remove_by_id("a")?;
+                bail!("失败")
+            })
+            .await;
+        assert!(res.is_err());
+
+        let collection = store.special_date.read().await;
+        assert_eq!(ids(&collection), ["c", "b", "a"]);
+        assert_eq!(
+            collection.get("a").unwrap().start_time.to_string(),
+            "2024-01-01"
+        );
+        assert!(collection.get("c").is_some());
+        assert!(collection.get("d").is_none());
+        assert_eq!(
+            collection
+                .list_by(IndexKey::Date, "2024-01-01", |_| true)
+                .len(),
+            1
+        );
+        assert!(
+            collection
+                .list_by(IndexKey::Date, "2024-02-01", |_| true)
+                .is_empty()
+        );
+        assert!(store.audit.request_entries(audit, "").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn undeclared_collections_are_not_locked() {
+        let dir = tempfile::tempdir().unwrap();
+        let store =
+            Store::load(&AppConfig::with_data_dir(dir.path()), &Keyring::default()).unwrap();
+
+        let _employee = store.employee.write().await;
+
+        let res = tokio::time::timeout(
+            Duration::from_secs(1),
+            store.transaction(EntitySpecialDate::scope(), |tx| {
+                tx.special_date.put(special_date("a", "2024-01-01"))?;
+                Ok(())
+            }),
+        )
+        .await;
+
+        assert!(res.is_ok());
+    }
+
+    #[tokio::test]
+    async fn undeclared_access_is_an_error_and_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let store =
+            Store::load(&AppConfig::with_data_dir(dir.path()), &Keyring::default()).unwrap();
+
+        let scope = Scope::new()
+            .read(CollectionId::Attendance)
+            .write(CollectionId::SpecialDate);
+
+        let res: Result<()> = store
+            .transaction(scope, |tx| {
+                tx.special_date.put(special_date("a", "2024-01-01"))?;
+                tx.employee.read()?;
+                Ok(())
+            })
+            .await;
+        assert!(res.unwrap_err().to_string().contains("employee"));
+
+        let res: Result<()> = store
+            .transaction(scope, |tx| {
+                tx.special_date.put(special_date("a", "2024-01-01"))?;
+                tx.attendance.remove_by_id("x")?;
+                Ok(())
+            })
+            .await;
+        assert!(res.unwrap_err().to_string().contains("写入集合 attendance"));
+
+        assert!(store.special_date.read().await.is_empty());
+    }
+}
@@ -0,0 +1,297 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Local, TimeDelta};
+use log::{error, info, warn};
+
+use crate::entity::{
+    attendance::EntityAttendance, employee::EntityEmployee, employee_change::EntityEmployeeChange,
+    leave_policy::EntityLeavePolicy, project::EntityProject, special_date::EntitySpecialDate,
+};
+
+use super::{
+    db::{DB, Record},
+    store::Store,
+    transaction::{Scope, Transaction},
+};
+
+/// 自动清理的检查间隔
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 从回收站恢复记录, 记录引用的其他记录需要存在 (未被删除), 回收站中不存在该记录时返回 None
+pub fn restore<D: DB>(tx: &mut Transaction, id: &str) -> Result<Option<D::Entity>> {
+    let Some(restored) = D::staged(tx).restore(id)?.cloned() else {
+        return Ok(None);
+    };
+
+    D::check_references(tx, &restored)?;
+    D::validate(tx, &restored)?;
+
+    Ok(Some(restored))
+}
+
+/// 彻底删除回收站中的记录, 回收站中不存在该记录时返回 None
+///
+/// 仍有记录 (包括回收站中的记录) 引用该记录时拒绝删除, 需要先彻底删除引用它的记录
+pub fn purge<D: DB>(tx: &mut Transaction, id: &str) -> Result<Option<D::Entity>> {
+    if D::staged(tx).read()?.get_deleted(id).is_none() {
+        return Ok(None);
+    }
+
+    D::check_unreferenced(tx, id)?;
+
+    D::staged(tx).remove_by_id(id)
+}
+
+/// 彻底删除在回收站中超过 days 天的记录, 返回删除的数量
+///
+/// 先清理引用其他记录的集合, 仍被引用的记录留在回收站中, 等引用它的记录被清理后再删除
+pub async fn purge_expired(store: &Store, days: u64) -> Result<usize> {
+    let before = Local::now() - TimeDelta::days(days as i64);
+
+    store
+        .transaction(Scope::ALL, |tx| {
+            Ok(purge_before::<EntityEmployeeChange>(tx, before)?
+                + purge_before::<EntityAttendance>(tx, before)?
+                + purge_before::<EntityLeavePolicy>(tx, before)?
+                + purge_before::<EntitySpecialDate>(tx, before)?
+                + purge_before::<EntityProject>(tx, before)?
+                + purge_before::<EntityEmployee>(tx, before)?)
+        })
+        .await
+}
+
+/// 启动自动清理任务, 每小时清理一次在回收站中超过 days 天的记录
+pub fn spawn_auto_purge(store: Store, days: u64) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(PURGE_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+
+            match purge_expired(&store, days).await {
+                Ok(0) => {}
+                Ok(count) => info!("已自动清理回收站中的 {} 条记录", count),
+                Err(err) => error!("自动清理回收站失败: {:#}", err),
+            }
+        }
+    });
+}
+
+fn purge_before<D: DB>(tx: &mut Transaction, before: DateTime<Local>) -> Result<usize> {
+    let ids: Vec<String> = D::staged(tx)
+        .read()?
+        .trash()
+        .into_iter()
+        .filter(|p| p.deleted_at().is_some_and(|time| time < before))
+        .map(|p| p.id().to_string())
+        .collect();
+
+    let mut count = 0;
+
+    for id in &ids {
+        if let Err(err) = D::check_unreferenced(tx, id) {
+            warn!(
+                "回收站中的{}记录 {} 暂不清理: {:#}",
+                D::collection_name(),
+                id,
+                err
+            );
+            continue;
+        }
+
+        D::staged(tx).remove_by_id(id)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        config::{AppConfig, IntegrityConfig},
+        repo::{crypto::Keyring, integrity::delete_employee},
+    };
+
+    use super::*;
+
+    /// 人员 e, 以及 e 的入项记录 c 和考勤记录 a
+    async fn store_with_employee(dir: &std::path::Path) -> Store {
+        let store = Store::load(&AppConfig::with_data_dir(dir), &Keyring::default()).unwrap();
+
+        store
+            .transaction(Scope::ALL, |tx| {
+                tx.employee.put(serde_json::from_value(json!({
+                    "id": "e",
+                    "name": "人员",
+                    "status": "Working",
+                    "position": "dev",
+                }))?)?;
+                tx.employee_change.put(serde_json::from_value(json!({
+                    "id": "c",
+                    "employee_id": "e",
+                    "in_time": "2024-01-01",
+                }))?)?;
+                tx.attendance.put(serde_json::from_value(json!({
+                    "id": "a",
+                    "employee_id": "e",
+                    "start_time": "2024-01-02",
+                    "date_type": "Leave",
+                    "start_half": false,
+                    "end_half": false,
+                }))?)?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        store
+    }
+
+    /// 按默认规则删除人员 e, 入项记录和考勤记录级联移入回收站
+    async fn delete(store: &Store) {
+        store
+            .transaction(EntityEmployee::scope(), |tx| {
+                delete_employee(tx, &IntegrityConfig::default(), "e")
+            })
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    async fn purge_one<D: DB>(store: &Store, id: &str) -> Result<Option<D::Entity>> {
+        store.transaction(D::scope(), |tx| purge::<D>(tx, id)).await
+    }
+
+    async fn restore_one<D: DB>(store: &Store, id: &str) -> Result<Option<D::Entity>> {
+        store
+            .transaction(D::scope(), |tx| restore::<D>(tx, id))
+            .await
+    }
+
+    #[tokio::test]
+    async fn soft_delete_moves_records_to_trash() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with_employee(dir.path()).await;
+
+        delete(&store).await;
+
+        let employees = store.employee.read().await;
+        assert!(employees.get("e").is_none());
+        assert!(employees.get_deleted("e").is_some());
+        assert_eq!(employees.trash().len(), 1);
+
+        let changes = store.employee_change.read().await;
+        assert!(changes.get("c").is_none());
+        assert!(changes.get_deleted("c").is_some());
+    }
+
+    #[tokio::test]
+    async fn restore_requires_referenced_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with_employee(dir.path()).await;
+
+        delete(&store).await;
+
+        // 人员仍在回收站中时不能单独恢复考勤记录
+        let err = restore_one::<EntityAttendance>(&store, "a")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("人员不存在"));
+        assert!(store.attendance.read().await.get_deleted("a").is_some());
+
+        restore_one::<EntityEmployee>(&store, "e")
+            .await
+            .unwrap()
+            .unwrap();
+        restore_one::<EntityAttendance>(&store, "a")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(store.attendance.read().await.get("a").is_some());
+
+        // 未删除的记录不能恢复
+        assert!(
+            restore_one::<EntityEmployee>(&store, "e")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn purge_refuses_records_referenced_from_trash() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with_employee(dir.path()).await;
+
+        // 未删除的记录不能彻底删除
+        assert!(
+            purge_one::<EntityEmployee>(&store, "e")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        delete(&store).await;
+
+        let err = purge_one::<EntityEmployee>(&store, "e").await.unwrap_err();
+        assert!(err.to_string().contains("回收站"));
+        assert!(store.employee.read().await.get_deleted("e").is_some());
+
+        purge_one::<EntityEmployeeChange>(&store, "c")
+            .await
+            .unwrap()
+            .unwrap();
+        purge_one::<EntityAttendance>(&store, "a")
+            .await
+            .unwrap()
+            .unwrap();
+        purge_one::<EntityEmployee>(&store, "e")
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(store.employee.read().await.find_by_id("e").is_none());
+    }
+
+    #[tokio::test]
+    async fn purge_expired_removes_referencing_records_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with_employee(dir.path()).await;
+
+        delete(&store).await;
+
+        // 直接写入一条引用回收站中人员的入项记录, 模拟人员仍被未删除的记录引用
+        store
+            .transaction(Scope::ALL, |tx| {
+                tx.employee_change.put(serde_json::from_value(json!({
+                    "id": "kept",
+                    "employee_id": "e",
+                    "in_time": "2024-02-01",
+                }))?)?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        // 仍被未删除的入项记录引用, 人员留在回收站中
+        assert_eq!(purge_expired(&store, 0).await.unwrap(), 2);
+        assert!(store.employee.read().await.get_deleted("e").is_some());
+        assert!(store.attendance.read().await.find_by_id("a").is_none());
+
+        store
+            .transaction(Scope::ALL, |tx| {
+                tx.employee_change.soft_delete("kept")?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        // 入项记录和人员在同一次清理中删除
+        assert_eq!(purge_expired(&store, 0).await.unwrap(), 2);
+        assert!(store.employee.read().await.find_by_id("e").is_none());
+    }
+}
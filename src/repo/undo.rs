@@ -0,0 +1,476 @@
+use std::{cell::Cell, collections::HashMap, sync::Mutex};
+
+use anyhow::{Context, Result, bail};
+use axum::{
+    extract::{Request, State},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+use log::error;
+use serde_json::{Map, Value};
+
+use crate::entity::{
+    attendance::EntityAttendance,
+    audit::{AuditOp, EntityAudit, FieldChange},
+    employee::EntityEmployee,
+    employee_change::EntityEmployeeChange,
+    leave_policy::EntityLeavePolicy,
+    project::EntityProject,
+    special_date::EntitySpecialDate,
+};
+
+use super::{
+    audit::{ANONYMOUS_ACTOR, current_actor, current_request_id},
+    db::DB,
+    store::Store,
+    transaction::{Scope, Transaction},
+};
+
+/// 每个操作人最多保留的撤销步骤数
+const MAX_STEPS: usize = 50;
+
+/// 一次请求中的全部变更, 作为一个撤销单位, 按变更顺序排列
+pub type UndoStep = Vec<EntityAudit>;
+
+/// 撤销或重做
+#[derive(Clone, Copy, PartialEq)]
+pub enum Direction {
+    Undo,
+    Redo,
+}
+
+impl Direction {
+    fn name(self) -> &'static str {
+        match self {
+            Direction::Undo => "撤销",
+            Direction::Redo => "重做",
+        }
+    }
+
+    /// 字段变化中记录当前应有的值和操作后的值
+    fn sides(self, change: &FieldChange) -> (&Value, &Value) {
+        match self {
+            Direction::Undo => (&change.after, &change.before),
+            Direction::Redo => (&change.before, &change.after),
+        }
+    }
+}
+
+/// 单个操作人的撤销栈和重做栈
+#[derive(Default)]
+struct History {
+    undo: Vec<UndoStep>,
+    redo: Vec<UndoStep>,
+}
+
+impl History {
+    fn stack(&mut self, direction: Direction) -> &mut Vec<UndoStep> {
+        match direction {
+            Direction::Undo => &mut self.undo,
+            Direction::Redo => &mut self.redo,
+        }
+    }
+}
+
+/// 按操作人区分的撤销历史, 由审计记录构成, 只保存在内存中
+#[derive(Default)]
+pub struct UndoHistory {
+    actors: Mutex<HashMap<String, History>>,
+}
+
+impl UndoHistory {
+    /// 记录操作人的一次请求, 同时清空其重做栈
+    ///
+    /// 包含整体替换 (如从备份恢复) 时之前的历史都已失效, 清空全部操作人的历史;
+    /// 未指定操作人的请求无法区分来源, 不记入历史
+    pub fn push(&self, actor: &str, step: UndoStep) {
+        let mut actors = self.actors.lock().unwrap();
+
+        if step.iter().any(|p| p.op == AuditOp::Replace) {
+            actors.clear();
+            return;
+        }
+
+        if actor == ANONYMOUS_ACTOR {
+            return;
+        }
+
+        let history = actors.entry(actor.to_string()).or_default();
+        history.redo.clear();
+        history.undo.push(step);
+
+        if history.undo.len() > MAX_STEPS {
+            history.undo.remove(0);
+        }
+    }
+
+    /// 取出最近一步
+    fn pop(&self, actor: &str, direction: Direction) -> Option<UndoStep> {
+        self.actors
+            .lock()
+            .unwrap()
+            .get_mut(actor)?
+            .stack(direction)
+            .pop()
+    }
+
+    /// 放回一步
+    fn push_to(&self, actor: &str, direction: Direction, step: UndoStep) {
+        self.actors
+            .lock()
+            .unwrap()
+            .entry(actor.to_string())
+            .or_default()
+            .stack(direction)
+            .push(step);
+    }
+}
+
+tokio::task_local! {
+    /// 当前请求的变更是否记入撤销历史
+    static TRACKING: Cell<bool>;
+}
+
+/// 当前请求的变更不记入撤销历史, 用于撤销和重做本身
+pub fn skip_tracking() {
+    let _ = TRACKING.try_with(|tracking| tracking.set(false));
+}
+
+/// 非 GET 请求完成后, 将本次请求产生的变更作为一步记入操作人的撤销历史
+///
+/// 需要在 [audit_actor](super::audit::audit_actor) 之内
+pub async fn track_undo(State(store): State<Store>, request: Request, next: Next) -> Response {
+    if request.method() == Method::GET {
+        return next.run(request).await;
+    }
+
+    let start = store.audit.next_seq();
+
+    let (tracking, response) = TRACKING
+        .scope(Cell::new(true), async {
+            let response = next.run(request).await;
+            (TRACKING.with(|tracking| tracking.get()), response)
+        })
+        .await;
+
+    if tracking && let Some(request_id) = current_request_id() {
+        match store.audit.request_entries(start, &request_id) {
+            Ok(step) if !step.is_empty() => store.undo.push(&current_actor(), step),
+            Ok(_) => {}
+            Err(err) => error!("读取审计日志失败, 本次变更无法撤销: {:#}", err),
+        }
+    }
+
+    response
+}
+
+/// 撤销或重做操作人最近的一步, 返回该步的变更, 没有可操作的步骤时返回 None
+///
+/// 一步中的全部变更在同一事务中完成; 记录在此之后被其他请求修改过时拒绝操作, 该步保留在原栈中.
+/// 未指定操作人时拒绝操作, 避免撤销其他客户端的变更
+pub async fn apply(store: &Store, actor: &str, direction: Direction) -> Result<Option<UndoStep>> {
+    if actor == ANONYMOUS_ACTOR {
+        bail!("{}需要通过请求头 x-actor 指定操作人", direction.name());
+    }
+
+    let Some(step) = store.undo.pop(actor, direction) else {
+        return Ok(None);
+    };
+
+    let res = store
+        .transaction(Scope::ALL, |tx| apply_step(tx, &step, direction))
+        .await;
+
+    let target = match (&res, direction) {
+        (Err(_), _) => direction,
+        (Ok(()), Direction::Undo) => Direction::Redo,
+        (Ok(()), Direction::Redo) => Direction::Undo,
+    };
+
+    store.undo.push_to(actor, target, step.clone());
+
+    res.map(|_| Some(step))
+}
+
+/// 撤销时倒序恢复每条变更前的值, 重做时顺序应用变更后的值, 全部完成后再校验关联
+///
+/// 关联放在最后校验, 以便撤销级联删除时先恢复子记录再恢复被引用的记录
+fn apply_step(tx: &mut Transaction, step: &UndoStep, direction: Direction) -> Result<()> {
+    let entries: Vec<&EntityAudit> = match direction {
+        Direction::Undo => step.iter().rev().collect(),
+        Direction::Redo => step.iter().collect(),
+    };
+
+    for entry in &entries {
+        match entry.entity.as_str() {
+            "project" => apply_change::<EntityProject>(tx, entry, direction)?,
+            "employee" => apply_change::<EntityEmployee>(tx, entry, direction)?,
+            "employee_change" => apply_change::<EntityEmployeeChange>(tx, entry, direction)?,
+            "attendance" => apply_change::<EntityAttendance>(tx, entry, direction)?,
+            "special_date" => apply_change::<EntitySpecialDate>(tx, entry, direction)?,
+            "leave_policy" => apply_change::<EntityLeavePolicy>(tx, entry, direction)?,
+            other => bail!("未知集合: {}", other),
+        }
+    }
+
+    for entry in &entries {
+        match entry.entity.as_str() {
+            "project" => check_change::<EntityProject>(tx, entry)?,
+            "employee" => check_change::<EntityEmployee>(tx, entry)?,
+            "employee_change" => check_change::<EntityEmployeeChange>(tx, entry)?,
+            "attendance" => check_change::<EntityAttendance>(tx, entry)?,
+            "special_date" => check_change::<EntitySpecialDate>(tx, entry)?,
+            "leave_policy" => check_change::<EntityLeavePolicy>(tx, entry)?,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// 将一条变更应用到集合, 记录当前的值需要与变更的另一侧一致
+fn apply_change<D: DB>(
+    tx: &mut Transaction,
+    entry: &EntityAudit,
+    direction: Direction,
+) -> Result<()> {
+    // 新增之前和彻底删除之后记录不存在
+    let (expected_absent, target_absent) = match (entry.op, direction) {
+        (AuditOp::Create, Direction::Undo) | (AuditOp::Purge, Direction::Redo) => (false, true),
+        (AuditOp::Create, Direction::Redo) | (AuditOp::Purge, Direction::Undo) => (true, false),
+        (AuditOp::Replace, _) => bail!("整体替换无法{}", direction.name()),
+        _ => (false, false),
+    };
+
+    let staged = D::staged(tx);
+
+    let current = staged
+        .read()?
+        .find_by_id(&entry.record_id)
+        .map(serde_json::to_value)
+        .transpose()?;
+
+    match &current {
+        Some(_) if expected_absent => {
+            bail!("记录已存在, 无法{}: {}", direction.name(), entry.record_id)
+        }
+        None if !expected_absent => {
+            bail!(
+                "记录已不存在, 无法{}: {}",
+                direction.name(),
+                entry.record_id
+            )
+        }
+        Some(current) => {
+            let modified = entry
+                .changes
+                .iter()
+                .any(|(k, c)| current.get(k).unwrap_or(&Value::Null) != direction.sides(c).0);
+
+            if modified {
+                bail!(
+                    "记录已被修改, 无法{}: {}",
+                    direction.name(),
+                    entry.record_id
+                );
+            }
+        }
+        None => {}
+    }
+
+    if target_absent {
+        staged.remove_by_id(&entry.record_id)?;
+        return Ok(());
+    }
+
+    let mut fields = match current {
+        Some(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    };
+
+    // 审计记录不包含版本号, 版本号由集合在写入时维护
+    for (k, c) in &entry.changes {
+        fields.insert(k.clone(), direction.sides(c).1.clone());
+    }
+
+    let record: D::Entity = serde_json::from_value(Value::Object(fields))
+        .with_context(|| format!("无法还原记录: {}", entry.record_id))?;
+
+    staged.put(record)?;
+
+    Ok(())
+}
+
+/// 校验变更后未删除的记录引用的记录存在且满足业务规则, 被移除的记录没有被引用
+fn check_change<D: DB>(tx: &mut Transaction, entry: &EntityAudit) -> Result<()> {
+    let staged = D::staged(tx);
+
+    if let Some(record) = staged.read()?.get(&entry.record_id).cloned() {
+        D::check_references(tx, &record)?;
+        D::validate(tx, &record)
+    } else if staged.read()?.find_by_id(&entry.record_id).is_none() {
+        D::check_unreferenced(tx, &entry.record_id)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::tests::TestApp;
+
+    use super::*;
+
+    const ALICE: &[(&str, &str)] = &[("x-actor", "alice")];
+
+    /// 人员及其一条请假记录, 返回人员id和考勤记录id
+    async fn employee_with_leave(app: &TestApp) -> (String, String) {
+        let (_, res) = app
+            .send(
+                "POST",
+                "/employee/create",
+                ALICE,
+                Some(json!({ "name": "人员", "position": "dev" })),
+            )
+            .await;
+        let employee_id = res["data"]["id"].as_str().unwrap().to_string();
+
+        let (_, res) = app
+            .send(
+                "POST",
+                "/attendance/create",
+                ALICE,
+                Some(json!({
+                    "employee_id": employee_id,
+                    "start_time": "2026-10-19",
+                    "date_type": "Leave",
+                    "start_half": false,
+                    "end_half": false,
+                })),
+            )
+            .await;
+        let attendance_id = res["data"]["id"].as_str().unwrap().to_string();
+
+        (employee_id, attendance_id)
+    }
+
+    async fn exists(app: &TestApp, employee_id: &str, attendance_id: &str) -> (bool, bool) {
+        (
+            app.store.employee.read().await.get(employee_id).is_some(),
+            app.store
+                .attendance
+                .read()
+                .await
+                .get(attendance_id)
+                .is_some(),
+        )
+    }
+
+    #[tokio::test]
+    async fn undo_and_redo_a_cascaded_delete() {
+        let app = TestApp::new().await;
+        let (employee_id, attendance_id) = employee_with_leave(&app).await;
+
+        // 删除人员时考勤记录级联移入回收站, 两个集合的变更为同一步
+        let uri = format!("/employee/delete/{}", employee_id);
+        let (_, res) = app.send("POST", &uri, ALICE, None).await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+        assert_eq!(
+            exists(&app, &employee_id, &attendance_id).await,
+            (false, false)
+        );
+
+        let (_, res) = app.send("POST", "/undo", ALICE, None).await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+        assert_eq!(res["data"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            exists(&app, &employee_id, &attendance_id).await,
+            (true, true)
+        );
+
+        let (_, res) = app.send("POST", "/redo", ALICE, None).await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+        assert_eq!(
+            exists(&app, &employee_id, &attendance_id).await,
+            (false, false)
+        );
+    }
+
+    #[tokio::test]
+    async fn new_write_clears_redo() {
+        let app = TestApp::new().await;
+        let (employee_id, _) = employee_with_leave(&app).await;
+
+        let (_, res) = app.send("POST", "/undo", ALICE, None).await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+
+        let uri = format!("/employee/update/{}", employee_id);
+        app.send("POST", &uri, ALICE, Some(json!({ "name": "新名字" })))
+            .await;
+
+        let (_, res) = app.send("POST", "/redo", ALICE, None).await;
+        assert_eq!(res["msg"], "没有可重做的操作");
+    }
+
+    #[tokio::test]
+    async fn undo_is_refused_after_another_change() {
+        let app = TestApp::new().await;
+        let (employee_id, _) = employee_with_leave(&app).await;
+
+        let uri = format!("/employee/update/{}", employee_id);
+        app.send("POST", &uri, ALICE, Some(json!({ "name": "新名字" })))
+            .await;
+
+        // 其他操作人在此之后修改了同一条记录
+        app.send(
+            "POST",
+            &uri,
+            &[("x-actor", "bob")],
+            Some(json!({ "name": "bob 的名字" })),
+        )
+        .await;
+
+        let (_, res) = app.send("POST", "/undo", ALICE, None).await;
+        assert_eq!(res["code"], "Err");
+        assert!(res["msg"].as_str().unwrap().contains("记录已被修改"));
+
+        // 被拒绝的一步保留在撤销栈中
+        let cur = app.store.employee.read().await.get(&employee_id).cloned();
+        assert_eq!(cur.unwrap().name, "bob 的名字");
+        assert_eq!(
+            app.store
+                .undo
+                .pop("alice", Direction::Undo)
+                .map(|p| p.len()),
+            Some(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn anonymous_requests_have_no_history() {
+        let app = TestApp::new().await;
+
+        app.send(
+            "POST",
+            "/employee/create",
+            &[],
+            Some(json!({ "name": "人员", "position": "dev" })),
+        )
+        .await;
+
+        let (_, res) = app.send("POST", "/undo", &[], None).await;
+        assert_eq!(res["code"], "Err");
+        assert!(res["msg"].as_str().unwrap().contains("x-actor"));
+        assert_eq!(app.store.employee.read().await.list(|_| true).len(), 1);
+
+        assert!(
+            app.store
+                .undo
+                .pop(ANONYMOUS_ACTOR, Direction::Undo)
+                .is_none()
+        );
+    }
+}
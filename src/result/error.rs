@@ -1,15 +1,25 @@
+use std::fmt;
+
 use axum::response::{IntoResponse, Response};
 
-use super::response::AppResponse;
+use super::{response::AppResponse, response_code::AppResponseCode};
 
 /// 应用通用错误类型
 pub struct AppError(pub anyhow::Error);
 
-// 支持 AppError 作为响应
+// 支持 AppError 作为响应, 版本冲突使用 [AppResponseCode::Conflict]
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let res = AppResponse::<()>::err(self.0.to_string());
-        res.into_response()
+        let code = if self.0.downcast_ref::<VersionConflict>().is_some() {
+            AppResponseCode::Conflict
+        } else {
+            AppResponseCode::Err
+        };
+
+        AppResponse::<()>::new()
+            .code(code)
+            .msg(self.0.to_string())
+            .into_response()
     }
 }
 
@@ -22,3 +32,24 @@ where
         Self(err.into())
     }
 }
+
+/// 版本冲突, 请求头 If-Match 中的版本号与记录当前的版本号不一致
+#[derive(Debug)]
+pub struct VersionConflict {
+    /// If-Match 中的版本号
+    pub expected: u64,
+    /// 记录当前的版本号
+    pub current: u64,
+}
+
+impl fmt::Display for VersionConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "记录已被修改, 请刷新后重试: 期望版本 {}, 当前版本 {}",
+            self.expected, self.current
+        )
+    }
+}
+
+impl std::error::Error for VersionConflict {}
@@ -0,0 +1,206 @@
+use anyhow::Result;
+use axum::{
+    extract::FromRequestParts,
+    http::{
+        HeaderValue,
+        header::{ETAG, IF_MATCH},
+        request::Parts,
+    },
+};
+
+use crate::repo::db::Record;
+
+use super::{
+    error::{AppError, VersionConflict},
+    response::AppResult,
+};
+
+/// 为响应加上 ETag 头, 值为记录的版本号, 记录不存在时不加
+pub fn with_etag(res: AppResult, version: Option<u64>) -> AppResult {
+    let mut res = res?;
+
+    if let Some(version) = version {
+        res.headers_mut().insert(
+            ETAG,
+            HeaderValue::from_str(&format!("\"{}\"", version)).unwrap(),
+        );
+    }
+
+    Ok(res)
+}
+
+/// 请求头 If-Match 中的版本号, 未提供或为 * 时不校验
+///
+/// 支持 "3" W/"3" 和 3 的写法
+pub struct IfMatch(pub Option<u64>);
+
+impl IfMatch {
+    /// 校验记录当前的版本号, 不一致时返回 [VersionConflict]
+    pub fn check<T: Record>(&self, record: &T) -> Result<()> {
+        match self.0 {
+            Some(expected) if expected != record.version() => Err(VersionConflict {
+                expected,
+                current: record.version(),
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.headers.get(IF_MATCH) else {
+            return Ok(IfMatch(None));
+        };
+
+        let value = value.to_str().unwrap_or_default().trim();
+
+        if value == "*" {
+            return Ok(IfMatch(None));
+        }
+
+        let version = value
+            .trim_start_matches("W/")
+            .trim_matches('"')
+            .parse()
+            .map_err(|_| anyhow::anyhow!("If-Match 格式错误: {}", value))?;
+
+        Ok(IfMatch(Some(version)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderMap;
+    use serde_json::{Value, json};
+
+    use crate::tests::TestApp;
+
+    use super::*;
+
+    const ACTOR: (&str, &str) = ("x-actor", "alice");
+
+    fn etag(headers: &HeaderMap) -> Option<&str> {
+        headers.get(ETAG).map(|v| v.to_str().unwrap())
+    }
+
+    async fn create(app: &TestApp) -> String {
+        let (_, res) = app
+            .send(
+                "POST",
+                "/employee/create",
+                &[ACTOR],
+                Some(json!({ "name": "人员", "position": "dev" })),
+            )
+            .await;
+
+        res["data"]["id"].as_str().unwrap().to_string()
+    }
+
+    async fn update(
+        app: &TestApp,
+        id: &str,
+        if_match: Option<&str>,
+        name: &str,
+    ) -> (HeaderMap, Value) {
+        let mut headers = vec![ACTOR];
+        headers.extend(if_match.map(|v| ("if-match", v)));
+
+        app.send(
+            "POST",
+            &format!("/employee/update/{}", id),
+            &headers,
+            Some(json!({ "name": name })),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn matching_if_match_updates_and_returns_new_etag() {
+        let app = TestApp::new().await;
+        let id = create(&app).await;
+
+        let (headers, _) = app
+            .send("GET", &format!("/employee/get/{}", id), &[], None)
+            .await;
+        assert_eq!(etag(&headers), Some("\"1\""));
+
+        let (headers, res) = update(&app, &id, Some("\"1\""), "a").await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+        assert_eq!(etag(&headers), Some("\"2\""));
+
+        // 弱校验和不带引号的写法同样接受
+        let (_, res) = update(&app, &id, Some("W/\"2\""), "b").await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+        let (_, res) = update(&app, &id, Some("3"), "c").await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+    }
+
+    #[tokio::test]
+    async fn stale_if_match_is_a_conflict() {
+        let app = TestApp::new().await;
+        let id = create(&app).await;
+
+        update(&app, &id, None, "a").await;
+
+        let (headers, res) = update(&app, &id, Some("\"1\""), "b").await;
+        assert_eq!(res["code"], "Conflict", "{}", res);
+        assert_eq!(etag(&headers), None);
+
+        let (_, res) = app
+            .send(
+                "POST",
+                &format!("/employee/delete/{}", id),
+                &[ACTOR, ("if-match", "\"1\"")],
+                None,
+            )
+            .await;
+        assert_eq!(res["code"], "Conflict", "{}", res);
+
+        let cur = app.store.employee.read().await.get(&id).cloned().unwrap();
+        assert_eq!(cur.name, "a");
+
+        let (_, res) = update(&app, &id, Some("abc"), "b").await;
+        assert_eq!(res["code"], "Err");
+    }
+
+    #[tokio::test]
+    async fn missing_if_match_is_not_checked() {
+        let app = TestApp::new().await;
+        let id = create(&app).await;
+
+        update(&app, &id, None, "a").await;
+
+        let (headers, res) = update(&app, &id, None, "b").await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+        assert_eq!(etag(&headers), Some("\"3\""));
+
+        let (_, res) = update(&app, &id, Some("*"), "c").await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+    }
+
+    #[tokio::test]
+    async fn etag_changes_after_undo() {
+        let app = TestApp::new().await;
+        let id = create(&app).await;
+
+        let (headers, _) = update(&app, &id, None, "a").await;
+        assert_eq!(etag(&headers), Some("\"2\""));
+
+        let (_, res) = app.send("POST", "/undo", &[ACTOR], None).await;
+        assert_eq!(res["code"], "Ok", "{}", res);
+
+        // 撤销同样是一次写入, 之前取得的版本号失效
+        let (headers, res) = app
+            .send("GET", &format!("/employee/get/{}", id), &[], None)
+            .await;
+        assert_eq!(res["data"]["name"], "人员");
+        assert_eq!(etag(&headers), Some("\"3\""));
+
+        let (_, res) = update(&app, &id, Some("\"2\""), "b").await;
+        assert_eq!(res["code"], "Conflict", "{}", res);
+    }
+}
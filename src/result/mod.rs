@@ -1,3 +1,5 @@
 pub mod error;
+pub mod etag;
+pub mod page;
 pub mod response;
 pub mod response_code;
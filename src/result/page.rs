@@ -0,0 +1,402 @@
+use std::cmp::Ordering;
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 未指定 page_size 时每页的记录数
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// page_size 的上限
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// 列表接口通用的分页及排序参数, 与各接口自己的查询参数一起从查询字符串读取
+///
+/// - `sort`: 排序字段, 格式为 `字段[:asc|desc]`, 多个字段以逗号分隔, 未指定时保持默认顺序 (新记录在前)
+/// - `page` / `page_size`: 页码从 1 开始
+/// - `cursor`: 上一页返回的 `next_cursor`, 从该记录之后继续, 不能与 page 同时使用
+#[derive(Deserialize, Debug, Default)]
+pub struct PageParam {
+    #[serde(default)]
+    pub page: Option<usize>,
+    #[serde(default)]
+    pub page_size: Option<usize>,
+    #[serde(default)]
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub sort: Option<String>,
+}
+
+/// 分页结果
+#[derive(Serialize, Debug)]
+pub struct Page {
+    /// 当前页的记录
+    pub items: Vec<Value>,
+    /// 满足条件的记录总数
+    pub total: usize,
+    /// 页码, 使用游标时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+    pub page_size: usize,
+    /// 下一页的游标, 没有下一页时为空
+    pub next_cursor: Option<String>,
+}
+
+/// 排序字段
+struct SortKey {
+    field: String,
+    desc: bool,
+}
+
+impl PageParam {
+    /// 不排序且不使用游标时, 取出当前页只需要默认顺序中的前若干条记录, 返回其条数, 否则返回 None
+    ///
+    /// 用于记录较多且需要从文件读取的列表, 配合 [PageParam::paginate_prefix] 使用
+    pub fn prefix_len(&self) -> Option<usize> {
+        if self.sort.is_some() || self.cursor.is_some() {
+            return None;
+        }
+
+        let page_size = self
+            .page_size
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .min(MAX_PAGE_SIZE);
+
+        Some(self.page.unwrap_or(1).saturating_mul(page_size))
+    }
+
+    /// 与 [PageParam::paginate] 相同, rows 只包含默认顺序中的前 [PageParam::prefix_len] 条记录, total 为记录总数
+    pub fn paginate_prefix<T: Serialize>(
+        &self,
+        rows: Vec<T>,
+        total: usize,
+        key: &str,
+    ) -> Result<Page> {
+        let page_size = self.page_size()?;
+        let page = self.page.unwrap_or(1);
+        let start = self.offset(page_size);
+
+        let items = rows
+            .into_iter()
+            .skip(start)
+            .take(page_size)
+            .map(serde_json::to_value)
+            .collect::<serde_json::Result<Vec<Value>>>()?;
+
+        let next_cursor = if start.saturating_add(items.len()) < total {
+            items.last().map(|p| cursor_of(p, key))
+        } else {
+            None
+        };
+
+        Ok(Page {
+            items,
+            total,
+            page: Some(page),
+            page_size,
+            next_cursor,
+        })
+    }
+
+    /// 对全部记录排序后取出当前页, key 为记录中唯一标识记录的字段, 用作游标
+    pub fn paginate<T: Serialize>(&self, rows: Vec<T>, key: &str) -> Result<Page> {
+        let page_size = self.page_size()?;
+
+        let sort = self.sort_keys()?;
+
+        let mut items = rows
+            .into_iter()
+            .map(serde_json::to_value)
+            .collect::<serde_json::Result<Vec<Value>>>()?;
+
+        // 稳定排序, 排序字段相同的记录保持默认顺序
+        if !sort.is_empty() {
+            items.sort_by(|a, b| {
+                sort.iter()
+                    .map(|k| {
+                        let ord = compare(&a[&k.field], &b[&k.field]);
+                        if k.desc { ord.reverse() } else { ord }
+                    })
+                    .find(|ord| ord.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            });
+        }
+
+        let total = items.len();
+
+        let start = match &self.cursor {
+            Some(cursor) => match items.iter().position(|p| cursor_of(p, key) == *cursor) {
+                Some(ind) => ind + 1,
+                None => bail!("游标已失效, 请从第一页重新查询"),
+            },
+            None => self.offset(page_size),
+        };
+
+        let items: Vec<Value> = items.into_iter().skip(start).take(page_size).collect();
+
+        let next_cursor = if start.saturating_add(items.len()) < total {
+            items.last().map(|p| cursor_of(p, key))
+        } else {
+            None
+        };
+
+        Ok(Page {
+            items,
+            total,
+            page: match self.cursor {
+                Some(_) => None,
+                None => Some(self.page.unwrap_or(1)),
+            },
+            page_size,
+            next_cursor,
+        })
+    }
+
+    /// 校验分页参数, 返回每页的记录数
+    fn page_size(&self) -> Result<usize> {
+        let page_size = self.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+
+        if page_size == 0 || page_size > MAX_PAGE_SIZE {
+            bail!("page_size 应在 1 到 {} 之间", MAX_PAGE_SIZE);
+        }
+
+        if self.page == Some(0) {
+            bail!("page 从 1 开始");
+        }
+
+        if self.page.is_some() && self.cursor.is_some() {
+            bail!("page 和 cursor 不能同时使用");
+        }
+
+        Ok(page_size)
+    }
+
+    /// 当前页第一条记录的位置, 页码过大时为 usize::MAX, 即返回空页
+    fn offset(&self, page_size: usize) -> usize {
+        (self.page.unwrap_or(1) - 1).saturating_mul(page_size)
+    }
+
+    fn sort_keys(&self) -> Result<Vec<SortKey>> {
+        let Some(sort) = &self.sort else {
+            return Ok(Vec::new());
+        };
+
+        sort.split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                let (field, dir) = p.split_once(':').unwrap_or((p, "asc"));
+
+                let desc = match dir {
+                    "asc" => false,
+                    "desc" => true,
+                    _ => bail!("排序方向应为 asc 或 desc: {}", p),
+                };
+
+                Ok(SortKey {
+                    field: field.to_string(),
+                    desc,
+                })
+            })
+            .collect()
+    }
+}
+
+/// 记录的游标, 即 key 字段的值
+fn cursor_of(item: &Value, key: &str) -> String {
+    match &item[key] {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// 比较两个字段值, 不存在的字段视为 null, 排在最前面; 不同类型按 null < bool < 数字 < 字符串 < 其他 排列
+fn compare(a: &Value, b: &Value) -> Ordering {
+    fn rank(v: &Value) -> u8 {
+        match v {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            _ => 4,
+        }
+    }
+
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .partial_cmp(&b.as_f64())
+            .unwrap_or(Ordering::Equal),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        _ => rank(a)
+            .cmp(&rank(b))
+            .then_with(|| a.to_string().cmp(&b.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// 默认顺序为 id 从 1 到 n
+    fn rows(n: usize) -> Vec<Value> {
+        (1..=n)
+            .map(|i| json!({ "id": i.to_string(), "rank": (i % 3) as u64 }))
+            .collect()
+    }
+
+    fn param(page: Option<usize>, page_size: Option<usize>) -> PageParam {
+        PageParam {
+            page,
+            page_size,
+            ..PageParam::default()
+        }
+    }
+
+    fn ids(page: &Page) -> Vec<&str> {
+        page.items
+            .iter()
+            .map(|p| p["id"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn sorts_by_multiple_keys_stably() {
+        let page = PageParam {
+            sort: Some("rank:desc, id".into()),
+            ..PageParam::default()
+        }
+        .paginate(rows(6), "id")
+        .unwrap();
+
+        assert_eq!(ids(&page), ["2", "5", "1", "4", "3", "6"]);
+
+        // 只按 rank 排序时, rank 相同的记录保持默认顺序
+        let page = PageParam {
+            sort: Some("rank".into()),
+            ..PageParam::default()
+        }
+        .paginate(rows(6), "id")
+        .unwrap();
+
+        assert_eq!(ids(&page), ["3", "6", "1", "4", "2", "5"]);
+
+        let res = PageParam {
+            sort: Some("rank:up".into()),
+            ..PageParam::default()
+        }
+        .paginate(rows(6), "id");
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn compares_mixed_types_in_rank_order() {
+        let mut values = vec![json!("a"), json!(2), json!(null), json!(true), json!(1.5)];
+        values.sort_by(compare);
+
+        assert_eq!(
+            values,
+            [json!(null), json!(true), json!(1.5), json!(2), json!("a")]
+        );
+    }
+
+    #[test]
+    fn cursor_continues_after_the_last_item() {
+        let first = param(None, Some(2)).paginate(rows(5), "id").unwrap();
+        assert_eq!(ids(&first), ["1", "2"]);
+        assert_eq!(first.next_cursor.as_deref(), Some("2"));
+
+        let next = PageParam {
+            page_size: Some(2),
+            cursor: first.next_cursor,
+            ..PageParam::default()
+        }
+        .paginate(rows(5), "id")
+        .unwrap();
+        assert_eq!(ids(&next), ["3", "4"]);
+        assert_eq!(next.page, None);
+
+        // 游标对应的记录已不存在
+        let res = PageParam {
+            cursor: Some("missing".into()),
+            ..PageParam::default()
+        }
+        .paginate(rows(5), "id");
+        assert!(res.unwrap_err().to_string().contains("游标已失效"));
+
+        let res = PageParam {
+            page: Some(1),
+            cursor: Some("2".into()),
+            ..PageParam::default()
+        }
+        .paginate(rows(5), "id");
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn page_size_is_capped() {
+        assert!(param(None, Some(0)).paginate(rows(1), "id").is_err());
+        assert!(
+            param(None, Some(MAX_PAGE_SIZE + 1))
+                .paginate(rows(1), "id")
+                .is_err()
+        );
+        assert!(param(Some(0), None).paginate(rows(1), "id").is_err());
+
+        let page = param(None, None).paginate(rows(60), "id").unwrap();
+        assert_eq!(page.items.len(), DEFAULT_PAGE_SIZE);
+
+        assert_eq!(
+            param(Some(2), Some(MAX_PAGE_SIZE + 1)).prefix_len(),
+            Some(2 * MAX_PAGE_SIZE)
+        );
+    }
+
+    #[test]
+    fn last_page_has_no_next_cursor() {
+        let page = param(Some(3), Some(2)).paginate(rows(5), "id").unwrap();
+        assert_eq!(ids(&page), ["5"]);
+        assert_eq!(page.total, 5);
+        assert_eq!(page.next_cursor, None);
+
+        // 刚好填满的最后一页
+        let page = param(Some(2), Some(2)).paginate(rows(4), "id").unwrap();
+        assert_eq!(ids(&page), ["3", "4"]);
+        assert_eq!(page.next_cursor, None);
+
+        let page = param(Some(4), Some(2)).paginate(rows(5), "id").unwrap();
+        assert!(page.items.is_empty());
+    }
+
+    #[test]
+    fn huge_page_returns_an_empty_page() {
+        let page = param(Some(usize::MAX), Some(MAX_PAGE_SIZE))
+            .paginate(rows(5), "id")
+            .unwrap();
+        assert!(page.items.is_empty());
+        assert_eq!(page.next_cursor, None);
+
+        let page = param(Some(usize::MAX), Some(MAX_PAGE_SIZE))
+            .paginate_prefix(rows(5), 10, "id")
+            .unwrap();
+        assert!(page.items.is_empty());
+        assert_eq!(page.total, 10);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn prefix_matches_full_pagination() {
+        let full = param(Some(2), Some(2)).paginate(rows(5), "id").unwrap();
+
+        let len = param(Some(2), Some(2)).prefix_len().unwrap();
+        let prefix = param(Some(2), Some(2))
+            .paginate_prefix(rows(5).into_iter().take(len).collect(), 5, "id")
+            .unwrap();
+
+        assert_eq!(ids(&prefix), ids(&full));
+        assert_eq!(prefix.next_cursor, full.next_cursor);
+    }
+}
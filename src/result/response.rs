@@ -72,13 +72,11 @@ impl<T: Serialize> AppResponse<T> {
         self
     }
 
-    #[allow(dead_code)]
     pub fn data(mut self, data: T) -> Self {
         self.data = Some(data);
         self
     }
 
-    #[allow(dead_code)]
     pub fn build(self) -> AppResult {
         Ok(self.into_response())
     }
@@ -123,7 +121,7 @@ pub async fn text_response_process(request: Request, next: Next) -> Response {
             parts,
             AppResponse::<()>::new()
                 .code(code)
-                .msg(String::from_utf8_lossy(&bytes.to_vec()))
+                .msg(String::from_utf8_lossy(&bytes))
                 .into_response()
                 .into_body(),
         )
@@ -6,4 +6,8 @@ pub enum AppResponseCode {
     Ok,
     Err,
     Unauthorized,
-}
\ No newline at end of file
+    /// 记录已被其他请求修改, 版本号不一致
+    Conflict,
+    /// 变更已生效但尚未写入磁盘, 后台会继续重试写入, 不应重复提交
+    NotDurable,
+}